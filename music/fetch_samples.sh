@@ -0,0 +1,19 @@
+#!/bin/sh
+# Copies Scratch's instrument and drum recordings, along with the license they
+# are under, from scratch-vm into this directory. Rash plays them from here when
+# it is run from the repository, see the README.
+set -e
+cd "$(dirname "$0")"
+checkout=$(mktemp -d)
+trap 'rm -rf "$checkout"' EXIT
+git clone --quiet --depth 1 --filter=blob:none --sparse https://github.com/scratchfoundation/scratch-vm "$checkout"
+git -C "$checkout" sparse-checkout set src/extensions/scratch3_music/assets
+assets="$checkout/src/extensions/scratch3_music/assets"
+if [ ! -d "$assets/instruments" ] || [ ! -d "$assets/drums" ]; then
+    echo "scratch-vm no longer has the music samples in src/extensions/scratch3_music/assets" >&2
+    exit 1
+fi
+rm -rf instruments drums
+cp -r "$assets/instruments" "$assets/drums" .
+cp "$checkout/LICENSE" LICENSE
+echo "Copied the music samples, commit them along with LICENSE"
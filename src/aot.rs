@@ -0,0 +1,23 @@
+// What games made by `rash compile` are built from. The code that makes them
+// is in aot_compiler, which only comes with the tools feature.
+
+use crate::interpreter::{Instruction, Memory};
+
+// Runs one instruction the compiled code can't, like anything that touches a
+// sprite, and gives where to carry on from. None once the thread yields.
+pub type Interpret<'a> = dyn FnMut(&mut Memory, usize) -> Option<usize> + 'a;
+
+// Runs a script from the counter until it yields, handing instructions it
+// wasn't compiled for to the interpreter without leaving the function.
+pub type CompiledFunction = fn(&mut Memory, &[Instruction], usize, &mut Interpret);
+
+// Everything a compiled game needs. The project is still there for its sounds
+// and monitors, but its scripts and costumes come from the cache, which was
+// made along with the functions so they can't get out of step.
+pub struct CompiledProject {
+    pub archive: &'static [u8],
+    // A ProjectCache, the same as the ones saved when running a project.
+    pub cache: &'static [u8],
+    // One function per script, in the same order as the sprites and their scripts.
+    pub sprites: &'static [&'static [CompiledFunction]],
+}
@@ -0,0 +1,408 @@
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    ansi_codes,
+    bc_compiler::bc_comp_diagnostics::ReportOptions,
+    interpreter::{Instruction, Value},
+    project::{project_cache::ProjectCache, project_main::Project},
+    thread::ScriptBytecode,
+};
+
+// Writes a crate to the output directory that builds the project into a
+// standalone game, with every script turned into a Rust function. A copy of
+// Rash's source from the runtime directory goes along with it, so the crate
+// builds anywhere without needing this checkout. Without a runtime directory,
+// it looks for the checkout this executable was built in.
+pub fn compile_project(
+    project_path: &str,
+    output: &str,
+    runtime: Option<&str>,
+    report_options: &ReportOptions,
+) -> Result<(), String> {
+    let runtime = match runtime {
+        Some(runtime) => PathBuf::from(runtime),
+        None => find_runtime().ok_or(
+            "Could not find the Rash source next to this executable, pass its location with --runtime",
+        )?,
+    };
+    check_output(&runtime, Path::new(output))?;
+    let archive = std::fs::read(project_path)
+        .map_err(|err| format!("Could not read {project_path}: {err}"))?;
+    let (_project_directory, extracted_path) = Project::extract_zip_file(archive.clone())?;
+    let json = Project::load_json(&extracted_path);
+    let mut font_database = usvg_text_layout::fontdb::Database::new();
+    font_database.load_system_fonts();
+    let cache = ProjectCache::build(&json, &extracted_path, &font_database)?;
+    cache.report.show(report_options)?;
+    let scripts: Vec<&[ScriptBytecode]> = cache
+        .sprites
+        .iter()
+        .map(|sprite| &sprite.scripts[..])
+        .collect();
+
+    let output = Path::new(output);
+    let write = |path: &str, contents: &[u8]| {
+        let path = output.join(path);
+        std::fs::write(&path, contents)
+            .map_err(|err| format!("Could not write {}: {err}", path.display()))
+    };
+    std::fs::create_dir_all(output.join("src"))
+        .map_err(|err| format!("Could not create {}: {err}", output.display()))?;
+
+    vendor_runtime(&runtime, &output.join("rash"))?;
+    // Builds with the same dependency versions as the runtime was tested with.
+    if let Ok(lock) = std::fs::read(runtime.join("Cargo.lock")) {
+        write("Cargo.lock", &lock)?;
+    }
+    let name = crate_name(project_path);
+    write("Cargo.toml", cargo_toml(&name).as_bytes())?;
+    write("project.sb3", &archive)?;
+    write("project.rashc", &cache.encode()?)?;
+    write("src/main.rs", main_rs(project_path, &scripts).as_bytes())?;
+
+    println!(
+        "{}[info]{} Wrote {} to {}, build it with cargo build --release",
+        ansi_codes::GREEN,
+        ansi_codes::RESET,
+        name,
+        output.display()
+    );
+    Ok(())
+}
+
+// The checkout a cargo built executable is in, found by going up from
+// target/<profile>/ until there is a Cargo.toml for Rash.
+fn find_runtime() -> Option<PathBuf> {
+    let executable = std::env::current_exe().ok()?.canonicalize().ok()?;
+    executable
+        .ancestors()
+        .skip(1)
+        .find(|directory| is_runtime(directory))
+        .map(Path::to_path_buf)
+}
+
+fn is_runtime(directory: &Path) -> bool {
+    std::fs::read_to_string(directory.join("Cargo.toml"))
+        .is_ok_and(|manifest| manifest.contains("name = \"rash\""))
+}
+
+// The game's files and the old copy of the runtime get overwritten,
+// which mustn't take the runtime itself with them.
+fn check_output(runtime: &Path, output: &Path) -> Result<(), String> {
+    let (runtime_path, output_path) = (real_path(runtime)?, real_path(output)?);
+    if runtime_path.starts_with(&output_path) || output_path.starts_with(runtime_path.join("src")) {
+        return Err(format!(
+            "{} would overwrite the Rash source in {}, pick another output directory",
+            output.display(),
+            runtime.display()
+        ));
+    }
+    Ok(())
+}
+
+// Like canonicalize, but for paths that don't exist yet.
+fn real_path(path: &Path) -> Result<PathBuf, String> {
+    let absolute = std::path::absolute(path)
+        .map_err(|err| format!("Could not find {}: {err}", path.display()))?;
+    let existing = absolute
+        .ancestors()
+        .find(|ancestor| ancestor.exists())
+        .unwrap_or(&absolute);
+    let canonical = existing
+        .canonicalize()
+        .map_err(|err| format!("Could not find {}: {err}", existing.display()))?;
+    Ok(match absolute.strip_prefix(existing) {
+        Ok(rest) => canonical.join(rest),
+        Err(_) => canonical,
+    })
+}
+
+fn vendor_runtime(runtime: &Path, output: &Path) -> Result<(), String> {
+    let manifest = std::fs::read_to_string(runtime.join("Cargo.toml")).map_err(|err| {
+        format!(
+            "Could not read the Rash source in {}, pass its location with --runtime: {err}",
+            runtime.display()
+        )
+    })?;
+    if !manifest.contains("name = \"rash\"") {
+        return Err(format!("{} isn't the Rash source", runtime.display()));
+    }
+    // Compiling again would leave files from older versions of the runtime behind.
+    let source = output.join("src");
+    if source.exists() {
+        std::fs::remove_dir_all(&source)
+            .map_err(|err| format!("Could not remove {}: {err}", source.display()))?;
+    }
+    copy_directory(&runtime.join("src"), &output.join("src"))?;
+    std::fs::write(output.join("Cargo.toml"), without_profiles(&manifest))
+        .map_err(|err| format!("Could not write {}: {err}", output.display()))?;
+    if let Ok(license) = std::fs::read(runtime.join("LICENSE")) {
+        std::fs::write(output.join("LICENSE"), license)
+            .map_err(|err| format!("Could not write {}: {err}", output.display()))?;
+    }
+    Ok(())
+}
+
+fn copy_directory(from: &Path, to: &Path) -> Result<(), String> {
+    std::fs::create_dir_all(to)
+        .map_err(|err| format!("Could not create {}: {err}", to.display()))?;
+    let entries = std::fs::read_dir(from)
+        .map_err(|err| format!("Could not read {}: {err}", from.display()))?;
+    for entry in entries {
+        let entry = entry.map_err(|err| format!("Could not read {}: {err}", from.display()))?;
+        let (from, to) = (entry.path(), to.join(entry.file_name()));
+        if from.is_dir() {
+            copy_directory(&from, &to)?;
+        } else {
+            std::fs::copy(&from, &to)
+                .map_err(|err| format!("Could not copy {}: {err}", from.display()))?;
+        }
+    }
+    Ok(())
+}
+
+// Cargo only uses the game's profiles, and warns about any in its dependencies.
+fn without_profiles(manifest: &str) -> String {
+    let mut in_profile = false;
+    let mut out = String::new();
+    for line in manifest.lines() {
+        if line.starts_with('[') {
+            in_profile = line.starts_with("[profile.");
+        }
+        if !in_profile {
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+    out
+}
+
+fn crate_name(project_path: &str) -> String {
+    let stem = Path::new(project_path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let name: String = stem
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    match name.chars().next() {
+        Some(c) if c.is_ascii_alphabetic() => name,
+        _ => format!("game_{name}"),
+    }
+}
+
+fn cargo_toml(name: &str) -> String {
+    format!(
+        r#"[package]
+name = "{name}"
+version = "0.1.0"
+edition = "2021"
+
+# Only the runtime, without the tools for compiling and inspecting projects.
+[dependencies]
+rash = {{ path = "rash", default-features = false }}
+
+[profile.release]
+strip = true
+
+# Keeps the game out of any workspace it was written into.
+[workspace]
+"#
+    )
+}
+
+fn main_rs(project_path: &str, scripts: &[&[ScriptBytecode]]) -> String {
+    let mut out = String::new();
+    writeln!(
+        out,
+        "// Made by `rash compile` from {project_path:?}. Changes will be lost when it is compiled again."
+    )
+    .unwrap();
+    out.push_str(
+        "#![allow(unused)]
+
+use rash::{
+    aot::{CompiledProject, Interpret},
+    interpreter::{Instruction, Memory, NumberOp, Value},
+};
+
+fn main() {
+    rash::run(
+        rash::arguments::Arguments::parse_without_project(),
+        Some(&PROJECT),
+    );
+}
+
+static PROJECT: CompiledProject = CompiledProject {
+    archive: include_bytes!(\"../project.sb3\"),
+    cache: include_bytes!(\"../project.rashc\"),
+    sprites: &[\n",
+    );
+    for (sprite, sprite_scripts) in scripts.iter().enumerate() {
+        out.push_str("        &[");
+        for script in 0..sprite_scripts.len() {
+            write!(out, "sprite{sprite}_script{script}, ").unwrap();
+        }
+        out.push_str("],\n");
+    }
+    out.push_str("    ],\n};\n");
+
+    for (sprite, sprite_scripts) in scripts.iter().enumerate() {
+        for (script, compiled) in sprite_scripts.iter().enumerate() {
+            writeln!(
+                out,
+                "\nfn sprite{sprite}_script{script}(memory: &mut Memory, instructions: &[Instruction], mut counter: usize, interpret: &mut Interpret) {{"
+            )
+            .unwrap();
+            out.push_str("    loop {\n        match counter {\n");
+            for (index, instruction) in compiled.instructions.iter().enumerate() {
+                if let Some(rust) = rust_for(index, instruction) {
+                    writeln!(out, "            {index} => {rust},").unwrap();
+                }
+            }
+            out.push_str(
+                "            _ => match interpret(memory, counter) {
+                Some(next) => {
+                    counter = next;
+                    continue;
+                }
+                None => return,
+            },
+        }
+        counter += 1;
+    }
+}
+",
+            );
+        }
+    }
+    out
+}
+
+// The body of one arm of a script's state machine. None for instructions left
+// to the interpreter, which the last arm hands over.
+fn rust_for(index: usize, instruction: &Instruction) -> Option<String> {
+    Some(match instruction {
+        Instruction::MemoryStore(location, value) => {
+            let location = value_literal(location)?;
+            let literal = value_literal(value)?;
+            let value = match value {
+                Value::Pointer(_)
+                | Value::Register(_)
+                | Value::NumberRegister(_)
+                | Value::BooleanRegister(_) => {
+                    format!("memory.resolve(&{literal}).into_owned()")
+                }
+                _ => literal,
+            };
+            format!("{{ let value = {value}; memory.set(&{location}, value); }}")
+        }
+        Instruction::OperatorNumbers(op, location, a, b) => {
+            let (location, a, b) = (value_literal(location)?, number_for(a)?, number_for(b)?);
+            format!(
+                "{{ let a = {a}; let b = {b}; memory.set(&{location}, NumberOp::{op:?}.apply(a, b)); }}"
+            )
+        }
+        Instruction::FlowIfJump(condition, target) => {
+            format!("if {} {{ counter = {target}; continue; }}", bool_for(condition)?)
+        }
+        Instruction::FlowIfNotJump(condition, target) => {
+            format!("if !{} {{ counter = {target}; continue; }}", bool_for(condition)?)
+        }
+        _ if instruction.is_operator() => format!(
+            "if let Some((location, value)) = instructions[{index}].evaluate_operator(memory) {{ memory.set(location, value); }}"
+        ),
+        _ => return None,
+    })
+}
+
+fn value_literal(value: &Value) -> Option<String> {
+    Some(match value {
+        Value::Number(n) => format!("Value::Number({})", f64_literal(*n)),
+        Value::Boolean(b) => format!("Value::Boolean({b})"),
+        Value::String(s) => format!("Value::String({s:?}.to_owned())"),
+        Value::Pointer(n) => format!("Value::Pointer({n})"),
+        Value::Register(n) => format!("Value::Register({n})"),
+        Value::NumberRegister(n) => format!("Value::NumberRegister({n})"),
+        Value::BooleanRegister(n) => format!("Value::BooleanRegister({n})"),
+        Value::List(_) => return None,
+    })
+}
+
+fn number_for(value: &Value) -> Option<String> {
+    match value {
+        Value::Number(n) => Some(f64_literal(*n)),
+        Value::NumberRegister(n) => Some(format!("memory.numbers[{n}]")),
+        _ => Some(format!("{}.get_number(memory)", value_literal(value)?)),
+    }
+}
+
+fn bool_for(value: &Value) -> Option<String> {
+    match value {
+        Value::Boolean(b) => Some(b.to_string()),
+        Value::BooleanRegister(n) => Some(format!("memory.booleans[{n}]")),
+        _ => Some(format!("{}.get_bool(memory)", value_literal(value)?)),
+    }
+}
+
+fn f64_literal(n: f64) -> String {
+    if n.is_nan() {
+        "f64::NAN".to_owned()
+    } else if n.is_infinite() {
+        if n > 0.0 {
+            "f64::INFINITY"
+        } else {
+            "f64::NEG_INFINITY"
+        }
+        .to_owned()
+    } else {
+        format!("{n:?}_f64")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_output, compile_project};
+    use crate::bc_compiler::bc_comp_diagnostics::ReportOptions;
+
+    #[test]
+    fn output_cant_overwrite_the_runtime() {
+        let directory = tempfile::tempdir().unwrap();
+        let runtime = directory.path().join("rash");
+        std::fs::create_dir_all(runtime.join("src")).unwrap();
+
+        assert!(check_output(&runtime, &runtime).is_err());
+        assert!(check_output(&runtime, directory.path()).is_err());
+        assert!(check_output(&runtime, &runtime.join("src/game")).is_err());
+        assert!(check_output(&runtime, &runtime.join("game")).is_ok());
+        assert!(check_output(&runtime, &directory.path().join("game")).is_ok());
+        // Nothing is made until the checks pass.
+        assert!(!runtime.join("src/game").exists());
+    }
+
+    // Builds the game and every dependency of the runtime, so it takes a few minutes:
+    //     cargo test generated_games_build -- --ignored
+    #[test]
+    #[ignore]
+    fn generated_games_build() {
+        let directory = tempfile::tempdir().unwrap();
+        let output = directory.path().to_str().unwrap();
+        compile_project(
+            "tests/pi calculator.sb3",
+            output,
+            Some(env!("CARGO_MANIFEST_DIR")),
+            &ReportOptions::default(),
+        )
+        .unwrap();
+        let status = std::process::Command::new(env!("CARGO"))
+            .arg("build")
+            .current_dir(output)
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+}
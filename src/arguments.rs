@@ -0,0 +1,347 @@
+use crate::bc_compiler::bc_comp_diagnostics::{ReportOptions, UnsupportedBlocks};
+
+pub struct Arguments {
+    pub project_path: String,
+    // Reported by the "username" sensing block. Empty, like a logged out user, by default.
+    pub username: String,
+    // How many pen layer pixels there are per stage pixel.
+    pub pen_resolution: u32,
+    // Overrides the stage size the project asks for.
+    pub stage_size: Option<(u32, u32)>,
+    // SDL audio driver and output device to use instead of the defaults.
+    pub audio_driver: Option<String>,
+    pub audio_device: Option<String>,
+    // Mix all audio into memory and save it to this WAV file when the project stops.
+    pub capture_audio: Option<String>,
+    // Where the recorded instruments and drums for the music extension are.
+    pub music_samples: Option<String>,
+    // Don't show a window, for running projects in tests.
+    pub headless: bool,
+    // Stop after this many seconds instead of running until the window is closed.
+    pub run_for: Option<f64>,
+    // Overrides the frame rate the project asks for.
+    pub fps: Option<u32>,
+    // Start with turbo mode on, it can still be toggled with F10.
+    pub turbo: bool,
+    // Compile the project again instead of loading it from the cache.
+    pub no_cache: bool,
+    // What to do about problems found while compiling.
+    pub report: ReportOptions,
+}
+
+#[cfg(feature = "tools")]
+pub enum Command {
+    Run(Arguments),
+    // Turns a project into a Rust crate that builds into a standalone game.
+    Compile {
+        project_path: String,
+        output: String,
+        // Rash's source, copied into the crate for it to build against.
+        // Found next to the executable if it isn't given.
+        runtime: Option<String>,
+        report: ReportOptions,
+    },
+    // Prints a cached compiled project, given the project or the cache file.
+    Inspect {
+        path: String,
+    },
+    // Prints the compiled scripts of a project as assembly.
+    Disasm {
+        project_path: String,
+        output: Option<String>,
+        // Also print every script before and after it is optimized.
+        dump: bool,
+        report: ReportOptions,
+    },
+    // Assembles a file and runs it.
+    Asm {
+        path: String,
+    },
+}
+
+#[cfg(feature = "tools")]
+impl Command {
+    pub fn parse() -> Command {
+        let args: Vec<String> = std::env::args().collect();
+        match args.get(1).map(String::as_str) {
+            Some("compile") => parse_compile(&args),
+            Some("inspect") => match args.get(2) {
+                Some(path) if args.len() == 3 => Command::Inspect { path: path.clone() },
+                _ => print_usage_and_exit(&args[0]),
+            },
+            Some("disasm") => {
+                let dump = args.iter().any(|arg| arg == "--dump");
+                let other_args: Vec<String> = args
+                    .iter()
+                    .filter(|arg| *arg != "--dump")
+                    .cloned()
+                    .collect();
+                let (project_path, output, report) = parse_path_and_output(&other_args);
+                Command::Disasm {
+                    project_path,
+                    output,
+                    dump,
+                    report,
+                }
+            }
+            Some("asm") => match args.get(2) {
+                Some(path) if args.len() == 3 => Command::Asm { path: path.clone() },
+                _ => print_usage_and_exit(&args[0]),
+            },
+            _ => Command::Run(Arguments::parse()),
+        }
+    }
+}
+
+#[cfg(feature = "tools")]
+fn parse_compile(args: &[String]) -> Command {
+    // Taken out first, the rest are the same as for disasm.
+    let mut runtime = None;
+    let mut other_args = Vec::with_capacity(args.len());
+    let mut args_iter = args.iter();
+    while let Some(arg) = args_iter.next() {
+        match arg.as_str() {
+            "--runtime" => runtime = Some(get_flag_value(&mut args_iter, arg, &args[0])),
+            _ => other_args.push(arg.clone()),
+        }
+    }
+    match parse_path_and_output(&other_args) {
+        (project_path, Some(output), report) => Command::Compile {
+            project_path,
+            output,
+            runtime,
+            report,
+        },
+        _ => print_usage_and_exit(&args[0]),
+    }
+}
+
+// For subcommands taking a project, an optional -o and the options for compile problems.
+#[cfg(feature = "tools")]
+fn parse_path_and_output(args: &[String]) -> (String, Option<String>, ReportOptions) {
+    let mut project_path = None;
+    let mut output = None;
+    let mut report = ReportOptions::default();
+    let mut args_iter = args.iter().skip(2);
+    while let Some(arg) = args_iter.next() {
+        match arg.as_str() {
+            "-o" | "--output" => output = Some(get_flag_value(&mut args_iter, arg, &args[0])),
+            "--unsupported-blocks" => {
+                report.unsupported_blocks = parse_unsupported_blocks(
+                    &get_flag_value(&mut args_iter, arg, &args[0]),
+                    &args[0],
+                )
+            }
+            "--report" => report.json_path = Some(get_flag_value(&mut args_iter, arg, &args[0])),
+            _ if arg.starts_with('-') => {
+                eprintln!("Unknown option: {arg}");
+                print_usage_and_exit(&args[0]);
+            }
+            _ if project_path.is_none() => project_path = Some(arg.clone()),
+            _ => print_usage_and_exit(&args[0]),
+        }
+    }
+    match project_path {
+        Some(project_path) => (project_path, output, report),
+        None => print_usage_and_exit(&args[0]),
+    }
+}
+
+impl Arguments {
+    pub fn parse() -> Arguments {
+        Arguments::parse_options(true)
+    }
+
+    // For compiled games, which have the project built in.
+    pub fn parse_without_project() -> Arguments {
+        Arguments::parse_options(false)
+    }
+
+    fn parse_options(needs_project: bool) -> Arguments {
+        let args: Vec<String> = std::env::args().collect();
+        let mut project_path: Option<String> = None;
+        let mut username = String::new();
+        let mut pen_resolution = 1;
+        let mut stage_size = None;
+        let mut audio_driver = None;
+        let mut audio_device = None;
+        let mut capture_audio = None;
+        let mut music_samples = None;
+        let mut headless = false;
+        let mut run_for = None;
+        let mut fps = None;
+        let mut turbo = false;
+        let mut no_cache = false;
+        let mut report = ReportOptions::default();
+
+        let mut args_iter = args.iter().skip(1);
+        while let Some(arg) = args_iter.next() {
+            match arg.as_str() {
+                "--username" => username = get_flag_value(&mut args_iter, arg, &args[0]),
+                "--pen-resolution" => {
+                    let value = get_flag_value(&mut args_iter, arg, &args[0]);
+                    pen_resolution = match value.as_str() {
+                        "1" | "1x" => 1,
+                        "2" | "2x" => 2,
+                        "4" | "4x" => 4,
+                        _ => {
+                            eprintln!("Pen resolution must be 1, 2 or 4, not {value}");
+                            print_usage_and_exit(&args[0]);
+                        }
+                    };
+                }
+                "--stage-size" => {
+                    let value = get_flag_value(&mut args_iter, arg, &args[0]);
+                    match parse_stage_size(&value) {
+                        Some(size) => stage_size = Some(size),
+                        None => {
+                            eprintln!("Stage size must look like 640x360, not {value}");
+                            print_usage_and_exit(&args[0]);
+                        }
+                    }
+                }
+                "--audio-driver" => {
+                    audio_driver = Some(get_flag_value(&mut args_iter, arg, &args[0]))
+                }
+                "--audio-device" => {
+                    audio_device = Some(get_flag_value(&mut args_iter, arg, &args[0]))
+                }
+                "--capture-audio" => {
+                    capture_audio = Some(get_flag_value(&mut args_iter, arg, &args[0]))
+                }
+                "--music-samples" => {
+                    music_samples = Some(get_flag_value(&mut args_iter, arg, &args[0]))
+                }
+                "--headless" => headless = true,
+                "--run-for" => {
+                    let value = get_flag_value(&mut args_iter, arg, &args[0]);
+                    match value.parse::<f64>() {
+                        Ok(seconds) if seconds >= 0.0 => run_for = Some(seconds),
+                        _ => {
+                            eprintln!("Run time must be a number of seconds, not {value}");
+                            print_usage_and_exit(&args[0]);
+                        }
+                    }
+                }
+                "--fps" => {
+                    let value = get_flag_value(&mut args_iter, arg, &args[0]);
+                    match value.parse::<u32>() {
+                        Ok(rate) if (1..=240).contains(&rate) => fps = Some(rate),
+                        _ => {
+                            eprintln!("Frame rate must be between 1 and 240, not {value}");
+                            print_usage_and_exit(&args[0]);
+                        }
+                    }
+                }
+                "--turbo" => turbo = true,
+                "--no-cache" => no_cache = true,
+                "--unsupported-blocks" => {
+                    report.unsupported_blocks = parse_unsupported_blocks(
+                        &get_flag_value(&mut args_iter, arg, &args[0]),
+                        &args[0],
+                    )
+                }
+                "--report" => {
+                    report.json_path = Some(get_flag_value(&mut args_iter, arg, &args[0]))
+                }
+                _ if arg.starts_with("--") => {
+                    eprintln!("Unknown option: {arg}");
+                    print_usage_and_exit(&args[0]);
+                }
+                _ if needs_project && project_path.is_none() => project_path = Some(arg.clone()),
+                _ => print_usage_and_exit(&args[0]),
+            }
+        }
+
+        let project_path = match project_path {
+            Some(project_path) => project_path,
+            None if !needs_project => String::new(),
+            None => print_usage_and_exit(&args[0]),
+        };
+
+        Arguments {
+            project_path,
+            username,
+            pen_resolution,
+            stage_size,
+            audio_driver,
+            audio_device,
+            capture_audio,
+            music_samples,
+            headless,
+            run_for,
+            fps,
+            turbo,
+            no_cache,
+            report,
+        }
+    }
+}
+
+fn get_flag_value<'a>(
+    args_iter: &mut impl Iterator<Item = &'a String>,
+    flag: &str,
+    program_name: &str,
+) -> String {
+    match args_iter.next() {
+        Some(value) => value.clone(),
+        None => {
+            eprintln!("Missing value for {flag}");
+            print_usage_and_exit(program_name);
+        }
+    }
+}
+
+fn parse_unsupported_blocks(value: &str, program_name: &str) -> UnsupportedBlocks {
+    match UnsupportedBlocks::parse(value) {
+        Some(policy) => policy,
+        None => {
+            eprintln!("Unsupported blocks must be fail, warn or no-op, not {value}");
+            print_usage_and_exit(program_name);
+        }
+    }
+}
+
+fn parse_stage_size(value: &str) -> Option<(u32, u32)> {
+    let (width, height) = value.split_once('x')?;
+    let (width, height) = (width.parse().ok()?, height.parse().ok()?);
+    if width == 0 || height == 0 || width > 4096 || height > 4096 {
+        return None;
+    }
+    Some((width, height))
+}
+
+fn print_usage_and_exit(program_name: &str) -> ! {
+    eprintln!("Usage: {program_name} [options] <file_path>");
+    eprintln!("       {program_name} compile <file_path> -o <directory> [--runtime <rash source>]");
+    eprintln!("       {program_name} inspect <file_path or cache file>");
+    eprintln!("       {program_name} disasm <file_path> [-o <file.rasm>] [--dump]");
+    eprintln!("       {program_name} asm <file.rasm>");
+    eprintln!();
+    eprintln!("Options:");
+    eprintln!("  --username <name>              Name reported by the username block");
+    eprintln!("  --pen-resolution <1|2|4>       Pen layer pixels per stage pixel");
+    eprintln!("  --stage-size <width>x<height>  Override the project's stage size");
+    eprintln!("  --audio-driver <name>          SDL audio driver, like dummy or disk");
+    eprintln!("  --audio-device <name>          Audio output device");
+    eprintln!("  --capture-audio <file.wav>     Record the audio to a file instead of playing it");
+    eprintln!("  --music-samples <directory>    Recorded instruments for the music extension");
+    eprintln!("  --headless                     Run without showing a window");
+    eprintln!("  --run-for <seconds>            Quit after running for this long");
+    eprintln!("  --fps <rate>                   Frames per second, usually 30 or 60");
+    eprintln!("  --turbo                        Start in turbo mode");
+    eprintln!(
+        "  --no-cache                     Compile the project again instead of using the cache"
+    );
+    eprintln!(
+        "  --unsupported-blocks <policy>  fail, warn (the default) or no-op for blocks Rash can't run"
+    );
+    eprintln!("  --report <file.json>           Save problems found while compiling as JSON");
+    eprintln!();
+    eprintln!("Keys:");
+    eprintln!("  F5   Green flag");
+    eprintln!("  F6   Stop");
+    eprintln!("  F10  Toggle turbo mode");
+    eprintln!("  F11  Toggle fullscreen");
+    std::process::exit(1);
+}
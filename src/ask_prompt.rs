@@ -0,0 +1,227 @@
+use std::collections::VecDeque;
+
+use sdl2::{event::Event, keyboard::Keycode};
+
+use crate::{
+    ansi_codes,
+    text_renderer::{escape_xml, estimate_text_width, render_svg, wrap_text},
+    viewport::Viewport,
+};
+
+type SDLTextureCreator = sdl2::render::TextureCreator<sdl2::video::WindowContext>;
+
+const FONT: &str = "Helvetica, Arial, sans-serif";
+const MARGIN: f64 = 8.0;
+const INPUT_HEIGHT: f64 = 32.0;
+const QUESTION_HEIGHT: f64 = 20.0;
+const BUBBLE_MAX_WIDTH: f64 = 170.0;
+const BUBBLE_LINE_HEIGHT: f64 = 16.0;
+
+struct AskRequest {
+    ticket: usize,
+    question: String,
+    // Visible sprites say the question in a speech bubble instead
+    // of showing it above the input box.
+    shows_bubble: bool,
+}
+
+pub struct AskPrompt<'a> {
+    // Only the front of the queue is shown, the rest wait their turn.
+    queue: VecDeque<AskRequest>,
+    next_ticket: usize,
+    pub answer: String,
+    input: String,
+    texture: Option<sdl2::render::Texture<'a>>,
+    // The contents and scale the texture was last rendered with.
+    rendered: Option<(String, f32)>,
+}
+
+impl<'a> AskPrompt<'a> {
+    pub fn new() -> AskPrompt<'a> {
+        AskPrompt {
+            queue: VecDeque::new(),
+            next_ticket: 0,
+            answer: String::new(),
+            input: String::new(),
+            texture: None,
+            rendered: None,
+        }
+    }
+
+    pub fn ask(&mut self, question: String, shows_bubble: bool) -> usize {
+        let ticket = self.next_ticket;
+        self.next_ticket += 1;
+        self.queue.push_back(AskRequest {
+            ticket,
+            question,
+            shows_bubble,
+        });
+        ticket
+    }
+
+    // Drops every question, used when the project is stopped.
+    pub fn clear(&mut self) {
+        self.queue.clear();
+        self.input.clear();
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.queue.is_empty()
+    }
+
+    pub fn is_waiting(&self, ticket: usize) -> bool {
+        self.queue.iter().any(|request| request.ticket == ticket)
+    }
+
+    // The speech bubble the asking sprite should show right now, if any.
+    pub fn get_bubble(&self, ticket: usize) -> Option<String> {
+        match self.queue.front() {
+            Some(request) if request.ticket == ticket && request.shows_bubble => {
+                Some(request.question.clone())
+            }
+            _ => None,
+        }
+    }
+
+    pub fn handle_event(&mut self, event: &Event) {
+        if !self.is_active() {
+            return;
+        }
+        match event {
+            Event::TextInput { text, .. } => self.input.push_str(text),
+            Event::KeyDown {
+                keycode: Some(Keycode::Backspace),
+                ..
+            } => {
+                self.input.pop();
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::Return) | Some(Keycode::KpEnter),
+                ..
+            } => {
+                self.answer = std::mem::take(&mut self.input);
+                self.queue.pop_front();
+            }
+            _ => {}
+        }
+    }
+
+    pub fn draw(
+        &mut self,
+        canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
+        viewport: &Viewport,
+        font_database: &usvg_text_layout::fontdb::Database,
+        texture_creator: &'a SDLTextureCreator,
+    ) {
+        let Some(request) = self.queue.front() else {
+            return;
+        };
+        let scale = viewport.scale as f32;
+
+        let question = if request.shows_bubble {
+            None
+        } else {
+            Some(request.question.as_str())
+        };
+        let key = format!("{question:?}\n{}", self.input);
+        let is_outdated = match &self.rendered {
+            Some((rendered_key, rendered_scale)) => {
+                *rendered_key != key || *rendered_scale != scale
+            }
+            None => true,
+        };
+        if is_outdated {
+            let svg = get_prompt_svg(viewport.stage_width, question, &self.input);
+            match render_svg(&svg, scale, font_database, texture_creator) {
+                Ok(texture) => self.texture = Some(texture),
+                Err(err) => eprintln!("{}[ask]{} {err}", ansi_codes::RED, ansi_codes::RESET),
+            }
+            self.rendered = Some((key, scale));
+        }
+
+        if let Some(texture) = &self.texture {
+            let query = texture.query();
+            let height = query.height as f64 / viewport.scale;
+            let (x, y) =
+                viewport.stage_pixel_to_window((MARGIN, viewport.stage_height - MARGIN - height));
+            canvas
+                .copy(
+                    texture,
+                    None,
+                    sdl2::rect::Rect::new(x as i32, y as i32, query.width, query.height),
+                )
+                .unwrap();
+        }
+    }
+}
+
+fn get_prompt_svg(stage_width: f64, question: Option<&str>, input: &str) -> String {
+    let width = stage_width - 2.0 * MARGIN;
+    let question_height = if question.is_some() {
+        QUESTION_HEIGHT
+    } else {
+        0.0
+    };
+    let height = question_height + INPUT_HEIGHT + 8.0;
+    let question_text = match question {
+        Some(question) => format!(
+            r##"<text x="10" y="18" font-family="{FONT}" font-size="12" font-weight="bold" fill="#575e75">{}</text>"##,
+            escape_xml(question)
+        ),
+        None => String::new(),
+    };
+
+    format!(
+        r##"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}">
+<rect x="1" y="1" width="{bw}" height="{bh}" rx="8" fill="white" stroke="#c3ccd9" stroke-width="2"/>
+{question_text}
+<rect x="8" y="{iy}" width="{iw}" height="{ih}" rx="12" fill="white" stroke="#4c97ff"/>
+<text x="18" y="{ty}" font-family="{FONT}" font-size="12" fill="#575e75">{input}|</text>
+<circle cx="{cx}" cy="{cy}" r="11" fill="#4c97ff"/>
+<path d="M {c1x} {cy} l 3.5 3.5 l 6 -6.5" fill="none" stroke="white" stroke-width="2" stroke-linecap="round"/>
+</svg>"##,
+        bw = width - 2.0,
+        bh = height - 2.0,
+        iy = question_height + 4.0,
+        iw = width - 16.0,
+        ih = INPUT_HEIGHT - 4.0,
+        ty = question_height + 4.0 + (INPUT_HEIGHT - 4.0) / 2.0 + 4.0,
+        input = escape_xml(input),
+        cx = width - 8.0 - 14.0,
+        cy = question_height + 4.0 + (INPUT_HEIGHT - 4.0) / 2.0,
+        c1x = width - 8.0 - 14.0 - 5.0,
+    )
+}
+
+pub fn get_bubble_svg(text: &str) -> String {
+    let lines = wrap_text(text, BUBBLE_MAX_WIDTH - 20.0, 12.0);
+    let text_width = lines
+        .iter()
+        .map(|line| estimate_text_width(line, 12.0))
+        .fold(0.0, f64::max);
+    let width = (text_width + 20.0).max(50.0);
+    let body_height = lines.len() as f64 * BUBBLE_LINE_HEIGHT + 12.0;
+    let height = body_height + 12.0;
+
+    let mut text_lines = String::new();
+    for (index, line) in lines.iter().enumerate() {
+        text_lines.push_str(&format!(
+            r##"<text x="10" y="{y}" font-family="{FONT}" font-size="12" fill="#575e75">{line}</text>
+"##,
+            y = 6.0 + BUBBLE_LINE_HEIGHT * (index as f64 + 1.0) - 3.0,
+            line = escape_xml(line),
+        ));
+    }
+
+    format!(
+        r##"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}">
+<rect x="1" y="1" width="{bw}" height="{bh}" rx="14" fill="white" stroke="#c3ccd9" stroke-width="2"/>
+<path d="M 14 {ty} l 2 10 l 12 -10" fill="white" stroke="#c3ccd9" stroke-width="2" stroke-linejoin="round"/>
+<rect x="12" y="{ty2}" width="18" height="3" fill="white"/>
+{text_lines}</svg>"##,
+        bw = width - 2.0,
+        bh = body_height - 2.0,
+        ty = body_height - 1.0,
+        ty2 = body_height - 4.0,
+    )
+}
@@ -0,0 +1,1009 @@
+use std::fmt::Write;
+
+use crate::{
+    ansi_codes,
+    bc_compiler::{
+        bc_comp_diagnostics::ReportOptions, bc_comp_main::ThreadCompiler,
+        bc_comp_variable_manager::VariableCompiler,
+    },
+    interpreter::{Instruction, Memory, NumberOp, Registers, Value},
+    project::project_main::Project,
+    thread::{dump_memory, run_memory_instruction, ScriptBytecode},
+};
+
+// Assembly files hold the instructions the way Instruction::print writes them,
+// one to a line, between these directives:
+//
+//     .variable *0 "id" = 10       a variable and what it starts as, in pointer order
+//     .sprite "Sprite1"            starts the scripts of a sprite
+//     .script registers 2          starts a script, the register count is optional
+//
+// Registers are written r0, or f0 and b0 for ones that only hold numbers or booleans,
+// which all share the count.
+// Lines starting with # are comments. Jumps can go to an instruction index,
+// or to a place defined by a line like "loop:", which is what the disassembler writes.
+pub struct Assembly {
+    pub variables: Vec<Value>,
+    pub variable_names: Vec<Option<String>>,
+    pub sprites: Vec<AssemblySprite>,
+}
+
+pub struct AssemblySprite {
+    pub name: String,
+    pub scripts: Vec<ScriptBytecode>,
+}
+
+// Stops runaway loops in scripts that can't be paused from outside.
+const STEP_LIMIT: usize = 100_000_000;
+
+// Writes the compiled scripts of a project as assembly, to a file or otherwise
+// to stdout. With dump, every script is also printed to stdout before and after it is optimized.
+pub fn disassemble(
+    project_path: &str,
+    output: Option<&str>,
+    dump: bool,
+    report_options: &ReportOptions,
+) -> Result<(), String> {
+    let archive = std::fs::read(project_path)
+        .map_err(|err| format!("Could not read {project_path}: {err}"))?;
+    let (_project_directory, extracted_path) = Project::extract_zip_file(archive)?;
+    let json = Project::load_json(&extracted_path);
+    let (variables, scripts, report) = Project::compile_scripts(&json, dump)?;
+    report.show(report_options)?;
+
+    let names = json["targets"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|sprite| sprite["name"].as_str().unwrap_or_default().to_owned());
+    let assembly = Assembly {
+        variable_names: variables.entries().map(|(name, _)| name.cloned()).collect(),
+        variables: variables
+            .entries()
+            .map(|(_, value)| value.clone())
+            .collect(),
+        sprites: names
+            .zip(scripts)
+            .map(|(name, scripts)| AssemblySprite { name, scripts })
+            .collect(),
+    };
+    let text = format!("# Disassembled from {project_path}\n{}", assembly.print());
+    match output {
+        Some(output) => {
+            std::fs::write(output, text).map_err(|err| format!("Could not write {output}: {err}"))
+        }
+        None => {
+            print!("{text}");
+            Ok(())
+        }
+    }
+}
+
+// Assembles a file and runs every script in it, one after another, on the same variables.
+// Only the instructions that don't need a project can run, which is enough for testing the VM.
+pub fn assemble(path: &str) -> Result<(), String> {
+    let text =
+        std::fs::read_to_string(path).map_err(|err| format!("Could not read {path}: {err}"))?;
+    Assembly::parse(&text)?.run()
+}
+
+// Returns how many frames the script would have taken, warp mode pauses don't end one.
+pub(crate) fn run(instructions: &[Instruction], memory: &mut Memory) -> Result<usize, String> {
+    let mut counter = 0;
+    let mut frames = 0;
+    for _ in 0..STEP_LIMIT {
+        let Some(instruction) = instructions.get(counter) else {
+            return Ok(frames);
+        };
+        counter += 1;
+        match instruction {
+            Instruction::MemoryDump => {
+                println!("{}[memory dump]{} {{", ansi_codes::GREEN, ansi_codes::RESET);
+                dump_memory(memory);
+                println!("}}")
+            }
+            Instruction::ThreadPause => frames += 1,
+            Instruction::ThreadWarpPause => {}
+            Instruction::ThreadKill => return Ok(frames),
+            _ if run_memory_instruction(instruction, memory, &mut counter) => {}
+            _ => {
+                return Err(format!(
+                    "{} needs a running project, at {}",
+                    instruction.print(None),
+                    counter - 1
+                ))
+            }
+        }
+    }
+    Err(format!("Still running after {STEP_LIMIT} instructions"))
+}
+
+impl Assembly {
+    pub fn run(&mut self) -> Result<(), String> {
+        for sprite in self.sprites.iter() {
+            for (number, script) in sprite.scripts.iter().enumerate() {
+                let mut registers = Registers::new(script.register_count);
+                let mut memory = Memory::new(&mut self.variables, &mut registers, &mut []);
+                run(&script.instructions, &mut memory).map_err(|err| {
+                    format!("Script {number} of sprite \"{}\": {err}", sprite.name)
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn print(&self) -> String {
+        let mut out = String::new();
+        for (pointer, value) in self.variables.iter().enumerate() {
+            let name = match self.variable_names.get(pointer) {
+                Some(Some(name)) => format!(" {name:?}"),
+                _ => String::new(),
+            };
+            writeln!(out, ".variable *{pointer}{name} = {}", value.print(None)).unwrap();
+        }
+        for sprite in self.sprites.iter() {
+            writeln!(out, "\n.sprite {:?}", sprite.name).unwrap();
+            for script in sprite.scripts.iter() {
+                writeln!(out, "\n.script registers {}", script.register_count).unwrap();
+                print_script(&mut out, &script.instructions);
+            }
+        }
+        out
+    }
+
+    pub fn parse(text: &str) -> Result<Assembly, String> {
+        let mut assembly = Assembly {
+            variables: Vec::new(),
+            variable_names: Vec::new(),
+            sprites: Vec::new(),
+        };
+        // Registers a script said it has, if it did.
+        let mut declared_registers: Vec<Vec<Option<usize>>> = Vec::new();
+
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |err: String| format!("Line {}: {err}", line_number + 1);
+            let mut cursor = Cursor { rest: line };
+
+            if cursor.eat(".variable ") {
+                let pointer = match cursor.value().map_err(error)? {
+                    Value::Pointer(pointer) => pointer,
+                    _ => return Err(error("Expected a variable like *0".to_owned())),
+                };
+                if pointer != assembly.variables.len() {
+                    return Err(error(format!(
+                        "Expected *{} next, variables have to be in order",
+                        assembly.variables.len()
+                    )));
+                }
+                cursor.expect(" ").map_err(error)?;
+                let name = if cursor.rest.starts_with('"') {
+                    let name = cursor.string().map_err(error)?;
+                    cursor.expect(" ").map_err(error)?;
+                    Some(name)
+                } else {
+                    None
+                };
+                cursor.expect("= ").map_err(error)?;
+                let value = cursor.value().map_err(error)?;
+                cursor.end().map_err(error)?;
+                assembly.variables.push(value);
+                assembly.variable_names.push(name);
+            } else if cursor.eat(".sprite ") {
+                let name = cursor.string().map_err(error)?;
+                cursor.end().map_err(error)?;
+                assembly.sprites.push(AssemblySprite {
+                    name,
+                    scripts: Vec::new(),
+                });
+                declared_registers.push(Vec::new());
+            } else if cursor.eat(".script") {
+                let registers = if cursor.eat(" registers ") {
+                    Some(cursor.index().map_err(error)?)
+                } else {
+                    None
+                };
+                cursor.end().map_err(error)?;
+                let (Some(sprite), Some(declared)) =
+                    (assembly.sprites.last_mut(), declared_registers.last_mut())
+                else {
+                    return Err(error("Scripts have to be inside a .sprite".to_owned()));
+                };
+                sprite.scripts.push(ScriptBytecode {
+                    instructions: Vec::new(),
+                    register_count: 0,
+                });
+                declared.push(registers);
+            } else if line.starts_with('.') {
+                return Err(error(format!("Unknown directive {line}")));
+            } else {
+                let Some(script) = assembly
+                    .sprites
+                    .last_mut()
+                    .and_then(|sprite| sprite.scripts.last_mut())
+                else {
+                    return Err(error("Instructions have to be inside a .script".to_owned()));
+                };
+                script
+                    .instructions
+                    .push(Instruction::parse(line).map_err(error)?);
+            }
+        }
+
+        let variable_count = assembly.variables.len();
+        for (sprite, declared) in assembly.sprites.iter_mut().zip(declared_registers) {
+            for (number, (script, declared)) in sprite.scripts.iter_mut().zip(declared).enumerate()
+            {
+                let error =
+                    |err: String| format!("Script {number} of sprite \"{}\": {err}", sprite.name);
+                // Linking doesn't look at the project, so there's none to give it.
+                let mut variables = VariableCompiler::new();
+                let no_sprite = serde_json::Value::Null;
+                ThreadCompiler::new(
+                    &mut variables,
+                    &mut script.instructions,
+                    number,
+                    &no_sprite,
+                    &[],
+                )
+                .link()
+                .map_err(error)?;
+
+                let mut used_registers = 0;
+                for instruction in script.instructions.iter_mut() {
+                    for (_, value) in instruction.operands_mut() {
+                        match value {
+                            Value::Register(register)
+                            | Value::NumberRegister(register)
+                            | Value::BooleanRegister(register) => {
+                                used_registers = used_registers.max(*register + 1)
+                            }
+                            Value::Pointer(pointer) if *pointer >= variable_count => {
+                                return Err(error(format!(
+                                    "Uses *{pointer}, but only {variable_count} variables are declared"
+                                )));
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                script.register_count = match declared {
+                    Some(declared) if declared < used_registers => {
+                        return Err(error(format!(
+                            "Uses r{}, but only has {declared} registers",
+                            used_registers - 1
+                        )))
+                    }
+                    Some(declared) => declared,
+                    None => used_registers,
+                };
+            }
+        }
+        Ok(assembly)
+    }
+}
+
+// Jumps are written going to places named after where they land,
+// so instructions can be added or taken out by hand without renumbering.
+fn print_script(out: &mut String, instructions: &[Instruction]) {
+    let mut targets = vec![false; instructions.len()];
+    for instruction in instructions.iter() {
+        if let Instruction::FlowIfJump(_, location) | Instruction::FlowIfNotJump(_, location) =
+            instruction
+        {
+            if let Some(target) = targets.get_mut(*location) {
+                *target = true;
+            }
+        }
+    }
+    for (index, instruction) in instructions.iter().enumerate() {
+        if targets[index] {
+            writeln!(out, "L{index}:").unwrap();
+        }
+        let instruction = match instruction {
+            Instruction::FlowIfJump(condition, location) if *location < instructions.len() => {
+                Instruction::FlowIfJumpToPlace(condition.clone(), format!("L{location}"))
+            }
+            Instruction::FlowIfNotJump(condition, location) if *location < instructions.len() => {
+                Instruction::FlowIfNotJumpToPlace(condition.clone(), format!("L{location}"))
+            }
+            instruction => instruction.clone(),
+        };
+        writeln!(out, "    {}", instruction.print(None)).unwrap();
+    }
+}
+
+// Constructors of instructions, by how many values they take.
+type OneValue = fn(Value) -> Instruction;
+type TwoValues = fn(Value, Value) -> Instruction;
+type ThreeValues = fn(Value, Value, Value) -> Instruction;
+
+const BINARY_OPERATORS: [(&str, ThreeValues, NumberOp); 9] = [
+    ("==", Instruction::OperatorEquals, NumberOp::Equals),
+    ("%", Instruction::OperatorModulo, NumberOp::Modulo),
+    ("+", Instruction::OperatorAdd, NumberOp::Add),
+    ("-", Instruction::OperatorSubtract, NumberOp::Subtract),
+    ("*", Instruction::OperatorMultiply, NumberOp::Multiply),
+    ("/", Instruction::OperatorDivide, NumberOp::Divide),
+    ("^", Instruction::OperatorPower, NumberOp::Power),
+    ("<", Instruction::OperatorLesser, NumberOp::Lesser),
+    (">", Instruction::OperatorGreater, NumberOp::Greater),
+];
+
+// Written as "location = name(input)".
+const FUNCTIONS: [(&str, TwoValues); 13] = [
+    ("sin", Instruction::OperatorSin),
+    ("cos", Instruction::OperatorCos),
+    ("tan", Instruction::OperatorTan),
+    ("abs", Instruction::OperatorAbs),
+    ("asin", Instruction::OperatorASin),
+    ("acos", Instruction::OperatorACos),
+    ("atan", Instruction::OperatorATan),
+    ("sqrt", Instruction::OperatorSqrt),
+    ("ln", Instruction::OperatorLn),
+    ("log", Instruction::OperatorLog),
+    ("floor", Instruction::OperatorFloor),
+    ("ceiling", Instruction::OperatorCeiling),
+    ("distance_to", Instruction::SensingDistanceTo),
+];
+
+// Written as "location = name()".
+const GETTERS: [(&str, OneValue); 9] = [
+    ("timer", Instruction::SensingTimer),
+    ("answer", Instruction::SensingAnswer),
+    ("days_since_2000", Instruction::SensingDaysSince2000),
+    ("username", Instruction::SensingUsername),
+    ("sound_volume", Instruction::SoundGetVolume),
+    ("music_tempo", Instruction::MusicGetTempo),
+    ("get_costume_number", Instruction::LooksGetCostumeNumber),
+    ("get_x", Instruction::MotionGetX),
+    ("get_y", Instruction::MotionGetY),
+];
+
+// Written as "name()".
+const COMMANDS: [(&str, Instruction); 12] = [
+    ("render_frame", Instruction::ThreadPause),
+    ("warp_render_frame", Instruction::ThreadWarpPause),
+    ("reset_timer", Instruction::SensingResetTimer),
+    ("sound_stop_all", Instruction::SoundStopAll),
+    ("sound_clear_effects", Instruction::SoundClearEffects),
+    ("pen_clear", Instruction::PenClear),
+    ("pen_stamp", Instruction::PenStamp),
+    ("pen_up", Instruction::PenUp),
+    ("pen_down", Instruction::PenDown),
+    ("looks_hide", Instruction::LooksHide),
+    ("looks_show", Instruction::LooksShow),
+    ("looks_next_costume", Instruction::LooksNextCostume),
+];
+
+// Written as "name(input)".
+const COMMANDS_WITH_INPUT: [(&str, OneValue); 15] = [
+    ("sound_play", Instruction::SoundPlay),
+    ("sound_play_until_done", Instruction::SoundPlayUntilDone),
+    ("sound_set_volume", Instruction::SoundSetVolume),
+    ("sound_change_volume", Instruction::SoundChangeVolume),
+    ("music_rest", Instruction::MusicRest),
+    ("music_set_tempo", Instruction::MusicSetTempo),
+    ("music_change_tempo", Instruction::MusicChangeTempo),
+    ("music_set_instrument", Instruction::MusicSetInstrument),
+    ("pen_set_size", Instruction::PenSetRadius),
+    ("pen_change_size", Instruction::PenChangeRadius),
+    ("pen_set_color", Instruction::PenSetColor),
+    ("pen_set_hue", Instruction::PenSetHue),
+    ("pen_set_shade", Instruction::PenSetShade),
+    ("show_variable", Instruction::DataShowVariable),
+    ("hide_variable", Instruction::DataHideVariable),
+];
+
+// Written as "name(input, input)".
+const COMMANDS_WITH_TWO_INPUTS: [(&str, TwoValues); 4] = [
+    ("music_play_drum", Instruction::MusicPlayDrum),
+    ("music_play_note", Instruction::MusicPlayNote),
+    ("pen_set_color_param", Instruction::PenSetColorParam),
+    ("pen_change_color_param", Instruction::PenChangeColorParam),
+];
+
+// Written as "phrase input".
+const PHRASES: [(&str, OneValue); 6] = [
+    ("change x by ", Instruction::MotionChangeX),
+    ("change y by ", Instruction::MotionChangeY),
+    ("set x to ", Instruction::MotionSetX),
+    ("set y to ", Instruction::MotionSetY),
+    ("set size to ", Instruction::LooksSetSize),
+    ("set costume to ", Instruction::LooksSetCostume),
+];
+
+fn lookup<T: Clone>(table: &[(&str, T)], name: &str) -> Option<T> {
+    table
+        .iter()
+        .find(|(entry, _)| *entry == name)
+        .map(|(_, item)| item.clone())
+}
+
+impl Instruction {
+    // Reads an instruction back from what print(None) gave.
+    pub fn parse(line: &str) -> Result<Instruction, String> {
+        let line = line.trim();
+        let mut cursor = Cursor { rest: line };
+        let is_register = |text: &str| {
+            text.strip_prefix(['r', 'f', 'b'])
+                .is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_digit()))
+        };
+        let instruction = match line {
+            "dump_memory();" => return Ok(Instruction::MemoryDump),
+            "return;" => return Ok(Instruction::ThreadKill),
+            _ if line.starts_with('*') || is_register(line) => cursor.assignment()?,
+            _ if cursor.eat("if ") => {
+                let negated = cursor.eat("!");
+                let condition = cursor.value()?;
+                if cursor.eat(" jump to") {
+                    cursor.expect(" ")?;
+                    let location = cursor.index()?;
+                    match negated {
+                        false => Instruction::FlowIfJump(condition, location),
+                        true => Instruction::FlowIfNotJump(condition, location),
+                    }
+                } else {
+                    cursor.expect(" goto ")?;
+                    let place = cursor.place()?;
+                    match negated {
+                        false => Instruction::FlowIfJumpToPlace(condition, place),
+                        true => Instruction::FlowIfNotJumpToPlace(condition, place),
+                    }
+                }
+            }
+            _ if cursor.eat("go to x: ") => {
+                let x = cursor.value()?;
+                cursor.expect(", y: ")?;
+                Instruction::MotionSetXY(x, cursor.value()?)
+            }
+            _ => match PHRASES
+                .iter()
+                .find(|(phrase, _)| cursor.rest.starts_with(phrase))
+            {
+                Some((phrase, instruction)) => {
+                    cursor.eat(phrase);
+                    instruction(cursor.value()?)
+                }
+                None => {
+                    let place = cursor.place()?;
+                    if cursor.eat(":") {
+                        Instruction::FlowDefinePlace(place)
+                    } else {
+                        cursor.command(&place)?
+                    }
+                }
+            },
+        };
+        cursor.end()?;
+        Ok(instruction)
+    }
+}
+
+struct Cursor<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Cursor<'a> {
+    fn eat(&mut self, text: &str) -> bool {
+        match self.rest.strip_prefix(text) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), String> {
+        if self.eat(text) {
+            Ok(())
+        } else {
+            Err(format!("Expected \"{text}\" at \"{}\"", self.rest))
+        }
+    }
+
+    fn end(&self) -> Result<(), String> {
+        if self.rest.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("Unexpected \"{}\"", self.rest))
+        }
+    }
+
+    // Takes characters for as long as they match.
+    fn take_while(&mut self, matches: impl Fn(char) -> bool) -> &'a str {
+        let length = self
+            .rest
+            .find(|c: char| !matches(c))
+            .unwrap_or(self.rest.len());
+        let (taken, rest) = self.rest.split_at(length);
+        self.rest = rest;
+        taken
+    }
+
+    fn index(&mut self) -> Result<usize, String> {
+        let digits = self.take_while(|c| c.is_ascii_digit());
+        digits
+            .parse()
+            .map_err(|_| format!("Expected a number at \"{}\"", self.rest))
+    }
+
+    fn place(&mut self) -> Result<String, String> {
+        match self.take_while(|c| c.is_ascii_alphanumeric() || c == '_') {
+            "" => Err(format!("Expected a name at \"{}\"", self.rest)),
+            name => Ok(name.to_owned()),
+        }
+    }
+
+    // Strings are quoted and escaped the same way as Rust's Debug formatting.
+    fn string(&mut self) -> Result<String, String> {
+        let start = self.rest;
+        self.expect("\"")?;
+        let mut string = String::new();
+        let mut chars = self.rest.char_indices();
+        while let Some((index, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.rest = &self.rest[index + 1..];
+                    return Ok(string);
+                }
+                '\\' => string.push(match chars.next().map(|(_, c)| c) {
+                    Some('n') => '\n',
+                    Some('r') => '\r',
+                    Some('t') => '\t',
+                    Some('0') => '\0',
+                    Some(c @ ('\\' | '"' | '\'')) => c,
+                    Some('u') => {
+                        let escape: String = chars
+                            .by_ref()
+                            .map(|(_, c)| c)
+                            .take_while(|c| *c != '}')
+                            .collect();
+                        escape
+                            .strip_prefix('{')
+                            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                            .and_then(char::from_u32)
+                            .ok_or_else(|| format!("Bad unicode escape in {start}"))?
+                    }
+                    _ => return Err(format!("Bad escape in {start}")),
+                }),
+                c => string.push(c),
+            }
+        }
+        Err(format!("Unterminated string {start}"))
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        if self.rest.starts_with('"') {
+            return Ok(Value::String(self.string()?));
+        }
+        if self.eat("*") {
+            return Ok(Value::Pointer(self.index()?));
+        }
+        if self.eat("[") {
+            let mut items = Vec::new();
+            if !self.eat("]") {
+                loop {
+                    items.push(self.value()?);
+                    if self.eat("]") {
+                        break;
+                    }
+                    self.expect(", ")?;
+                }
+            }
+            return Ok(Value::List(items));
+        }
+        let start = self.rest;
+        let token = self.take_while(|c| c.is_ascii_alphanumeric() || "._+-".contains(c));
+        match token {
+            "true" => Ok(Value::Boolean(true)),
+            "false" => Ok(Value::Boolean(false)),
+            _ => {
+                let register = |prefix| token.strip_prefix(prefix).and_then(|n| n.parse().ok());
+                if let Some(register) = register('r') {
+                    Ok(Value::Register(register))
+                } else if let Some(register) = register('f') {
+                    Ok(Value::NumberRegister(register))
+                } else if let Some(register) = register('b') {
+                    Ok(Value::BooleanRegister(register))
+                } else {
+                    token
+                        .parse()
+                        .map(Value::Number)
+                        .map_err(|_| format!("Expected a value at \"{start}\""))
+                }
+            }
+        }
+    }
+
+    // Inputs between brackets, after the name.
+    fn inputs(&mut self) -> Result<Vec<Value>, String> {
+        self.expect("(")?;
+        let mut inputs = Vec::new();
+        if !self.eat(")") {
+            loop {
+                inputs.push(self.value()?);
+                if self.eat(")") {
+                    break;
+                }
+                self.expect(", ")?;
+            }
+        }
+        Ok(inputs)
+    }
+
+    fn assignment(&mut self) -> Result<Instruction, String> {
+        let location = self.value()?;
+        if !matches!(
+            location,
+            Value::Pointer(_)
+                | Value::Register(_)
+                | Value::NumberRegister(_)
+                | Value::BooleanRegister(_)
+        ) {
+            return Err("Can only store values in variables and registers".to_owned());
+        }
+        self.expect(" = ")?;
+
+        if self.eat("e ^ ") {
+            return Ok(Instruction::OperatorERaised(location, self.value()?));
+        }
+        let name = self
+            .rest
+            .split('(')
+            .next()
+            .filter(|name| {
+                name.chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+            })
+            .filter(|name| self.rest.len() > name.len());
+        if let Some(name) = name {
+            self.eat(name);
+            let mut inputs = self.inputs()?.into_iter();
+            let instruction = match (name, inputs.next(), inputs.next()) {
+                ("current", Some(Value::String(menu)), None) => {
+                    Instruction::SensingCurrent(location, menu)
+                }
+                (_, Some(input), None) if lookup(&FUNCTIONS, name).is_some() => {
+                    lookup(&FUNCTIONS, name).unwrap()(location, input)
+                }
+                (_, None, None) if lookup(&GETTERS, name).is_some() => {
+                    lookup(&GETTERS, name).unwrap()(location)
+                }
+                _ => return Err(format!("Unknown function {name}")),
+            };
+            return Ok(instruction);
+        }
+
+        let a = self.value()?;
+        if self.eat(";") {
+            return Ok(Instruction::MemoryStore(location, a));
+        }
+        if self.eat(" of ") {
+            let Value::String(property) = a else {
+                return Err("The property before \"of\" has to be a string".to_owned());
+            };
+            return Ok(Instruction::SensingOf(location, property, self.value()?));
+        }
+        self.expect(" ")?;
+        let Some((_, instruction, op)) = BINARY_OPERATORS
+            .iter()
+            .find(|(symbol, ..)| self.eat(symbol))
+        else {
+            return Err(format!("Expected an operator at \"{}\"", self.rest));
+        };
+        self.expect(" ")?;
+        let b = self.value()?;
+        if self.eat(" (numbers)") {
+            Ok(Instruction::OperatorNumbers(*op, location, a, b))
+        } else {
+            Ok(instruction(location, a, b))
+        }
+    }
+
+    fn command(&mut self, name: &str) -> Result<Instruction, String> {
+        let mut inputs = self.inputs()?.into_iter();
+        let instruction = match (name, inputs.next(), inputs.next(), inputs.next()) {
+            ("ask_and_wait", Some(question), Some(Value::Boolean(can_show_bubble)), None) => {
+                Instruction::SensingAskAndWait(question, can_show_bubble)
+            }
+            ("sound_set_effect", Some(Value::String(effect)), Some(value), None) => {
+                Instruction::SoundSetEffect(effect, value)
+            }
+            ("sound_change_effect", Some(Value::String(effect)), Some(value), None) => {
+                Instruction::SoundChangeEffect(effect, value)
+            }
+            (_, None, None, None) if lookup(&COMMANDS, name).is_some() => {
+                lookup(&COMMANDS, name).unwrap()
+            }
+            (_, Some(input), None, None) if lookup(&COMMANDS_WITH_INPUT, name).is_some() => {
+                lookup(&COMMANDS_WITH_INPUT, name).unwrap()(input)
+            }
+            (_, Some(a), Some(b), None) if lookup(&COMMANDS_WITH_TWO_INPUTS, name).is_some() => {
+                lookup(&COMMANDS_WITH_TWO_INPUTS, name).unwrap()(a, b)
+            }
+            _ => return Err(format!("Unknown instruction {name}")),
+        };
+        Ok(instruction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Assembly;
+    use crate::interpreter::{Instruction, NumberOp, Value};
+
+    // One of every instruction, with operands of every kind between them.
+    fn examples() -> Vec<Instruction> {
+        let (r, p) = (Value::Register(3), Value::Pointer(12));
+        let n = || Value::Number(-2.5);
+        let s = || Value::String("say \"hi\"\n\tü".to_owned());
+        vec![
+            Instruction::MemoryStore(p.clone(), s()),
+            Instruction::MemoryStore(
+                r.clone(),
+                Value::List(vec![n(), s(), Value::Boolean(false), Value::List(vec![])]),
+            ),
+            Instruction::MemoryDump,
+            Instruction::ThreadKill,
+            Instruction::ThreadPause,
+            Instruction::ThreadWarpPause,
+            Instruction::OperatorModulo(r.clone(), p.clone(), n()),
+            Instruction::OperatorAdd(p.clone(), s(), r.clone()),
+            Instruction::OperatorSubtract(r.clone(), Value::Number(1e21), n()),
+            Instruction::OperatorMultiply(r.clone(), Value::Number(0.1), p.clone()),
+            Instruction::OperatorDivide(r.clone(), n(), Value::Number(f64::INFINITY)),
+            Instruction::OperatorLesser(r.clone(), p.clone(), Value::Boolean(true)),
+            Instruction::OperatorPower(r.clone(), n(), n()),
+            Instruction::OperatorERaised(r.clone(), p.clone()),
+            Instruction::OperatorSin(r.clone(), n()),
+            Instruction::OperatorCos(r.clone(), n()),
+            Instruction::OperatorTan(r.clone(), n()),
+            Instruction::OperatorAbs(r.clone(), n()),
+            Instruction::OperatorASin(r.clone(), n()),
+            Instruction::OperatorACos(r.clone(), n()),
+            Instruction::OperatorATan(r.clone(), n()),
+            Instruction::OperatorSqrt(r.clone(), n()),
+            Instruction::OperatorLn(r.clone(), n()),
+            Instruction::OperatorLog(r.clone(), n()),
+            Instruction::OperatorFloor(r.clone(), n()),
+            Instruction::OperatorCeiling(r.clone(), n()),
+            Instruction::OperatorGreater(p.clone(), r.clone(), s()),
+            Instruction::OperatorEquals(p.clone(), s(), s()),
+            Instruction::OperatorNumbers(NumberOp::Add, r.clone(), r.clone(), n()),
+            Instruction::OperatorNumbers(NumberOp::Subtract, r.clone(), r.clone(), n()),
+            Instruction::OperatorNumbers(NumberOp::Multiply, r.clone(), r.clone(), n()),
+            Instruction::OperatorNumbers(NumberOp::Divide, r.clone(), r.clone(), n()),
+            Instruction::OperatorNumbers(NumberOp::Modulo, r.clone(), r.clone(), n()),
+            Instruction::OperatorNumbers(NumberOp::Power, r.clone(), r.clone(), n()),
+            Instruction::OperatorNumbers(NumberOp::Lesser, r.clone(), r.clone(), n()),
+            Instruction::OperatorNumbers(NumberOp::Greater, r.clone(), r.clone(), n()),
+            Instruction::OperatorNumbers(NumberOp::Equals, r.clone(), r.clone(), n()),
+            Instruction::SensingTimer(r.clone()),
+            Instruction::SensingAskAndWait(s(), true),
+            Instruction::SensingAskAndWait(p.clone(), false),
+            Instruction::SensingAnswer(p.clone()),
+            Instruction::SensingResetTimer,
+            Instruction::SensingDistanceTo(r.clone(), s()),
+            Instruction::SensingOf(r.clone(), "x position".to_owned(), s()),
+            Instruction::SensingCurrent(r.clone(), "YEAR".to_owned()),
+            Instruction::SensingDaysSince2000(r.clone()),
+            Instruction::SensingUsername(r.clone()),
+            Instruction::SoundPlay(s()),
+            Instruction::SoundPlayUntilDone(r.clone()),
+            Instruction::SoundStopAll,
+            Instruction::SoundSetEffect("PITCH".to_owned(), n()),
+            Instruction::SoundChangeEffect("PAN".to_owned(), p.clone()),
+            Instruction::SoundClearEffects,
+            Instruction::SoundSetVolume(n()),
+            Instruction::SoundChangeVolume(r.clone()),
+            Instruction::SoundGetVolume(r.clone()),
+            Instruction::MusicPlayDrum(n(), r.clone()),
+            Instruction::MusicPlayNote(p.clone(), n()),
+            Instruction::MusicRest(n()),
+            Instruction::MusicSetTempo(n()),
+            Instruction::MusicChangeTempo(n()),
+            Instruction::MusicGetTempo(r.clone()),
+            Instruction::MusicSetInstrument(s()),
+            Instruction::FlowIfJump(r.clone(), 7),
+            Instruction::FlowIfJumpToPlace(Value::Boolean(true), "loop_end2".to_owned()),
+            Instruction::FlowDefinePlace("loop_end2".to_owned()),
+            Instruction::FlowIfNotJump(p.clone(), 0),
+            Instruction::FlowIfNotJumpToPlace(r.clone(), "L12".to_owned()),
+            Instruction::MotionChangeX(n()),
+            Instruction::MotionChangeY(p.clone()),
+            Instruction::MotionSetXY(n(), r.clone()),
+            Instruction::MotionSetX(n()),
+            Instruction::MotionSetY(s()),
+            Instruction::MotionGetX(r.clone()),
+            Instruction::MotionGetY(p.clone()),
+            Instruction::LooksSetSize(n()),
+            Instruction::LooksSetCostume(s()),
+            Instruction::LooksNextCostume,
+            Instruction::LooksGetCostumeNumber(r.clone()),
+            Instruction::LooksHide,
+            Instruction::LooksShow,
+            Instruction::PenClear,
+            Instruction::PenStamp,
+            Instruction::PenUp,
+            Instruction::PenDown,
+            Instruction::PenSetRadius(n()),
+            Instruction::PenChangeRadius(r.clone()),
+            Instruction::PenSetColor(s()),
+            Instruction::PenSetColorParam(s(), n()),
+            Instruction::PenChangeColorParam(s(), r.clone()),
+            Instruction::PenSetHue(n()),
+            Instruction::PenSetShade(p.clone()),
+            Instruction::DataShowVariable(p.clone()),
+            Instruction::DataHideVariable(p),
+        ]
+    }
+
+    // Doesn't do anything, but stops compiling when an instruction is added,
+    // as a reminder to add an example of it above.
+    fn _every_instruction_has_an_example(instruction: Instruction) {
+        match instruction {
+            Instruction::MemoryStore(..)
+            | Instruction::MemoryDump
+            | Instruction::ThreadKill
+            | Instruction::ThreadPause
+            | Instruction::ThreadWarpPause
+            | Instruction::OperatorModulo(..)
+            | Instruction::OperatorAdd(..)
+            | Instruction::OperatorSubtract(..)
+            | Instruction::OperatorMultiply(..)
+            | Instruction::OperatorDivide(..)
+            | Instruction::OperatorLesser(..)
+            | Instruction::OperatorPower(..)
+            | Instruction::OperatorERaised(..)
+            | Instruction::OperatorSin(..)
+            | Instruction::OperatorCos(..)
+            | Instruction::OperatorTan(..)
+            | Instruction::OperatorAbs(..)
+            | Instruction::OperatorASin(..)
+            | Instruction::OperatorACos(..)
+            | Instruction::OperatorATan(..)
+            | Instruction::OperatorSqrt(..)
+            | Instruction::OperatorLn(..)
+            | Instruction::OperatorLog(..)
+            | Instruction::OperatorFloor(..)
+            | Instruction::OperatorCeiling(..)
+            | Instruction::OperatorGreater(..)
+            | Instruction::OperatorEquals(..)
+            | Instruction::OperatorNumbers(..)
+            | Instruction::SensingTimer(..)
+            | Instruction::SensingAskAndWait(..)
+            | Instruction::SensingAnswer(..)
+            | Instruction::SensingResetTimer
+            | Instruction::SensingDistanceTo(..)
+            | Instruction::SensingOf(..)
+            | Instruction::SensingCurrent(..)
+            | Instruction::SensingDaysSince2000(..)
+            | Instruction::SensingUsername(..)
+            | Instruction::SoundPlay(..)
+            | Instruction::SoundPlayUntilDone(..)
+            | Instruction::SoundStopAll
+            | Instruction::SoundSetEffect(..)
+            | Instruction::SoundChangeEffect(..)
+            | Instruction::SoundClearEffects
+            | Instruction::SoundSetVolume(..)
+            | Instruction::SoundChangeVolume(..)
+            | Instruction::SoundGetVolume(..)
+            | Instruction::MusicPlayDrum(..)
+            | Instruction::MusicPlayNote(..)
+            | Instruction::MusicRest(..)
+            | Instruction::MusicSetTempo(..)
+            | Instruction::MusicChangeTempo(..)
+            | Instruction::MusicGetTempo(..)
+            | Instruction::MusicSetInstrument(..)
+            | Instruction::FlowIfJump(..)
+            | Instruction::FlowIfJumpToPlace(..)
+            | Instruction::FlowDefinePlace(..)
+            | Instruction::FlowIfNotJump(..)
+            | Instruction::FlowIfNotJumpToPlace(..)
+            | Instruction::MotionChangeX(..)
+            | Instruction::MotionChangeY(..)
+            | Instruction::MotionSetXY(..)
+            | Instruction::MotionSetX(..)
+            | Instruction::MotionSetY(..)
+            | Instruction::MotionGetX(..)
+            | Instruction::MotionGetY(..)
+            | Instruction::LooksSetSize(..)
+            | Instruction::LooksSetCostume(..)
+            | Instruction::LooksNextCostume
+            | Instruction::LooksGetCostumeNumber(..)
+            | Instruction::LooksHide
+            | Instruction::LooksShow
+            | Instruction::PenClear
+            | Instruction::PenStamp
+            | Instruction::PenUp
+            | Instruction::PenDown
+            | Instruction::PenSetRadius(..)
+            | Instruction::PenChangeRadius(..)
+            | Instruction::PenSetColor(..)
+            | Instruction::PenSetColorParam(..)
+            | Instruction::PenChangeColorParam(..)
+            | Instruction::PenSetHue(..)
+            | Instruction::PenSetShade(..)
+            | Instruction::DataShowVariable(..)
+            | Instruction::DataHideVariable(..) => {}
+        }
+    }
+
+    #[test]
+    fn every_instruction_reads_back_as_itself() {
+        let examples = examples();
+        let kinds: std::collections::HashSet<_> =
+            examples.iter().map(std::mem::discriminant).collect();
+        // Some are there more than once, like OperatorNumbers for each of its operators.
+        assert_eq!(kinds.len(), 84);
+        for instruction in examples {
+            let printed = instruction.print(None);
+            assert_eq!(Instruction::parse(&printed), Ok(instruction), "{printed}");
+        }
+    }
+
+    #[test]
+    fn files_read_back_as_themselves() {
+        for path in ["fibonacci", "multiplication_table", "conversions"] {
+            let text = std::fs::read_to_string(format!("tests/asm/{path}.rasm")).unwrap();
+            let assembly = Assembly::parse(&text).unwrap();
+            let printed = assembly.print();
+            let reassembled = Assembly::parse(&printed).unwrap();
+            assert_eq!(reassembled.variables, assembly.variables);
+            assert_eq!(reassembled.variable_names, assembly.variable_names);
+            for (sprite, reassembled) in assembly.sprites.iter().zip(&reassembled.sprites) {
+                assert_eq!(sprite.name, reassembled.name);
+                for (script, reassembled) in sprite.scripts.iter().zip(&reassembled.scripts) {
+                    assert_eq!(script.instructions, reassembled.instructions);
+                    assert_eq!(script.register_count, reassembled.register_count);
+                }
+            }
+        }
+    }
+
+    // Runs a file from tests/asm and gives what it left in its variables.
+    fn run_file(name: &str) -> Vec<Value> {
+        let text = std::fs::read_to_string(format!("tests/asm/{name}.rasm")).unwrap();
+        let mut assembly = Assembly::parse(&text).unwrap();
+        assembly.run().unwrap();
+        assembly.variables
+    }
+
+    #[test]
+    fn fibonacci() {
+        let variables = run_file("fibonacci");
+        assert_eq!(variables[0], Value::Number(832040.0));
+        assert_eq!(variables[2], Value::Number(30.0));
+    }
+
+    #[test]
+    fn multiplication_table() {
+        let variables = run_file("multiplication_table");
+        assert_eq!(variables[0], Value::Number(2738.0));
+        assert_eq!(variables[3], Value::Number(51.0));
+    }
+
+    #[test]
+    fn conversions() {
+        let variables = run_file("conversions");
+        let expected = [
+            Value::Number(7.0),
+            Value::Number(1.0),
+            Value::Number(f64::INFINITY),
+            Value::Number(2.0),
+            Value::Number(0.0),
+            Value::Number(-3.0),
+            Value::Number(2000.0),
+            Value::Boolean(true),
+            Value::Number(0.0),
+            Value::Number(2.0),
+        ];
+        assert_eq!(variables[..expected.len()], expected);
+        assert_eq!(variables[11], Value::Boolean(true));
+    }
+}
@@ -0,0 +1,96 @@
+use std::sync::Arc;
+
+use symphonia::core::{
+    audio::SampleBuffer, codecs::DecoderOptions, errors::Error, formats::FormatOptions,
+    io::MediaSourceStream, meta::MetadataOptions, probe::Hint,
+};
+
+use crate::project::project_main::Project;
+
+pub struct Sound {
+    pub name: String,
+    // Mono samples. Scratch plays everything in mono anyway.
+    pub samples: Arc<[f32]>,
+    pub sample_rate: u32,
+}
+
+impl Sound {
+    pub fn load(sound_json: &serde_json::Value, project: &Project<'_>) -> Result<Sound, String> {
+        let name = sound_json["name"].as_str().unwrap_or_default().to_owned();
+        let Some(file_name) = sound_json["md5ext"].as_str() else {
+            return Err(format!("JSON error: Sound {name} has no md5ext field"));
+        };
+        let (samples, sample_rate) = decode(&project.path.join(file_name))
+            .map_err(|err| format!("Failed to load sound {name}: {err}"))?;
+        Ok(Sound {
+            name,
+            samples: samples.into(),
+            sample_rate,
+        })
+    }
+
+    // Used in place of sounds that couldn't be loaded, so the other sound numbers stay right.
+    pub fn silent(name: String) -> Sound {
+        Sound {
+            name,
+            samples: Arc::new([]),
+            sample_rate: 48000,
+        }
+    }
+}
+
+// Decodes WAV (PCM or ADPCM) and MP3 files, returning mono samples and the sample rate.
+pub fn decode(path: &std::path::Path) -> Result<(Vec<f32>, u32), String> {
+    let file = std::fs::File::open(path).map_err(|err| format!("Could not open file: {err}"))?;
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|extension| extension.to_str()) {
+        hint.with_extension(extension);
+    }
+
+    let probed = symphonia::default::get_probe()
+        .format(
+            &hint,
+            stream,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .map_err(|err| format!("Unsupported format: {err}"))?;
+    let mut format = probed.format;
+    let track = format.default_track().ok_or("No audio track")?;
+    let track_id = track.id;
+    let mut sample_rate = track.codec_params.sample_rate;
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .map_err(|err| format!("Unsupported codec: {err}"))?;
+
+    let mut samples: Vec<f32> = vec![];
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            // The end of the file.
+            Err(Error::IoError(err)) if err.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(err) => return Err(format!("Could not read packet: {err}")),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // A corrupt packet, skip it like browsers do.
+            Err(Error::DecodeError(_)) => continue,
+            Err(err) => return Err(format!("Could not decode packet: {err}")),
+        };
+
+        let spec = *decoded.spec();
+        sample_rate = Some(spec.rate);
+        let channels = spec.channels.count().max(1);
+        let mut buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+        buffer.copy_interleaved_ref(decoded);
+        for frame in buffer.samples().chunks(channels) {
+            samples.push(frame.iter().sum::<f32>() / channels as f32);
+        }
+    }
+
+    Ok((samples, sample_rate.ok_or("Unknown sample rate")?))
+}
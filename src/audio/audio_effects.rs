@@ -0,0 +1,58 @@
+// A sprite's volume and sound effects. They apply to every sound the sprite plays,
+// including ones that are already playing.
+#[derive(Clone, Copy, PartialEq)]
+pub struct SoundEffects {
+    // From 0 to 100.
+    pub volume: f64,
+    // In tenths of a semitone, from -360 to 360.
+    pub pitch: f64,
+    // From -100 (left) to 100 (right).
+    pub pan: f64,
+}
+
+impl Default for SoundEffects {
+    fn default() -> Self {
+        Self {
+            volume: 100.0,
+            pitch: 0.0,
+            pan: 0.0,
+        }
+    }
+}
+
+impl SoundEffects {
+    pub fn set_effect(&mut self, effect: &str, value: f64, is_change: bool) {
+        match effect {
+            "PITCH" => {
+                let base = if is_change { self.pitch } else { 0.0 };
+                self.pitch = (base + value).clamp(-360.0, 360.0);
+            }
+            "PAN" => {
+                let base = if is_change { self.pan } else { 0.0 };
+                self.pan = (base + value).clamp(-100.0, 100.0);
+            }
+            _ => {}
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.pitch = 0.0;
+        self.pan = 0.0;
+    }
+
+    pub fn set_volume(&mut self, volume: f64) {
+        self.volume = volume.clamp(0.0, 100.0);
+    }
+
+    pub fn get_playback_rate(&self) -> f64 {
+        2.0_f64.powf(self.pitch / 10.0 / 12.0)
+    }
+
+    // Left and right gain. Panning keeps the total power the same, like Scratch.
+    pub fn get_gains(&self) -> (f32, f32) {
+        let volume = self.volume / 100.0;
+        let position = (self.pan / 100.0 + 1.0) / 2.0;
+        let angle = position * std::f64::consts::FRAC_PI_2;
+        ((angle.cos() * volume) as f32, (angle.sin() * volume) as f32)
+    }
+}
@@ -0,0 +1,247 @@
+use std::sync::{Arc, Mutex};
+
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+
+use crate::{
+    ansi_codes,
+    audio::{audio_decoder::Sound, audio_effects::SoundEffects},
+};
+
+const SAMPLE_RATE: i32 = 48000;
+
+struct Voice {
+    id: usize,
+    // The sprite that is playing this sound.
+    owner: usize,
+    samples: Arc<[f32]>,
+    // Position in the sound's samples. Fractional, because sounds
+    // are resampled to the output rate while they play.
+    position: f64,
+    // How far to move for each output sample at normal pitch.
+    base_step: f64,
+    step: f64,
+    gains: (f32, f32),
+}
+
+impl Voice {
+    fn apply_effects(&mut self, effects: &SoundEffects) {
+        self.step = self.base_step * effects.get_playback_rate();
+        self.gains = effects.get_gains();
+    }
+}
+
+#[derive(Default)]
+struct MixerState {
+    voices: Vec<Voice>,
+}
+
+impl MixerState {
+    // Fills an interleaved stereo buffer with every playing sound.
+    fn mix(&mut self, output: &mut [f32]) {
+        output.fill(0.0);
+        for voice in self.voices.iter_mut() {
+            for frame in output.chunks_mut(2) {
+                let index = voice.position as usize;
+                if index >= voice.samples.len() {
+                    break;
+                }
+                // Linear interpolation between neighbouring samples.
+                let fraction = (voice.position - index as f64) as f32;
+                let next = voice.samples.get(index + 1).copied().unwrap_or(0.0);
+                let sample = voice.samples[index] * (1.0 - fraction) + next * fraction;
+                frame[0] += sample * voice.gains.0;
+                if let Some(right) = frame.get_mut(1) {
+                    *right += sample * voice.gains.1;
+                }
+                voice.position += voice.step;
+            }
+        }
+        self.voices
+            .retain(|voice| (voice.position as usize) < voice.samples.len());
+        for sample in output.iter_mut() {
+            *sample = sample.clamp(-1.0, 1.0);
+        }
+    }
+}
+
+struct MixerCallback {
+    state: Arc<Mutex<MixerState>>,
+}
+
+impl AudioCallback for MixerCallback {
+    type Channel = f32;
+
+    fn callback(&mut self, output: &mut [f32]) {
+        self.state.lock().unwrap().mix(output);
+    }
+}
+
+pub struct AudioMixer {
+    state: Arc<Mutex<MixerState>>,
+    // None if no audio device could be opened. Sounds finish instantly then,
+    // unless they are being captured.
+    device: Option<AudioDevice<MixerCallback>>,
+    // Interleaved stereo samples, when the output goes to memory instead of a device.
+    capture: Option<Vec<f32>>,
+    // The part of a sample that didn't fit into the last captured frame.
+    capture_leftover: f64,
+    sample_rate: i32,
+    next_voice_id: usize,
+}
+
+impl AudioMixer {
+    // The driver is picked with the SDL_AUDIODRIVER hint before SDL starts,
+    // so the dummy and disk drivers can be used when there is no sound card.
+    pub fn new(sdl_context: &sdl2::Sdl, device_name: Option<&str>) -> AudioMixer {
+        let state = Arc::new(Mutex::new(MixerState::default()));
+        let device = match AudioMixer::open_device(sdl_context, device_name, &state) {
+            Ok(device) => Some(device),
+            Err(err) => {
+                eprintln!(
+                    "{}[audio]{} Could not open audio device, sounds are disabled: {err}",
+                    ansi_codes::YELLOW,
+                    ansi_codes::RESET
+                );
+                None
+            }
+        };
+        let sample_rate = match &device {
+            Some(device) => device.spec().freq,
+            None => SAMPLE_RATE,
+        };
+        AudioMixer {
+            state,
+            device,
+            capture: None,
+            capture_leftover: 0.0,
+            sample_rate,
+            next_voice_id: 0,
+        }
+    }
+
+    // Mixes into memory instead of playing anything. Time only moves forward when
+    // capture_frame is called, so the same project always captures the same audio.
+    pub fn new_capture() -> AudioMixer {
+        AudioMixer {
+            state: Arc::new(Mutex::new(MixerState::default())),
+            device: None,
+            capture: Some(vec![]),
+            capture_leftover: 0.0,
+            sample_rate: SAMPLE_RATE,
+            next_voice_id: 0,
+        }
+    }
+
+    pub fn capture_frame(&mut self, seconds: f64) {
+        let Some(capture) = &mut self.capture else {
+            return;
+        };
+        let frames = seconds * self.sample_rate as f64 + self.capture_leftover;
+        self.capture_leftover = frames.fract();
+
+        let start = capture.len();
+        capture.resize(start + frames as usize * 2, 0.0);
+        self.state.lock().unwrap().mix(&mut capture[start..]);
+    }
+
+    pub fn write_capture(&self, path: &str) -> Result<(), String> {
+        let Some(capture) = &self.capture else {
+            return Err("Audio wasn't being captured".to_owned());
+        };
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: self.sample_rate as u32,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(path, spec)
+            .map_err(|err| format!("Could not create {path}: {err}"))?;
+        for sample in capture {
+            writer
+                .write_sample((sample * i16::MAX as f32) as i16)
+                .map_err(|err| format!("Could not write {path}: {err}"))?;
+        }
+        writer
+            .finalize()
+            .map_err(|err| format!("Could not write {path}: {err}"))
+    }
+
+    fn open_device(
+        sdl_context: &sdl2::Sdl,
+        device_name: Option<&str>,
+        state: &Arc<Mutex<MixerState>>,
+    ) -> Result<AudioDevice<MixerCallback>, String> {
+        let audio_subsystem = sdl_context.audio()?;
+        let desired_spec = AudioSpecDesired {
+            freq: Some(SAMPLE_RATE),
+            channels: Some(2),
+            samples: Some(1024),
+        };
+        let device =
+            audio_subsystem.open_playback(device_name, &desired_spec, |_| MixerCallback {
+                state: state.clone(),
+            })?;
+        device.resume();
+        Ok(device)
+    }
+
+    // Starts playing a sound, returning an id to check on it with. If the same
+    // sound is already playing it starts over, like in Scratch.
+    pub fn play(&mut self, sound: &Sound, owner: usize, effects: &SoundEffects) -> usize {
+        self.state
+            .lock()
+            .unwrap()
+            .voices
+            .retain(|voice| !Arc::ptr_eq(&voice.samples, &sound.samples));
+        self.play_samples(sound.samples.clone(), sound.sample_rate, owner, effects)
+    }
+
+    // Plays samples that aren't from a sound file, like music extension notes.
+    pub fn play_samples(
+        &mut self,
+        samples: Arc<[f32]>,
+        sample_rate: u32,
+        owner: usize,
+        effects: &SoundEffects,
+    ) -> usize {
+        let id = self.next_voice_id;
+        self.next_voice_id += 1;
+        if self.device.is_none() && self.capture.is_none() {
+            return id;
+        }
+
+        let mut voice = Voice {
+            id,
+            owner,
+            samples,
+            position: 0.0,
+            base_step: sample_rate as f64 / self.sample_rate as f64,
+            step: 0.0,
+            gains: (0.0, 0.0),
+        };
+        voice.apply_effects(effects);
+        self.state.lock().unwrap().voices.push(voice);
+        id
+    }
+
+    // Updates the sounds a sprite is already playing after its effects changed.
+    pub fn set_effects(&mut self, owner: usize, effects: &SoundEffects) {
+        let mut state = self.state.lock().unwrap();
+        for voice in state.voices.iter_mut().filter(|voice| voice.owner == owner) {
+            voice.apply_effects(effects);
+        }
+    }
+
+    pub fn is_playing(&self, id: usize) -> bool {
+        self.state
+            .lock()
+            .unwrap()
+            .voices
+            .iter()
+            .any(|voice| voice.id == id)
+    }
+
+    pub fn stop_all(&mut self) {
+        self.state.lock().unwrap().voices.clear();
+    }
+}
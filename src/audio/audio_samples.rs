@@ -0,0 +1,318 @@
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
+
+use crate::ansi_codes;
+
+use super::{
+    audio_decoder::decode,
+    audio_synth::{DRUM_COUNT, INSTRUMENT_COUNT},
+};
+
+// Recorded instruments and drums for the music extension, laid out the same way
+// as the samples in Scratch's music extension (scratch3_music/assets in scratch-vm):
+//
+//     instruments/1-piano/60.mp3     a recording of a note, named after its MIDI pitch
+//     drums/1-snare.mp3
+//
+// music/fetch_samples.sh copies them into the repository's "music" directory. They
+// are looked for wherever --music-samples says, otherwise in a "music" directory
+// next to the executable and then in the current directory, so running from the
+// repository finds them. Instruments and drums without a recording are
+// synthesized by audio_synth instead.
+
+struct InstrumentSamples {
+    directory: &'static str,
+    // The notes there are recordings of. Other notes play the closest one below, sped up.
+    notes: &'static [u8],
+    // How long a note takes to fade out after it is released.
+    release: f64,
+}
+
+// In the same order as Scratch's instrument menu, with the release times Scratch uses.
+const INSTRUMENTS: [InstrumentSamples; INSTRUMENT_COUNT] = [
+    InstrumentSamples {
+        directory: "1-piano",
+        notes: &[24, 36, 48, 60, 72, 84, 96, 108],
+        release: 0.5,
+    },
+    InstrumentSamples {
+        directory: "2-electric-piano",
+        notes: &[60],
+        release: 0.5,
+    },
+    InstrumentSamples {
+        directory: "3-organ",
+        notes: &[60],
+        release: 0.5,
+    },
+    InstrumentSamples {
+        directory: "4-guitar",
+        notes: &[60],
+        release: 0.5,
+    },
+    InstrumentSamples {
+        directory: "5-electric-guitar",
+        notes: &[60],
+        release: 0.5,
+    },
+    InstrumentSamples {
+        directory: "6-bass",
+        notes: &[36, 48],
+        release: 0.25,
+    },
+    InstrumentSamples {
+        directory: "7-pizzicato",
+        notes: &[60],
+        release: 0.25,
+    },
+    InstrumentSamples {
+        directory: "8-cello",
+        notes: &[36, 48, 60],
+        release: 0.1,
+    },
+    InstrumentSamples {
+        directory: "9-trombone",
+        notes: &[36, 48, 60],
+        release: 0.01,
+    },
+    InstrumentSamples {
+        directory: "10-clarinet",
+        notes: &[48, 60],
+        release: 0.01,
+    },
+    InstrumentSamples {
+        directory: "11-saxophone",
+        notes: &[36, 60, 84],
+        release: 0.01,
+    },
+    InstrumentSamples {
+        directory: "12-flute",
+        notes: &[60, 72],
+        release: 0.01,
+    },
+    InstrumentSamples {
+        directory: "13-wooden-flute",
+        notes: &[60, 72],
+        release: 0.01,
+    },
+    InstrumentSamples {
+        directory: "14-bassoon",
+        notes: &[36, 48, 60],
+        release: 0.01,
+    },
+    InstrumentSamples {
+        directory: "15-choir",
+        notes: &[48, 60, 72],
+        release: 0.25,
+    },
+    InstrumentSamples {
+        directory: "16-vibraphone",
+        notes: &[60, 72],
+        release: 0.5,
+    },
+    InstrumentSamples {
+        directory: "17-music-box",
+        notes: &[60],
+        release: 0.25,
+    },
+    InstrumentSamples {
+        directory: "18-steel-drum",
+        notes: &[60],
+        release: 0.5,
+    },
+    InstrumentSamples {
+        directory: "19-marimba",
+        notes: &[60],
+        release: 0.01,
+    },
+    InstrumentSamples {
+        directory: "20-synth-lead",
+        notes: &[60],
+        release: 0.1,
+    },
+    InstrumentSamples {
+        directory: "21-synth-pad",
+        notes: &[60],
+        release: 0.25,
+    },
+];
+
+// In the same order as Scratch's drum menu.
+const DRUMS: [&str; DRUM_COUNT] = [
+    "1-snare",
+    "2-bass-drum",
+    "3-side-stick",
+    "4-crash-cymbal",
+    "5-open-hi-hat",
+    "6-closed-hi-hat",
+    "7-tambourine",
+    "8-hand-clap",
+    "9-claves",
+    "10-wood-block",
+    "11-cowbell",
+    "12-triangle",
+    "13-bongo",
+    "14-conga",
+    "15-cabasa",
+    "16-guiro",
+    "17-vibraslap",
+    "18-cuica",
+];
+
+type Recording = (Arc<[f32]>, u32);
+
+pub struct MusicSamples {
+    directory: Option<PathBuf>,
+    // Recordings are loaded the first time they are played. None if one couldn't be.
+    loaded: HashMap<PathBuf, Option<Recording>>,
+}
+
+impl MusicSamples {
+    pub fn new(directory: Option<PathBuf>) -> MusicSamples {
+        MusicSamples {
+            directory,
+            loaded: HashMap::new(),
+        }
+    }
+
+    // Uses the given directory, or otherwise the first "music" directory with recordings in it.
+    pub fn find(directory: Option<&str>) -> MusicSamples {
+        let directory = match directory {
+            Some(directory) => Some(PathBuf::from(directory)),
+            None => {
+                let next_to_executable = std::env::current_exe()
+                    .ok()
+                    .and_then(|executable| Some(executable.parent()?.join("music")));
+                next_to_executable
+                    .into_iter()
+                    .chain(Some(PathBuf::from("music")))
+                    .find(|directory| {
+                        directory.join("instruments").is_dir() || directory.join("drums").is_dir()
+                    })
+            }
+        };
+        MusicSamples::new(directory)
+    }
+
+    // A note held for the given number of seconds, including its release, and the sample
+    // rate to play it at. None if the instrument has to be synthesized.
+    // Instrument numbers start at 0.
+    pub fn note(&mut self, instrument: usize, note: f64, seconds: f64) -> Option<Recording> {
+        let instrument = &INSTRUMENTS[instrument % INSTRUMENT_COUNT];
+        let recorded_note = choose_recording(instrument.notes, note);
+        let path = self
+            .directory
+            .as_ref()?
+            .join("instruments")
+            .join(instrument.directory)
+            .join(recorded_note.to_string());
+        let (samples, sample_rate) = self.load(path)?;
+        let (samples, sample_rate) = play_recording(
+            &samples,
+            sample_rate,
+            note - recorded_note as f64,
+            seconds,
+            instrument.release,
+        );
+        Some((samples.into(), sample_rate))
+    }
+
+    // Drum numbers start at 0.
+    pub fn drum(&mut self, drum: usize) -> Option<Recording> {
+        let path = self
+            .directory
+            .as_ref()?
+            .join("drums")
+            .join(DRUMS[drum % DRUM_COUNT]);
+        self.load(path)
+    }
+
+    // Takes a path without an extension, Scratch's samples are MP3 but WAV works too.
+    fn load(&mut self, path: PathBuf) -> Option<Recording> {
+        if let Some(recording) = self.loaded.get(&path) {
+            return recording.clone();
+        }
+        let recording = ["mp3", "wav"]
+            .iter()
+            .map(|extension| path.with_extension(extension))
+            .find(|path| path.exists())
+            .ok_or_else(|| "No MP3 or WAV file".to_owned())
+            .and_then(|path| decode(&path));
+        let recording = match recording {
+            Ok((samples, sample_rate)) => Some((samples.into(), sample_rate)),
+            Err(err) => {
+                eprintln!(
+                    "{}[music]{} Could not load {}, synthesizing it instead: {err}",
+                    ansi_codes::YELLOW,
+                    ansi_codes::RESET,
+                    path.display()
+                );
+                None
+            }
+        };
+        self.loaded.insert(path, recording.clone());
+        recording
+    }
+}
+
+// Like Scratch, the closest recording at or below the note, or the lowest one.
+fn choose_recording(notes: &[u8], note: f64) -> u8 {
+    notes
+        .iter()
+        .rev()
+        .find(|&&recorded| note >= recorded as f64)
+        .copied()
+        .unwrap_or(notes[0])
+}
+
+// Plays a recording some semitones higher by speeding it up, cut off after the note
+// is held for long enough and faded out over the release time.
+fn play_recording(
+    samples: &[f32],
+    sample_rate: u32,
+    semitones: f64,
+    seconds: f64,
+    release: f64,
+) -> (Vec<f32>, u32) {
+    let sample_rate = sample_rate as f64 * 2.0_f64.powf(semitones / 12.0);
+    let held = (seconds * sample_rate) as usize;
+    let release = ((release * sample_rate) as usize).max(1);
+    let length = (held + release).min(samples.len());
+    let samples = samples[..length]
+        .iter()
+        .enumerate()
+        .map(|(index, sample)| match index.checked_sub(held) {
+            Some(released) => sample * (1.0 - released as f32 / release as f32),
+            None => *sample,
+        })
+        .collect();
+    (samples, sample_rate.round() as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{choose_recording, play_recording};
+
+    #[test]
+    fn notes_use_the_closest_recording_below() {
+        let piano = [24, 36, 48, 60, 72, 84, 96, 108];
+        assert_eq!(choose_recording(&piano, 60.0), 60);
+        assert_eq!(choose_recording(&piano, 71.5), 60);
+        assert_eq!(choose_recording(&piano, 130.0), 108);
+        assert_eq!(choose_recording(&piano, 0.0), 24);
+    }
+
+    #[test]
+    fn notes_are_pitched_cut_and_released() {
+        let recording = vec![1.0; 48000];
+        let (samples, sample_rate) = play_recording(&recording, 22050, 12.0, 0.5, 0.1);
+        assert_eq!(sample_rate, 44100);
+        // Half a second held, then a tenth of a second fading out.
+        assert_eq!(samples.len(), 22050 + 4410);
+        assert_eq!(samples[22049], 1.0);
+        assert!(samples[22050 + 2205] < 0.51 && samples[22050 + 2205] > 0.49);
+
+        // Recordings shorter than the note just end.
+        let (samples, _) = play_recording(&recording, 22050, 0.0, 10.0, 0.1);
+        assert_eq!(samples.len(), 48000);
+    }
+}
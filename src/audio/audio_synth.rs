@@ -0,0 +1,503 @@
+// Instruments and drums for the music extension, for when the recordings Scratch
+// plays aren't there (see audio_samples). They won't sound the same, but they keep
+// the character (plucked, blown, struck...) and the timing of the originals.
+
+pub const SYNTH_SAMPLE_RATE: u32 = 22050;
+
+pub const INSTRUMENT_COUNT: usize = 21;
+pub const DRUM_COUNT: usize = 18;
+
+enum Tone {
+    // Relative strength of the 1st, 2nd, 3rd... harmonic.
+    Harmonics(&'static [f32]),
+    // Karplus-Strong plucked string. The value is how bright the pluck is.
+    Pluck(f32),
+}
+
+struct Instrument {
+    tone: Tone,
+    attack: f32,
+    // How long it takes the note to fade to about a third while held. 0 means it doesn't fade.
+    decay: f32,
+    // How long the note takes to fade out after it is released.
+    release: f32,
+    vibrato: f32,
+}
+
+// In the same order as Scratch's instrument menu.
+const INSTRUMENTS: [Instrument; INSTRUMENT_COUNT] = [
+    // (1) Piano
+    Instrument {
+        tone: Tone::Harmonics(&[1.0, 0.5, 0.3, 0.15, 0.1, 0.05]),
+        attack: 0.002,
+        decay: 1.0,
+        release: 0.3,
+        vibrato: 0.0,
+    },
+    // (2) Electric Piano
+    Instrument {
+        tone: Tone::Harmonics(&[1.0, 0.2, 0.1, 0.3]),
+        attack: 0.002,
+        decay: 1.2,
+        release: 0.3,
+        vibrato: 0.0,
+    },
+    // (3) Organ
+    Instrument {
+        tone: Tone::Harmonics(&[1.0, 0.8, 0.6, 0.0, 0.4, 0.0, 0.0, 0.3]),
+        attack: 0.01,
+        decay: 0.0,
+        release: 0.05,
+        vibrato: 0.0,
+    },
+    // (4) Guitar
+    Instrument {
+        tone: Tone::Pluck(0.6),
+        attack: 0.0,
+        decay: 0.0,
+        release: 0.2,
+        vibrato: 0.0,
+    },
+    // (5) Electric Guitar
+    Instrument {
+        tone: Tone::Pluck(0.9),
+        attack: 0.0,
+        decay: 0.0,
+        release: 0.3,
+        vibrato: 0.0,
+    },
+    // (6) Bass
+    Instrument {
+        tone: Tone::Pluck(0.3),
+        attack: 0.0,
+        decay: 0.0,
+        release: 0.15,
+        vibrato: 0.0,
+    },
+    // (7) Pizzicato
+    Instrument {
+        tone: Tone::Pluck(0.5),
+        attack: 0.0,
+        decay: 0.3,
+        release: 0.1,
+        vibrato: 0.0,
+    },
+    // (8) Cello
+    Instrument {
+        tone: Tone::Harmonics(&[1.0, 0.7, 0.5, 0.4, 0.3, 0.2, 0.1]),
+        attack: 0.08,
+        decay: 0.0,
+        release: 0.15,
+        vibrato: 0.004,
+    },
+    // (9) Trombone
+    Instrument {
+        tone: Tone::Harmonics(&[1.0, 0.9, 0.7, 0.5, 0.3, 0.2]),
+        attack: 0.05,
+        decay: 0.0,
+        release: 0.1,
+        vibrato: 0.002,
+    },
+    // (10) Clarinet
+    Instrument {
+        tone: Tone::Harmonics(&[1.0, 0.0, 0.6, 0.0, 0.4, 0.0, 0.2]),
+        attack: 0.04,
+        decay: 0.0,
+        release: 0.1,
+        vibrato: 0.0,
+    },
+    // (11) Saxophone
+    Instrument {
+        tone: Tone::Harmonics(&[1.0, 0.8, 0.6, 0.5, 0.4, 0.3]),
+        attack: 0.04,
+        decay: 0.0,
+        release: 0.1,
+        vibrato: 0.004,
+    },
+    // (12) Flute
+    Instrument {
+        tone: Tone::Harmonics(&[1.0, 0.2, 0.05]),
+        attack: 0.06,
+        decay: 0.0,
+        release: 0.1,
+        vibrato: 0.004,
+    },
+    // (13) Wooden Flute
+    Instrument {
+        tone: Tone::Harmonics(&[1.0, 0.3, 0.1, 0.05]),
+        attack: 0.05,
+        decay: 0.0,
+        release: 0.1,
+        vibrato: 0.002,
+    },
+    // (14) Bassoon
+    Instrument {
+        tone: Tone::Harmonics(&[0.6, 1.0, 0.7, 0.5, 0.3]),
+        attack: 0.05,
+        decay: 0.0,
+        release: 0.1,
+        vibrato: 0.0,
+    },
+    // (15) Choir
+    Instrument {
+        tone: Tone::Harmonics(&[1.0, 0.5, 0.4, 0.2, 0.1]),
+        attack: 0.2,
+        decay: 0.0,
+        release: 0.3,
+        vibrato: 0.006,
+    },
+    // (16) Vibraphone
+    Instrument {
+        tone: Tone::Harmonics(&[1.0, 0.0, 0.0, 0.3]),
+        attack: 0.002,
+        decay: 1.5,
+        release: 0.5,
+        vibrato: 0.003,
+    },
+    // (17) Music Box
+    Instrument {
+        tone: Tone::Harmonics(&[1.0, 0.0, 0.2, 0.0, 0.1]),
+        attack: 0.001,
+        decay: 0.6,
+        release: 0.4,
+        vibrato: 0.0,
+    },
+    // (18) Steel Drum
+    Instrument {
+        tone: Tone::Harmonics(&[1.0, 0.6, 0.0, 0.4, 0.0, 0.2]),
+        attack: 0.002,
+        decay: 0.7,
+        release: 0.3,
+        vibrato: 0.0,
+    },
+    // (19) Marimba
+    Instrument {
+        tone: Tone::Harmonics(&[1.0, 0.0, 0.0, 0.2]),
+        attack: 0.001,
+        decay: 0.35,
+        release: 0.1,
+        vibrato: 0.0,
+    },
+    // (20) Synth Lead
+    Instrument {
+        tone: Tone::Harmonics(&[1.0, 0.5, 0.33, 0.25, 0.2, 0.17, 0.14, 0.12]),
+        attack: 0.005,
+        decay: 0.0,
+        release: 0.05,
+        vibrato: 0.0,
+    },
+    // (21) Synth Pad
+    Instrument {
+        tone: Tone::Harmonics(&[1.0, 0.4, 0.2, 0.1]),
+        attack: 0.3,
+        decay: 0.0,
+        release: 0.6,
+        vibrato: 0.005,
+    },
+];
+
+struct Drum {
+    // A sine that sweeps from the first frequency to the second, for the body of drums.
+    tone: Option<(f32, f32)>,
+    // Inharmonic partials for metal, wood and glass sounds.
+    partials: &'static [f32],
+    noise: f32,
+    // 0 is dull, 1 is as hissy as it gets.
+    noise_brightness: f32,
+    decay: f32,
+    // Hits in a row, for hand claps and the like.
+    hits: u32,
+}
+
+// In the same order as Scratch's drum menu.
+const DRUMS: [Drum; DRUM_COUNT] = [
+    // (1) Snare Drum
+    Drum {
+        tone: Some((220.0, 160.0)),
+        partials: &[],
+        noise: 0.8,
+        noise_brightness: 0.7,
+        decay: 0.15,
+        hits: 1,
+    },
+    // (2) Bass Drum
+    Drum {
+        tone: Some((120.0, 45.0)),
+        partials: &[],
+        noise: 0.05,
+        noise_brightness: 0.2,
+        decay: 0.3,
+        hits: 1,
+    },
+    // (3) Side Stick
+    Drum {
+        tone: Some((800.0, 600.0)),
+        partials: &[1650.0],
+        noise: 0.3,
+        noise_brightness: 0.8,
+        decay: 0.04,
+        hits: 1,
+    },
+    // (4) Crash Cymbal
+    Drum {
+        tone: None,
+        partials: &[3100.0, 4270.0, 5800.0],
+        noise: 1.0,
+        noise_brightness: 1.0,
+        decay: 1.2,
+        hits: 1,
+    },
+    // (5) Open Hi-Hat
+    Drum {
+        tone: None,
+        partials: &[6000.0, 8300.0],
+        noise: 1.0,
+        noise_brightness: 1.0,
+        decay: 0.4,
+        hits: 1,
+    },
+    // (6) Closed Hi-Hat
+    Drum {
+        tone: None,
+        partials: &[6000.0, 8300.0],
+        noise: 1.0,
+        noise_brightness: 1.0,
+        decay: 0.06,
+        hits: 1,
+    },
+    // (7) Tambourine
+    Drum {
+        tone: None,
+        partials: &[5200.0, 7100.0, 9100.0],
+        noise: 0.8,
+        noise_brightness: 1.0,
+        decay: 0.25,
+        hits: 1,
+    },
+    // (8) Hand Clap
+    Drum {
+        tone: None,
+        partials: &[],
+        noise: 1.0,
+        noise_brightness: 0.6,
+        decay: 0.08,
+        hits: 3,
+    },
+    // (9) Claves
+    Drum {
+        tone: None,
+        partials: &[2500.0],
+        noise: 0.0,
+        noise_brightness: 0.0,
+        decay: 0.06,
+        hits: 1,
+    },
+    // (10) Wood Block
+    Drum {
+        tone: None,
+        partials: &[1100.0, 2900.0],
+        noise: 0.05,
+        noise_brightness: 0.5,
+        decay: 0.07,
+        hits: 1,
+    },
+    // (11) Cowbell
+    Drum {
+        tone: None,
+        partials: &[560.0, 845.0],
+        noise: 0.0,
+        noise_brightness: 0.0,
+        decay: 0.3,
+        hits: 1,
+    },
+    // (12) Triangle
+    Drum {
+        tone: None,
+        partials: &[4200.0, 6300.0, 9500.0],
+        noise: 0.0,
+        noise_brightness: 0.0,
+        decay: 1.5,
+        hits: 1,
+    },
+    // (13) Bongo
+    Drum {
+        tone: Some((420.0, 380.0)),
+        partials: &[],
+        noise: 0.05,
+        noise_brightness: 0.3,
+        decay: 0.12,
+        hits: 1,
+    },
+    // (14) Conga
+    Drum {
+        tone: Some((260.0, 230.0)),
+        partials: &[],
+        noise: 0.05,
+        noise_brightness: 0.3,
+        decay: 0.2,
+        hits: 1,
+    },
+    // (15) Cabasa
+    Drum {
+        tone: None,
+        partials: &[],
+        noise: 0.7,
+        noise_brightness: 1.0,
+        decay: 0.1,
+        hits: 1,
+    },
+    // (16) Guiro
+    Drum {
+        tone: None,
+        partials: &[],
+        noise: 0.7,
+        noise_brightness: 0.6,
+        decay: 0.05,
+        hits: 6,
+    },
+    // (17) Vibraslap
+    Drum {
+        tone: None,
+        partials: &[900.0, 1350.0],
+        noise: 0.2,
+        noise_brightness: 0.8,
+        decay: 0.06,
+        hits: 10,
+    },
+    // (18) Cuica
+    Drum {
+        tone: Some((500.0, 900.0)),
+        partials: &[],
+        noise: 0.0,
+        noise_brightness: 0.0,
+        decay: 0.3,
+        hits: 1,
+    },
+];
+
+// Renders a note held for the given number of seconds, including its release.
+// Instrument numbers start at 0.
+pub fn render_note(instrument: usize, note: f64, seconds: f64) -> Vec<f32> {
+    let instrument = &INSTRUMENTS[instrument % INSTRUMENT_COUNT];
+    let frequency = 440.0 * 2.0_f32.powf((note as f32 - 69.0) / 12.0);
+    let held = (seconds as f32 * SYNTH_SAMPLE_RATE as f32) as usize;
+    let length = held + (instrument.release * SYNTH_SAMPLE_RATE as f32) as usize;
+
+    let mut samples = match instrument.tone {
+        Tone::Harmonics(harmonics) => {
+            render_harmonics(harmonics, frequency, instrument.vibrato, length)
+        }
+        Tone::Pluck(brightness) => render_pluck(frequency, brightness, length),
+    };
+
+    for (index, sample) in samples.iter_mut().enumerate() {
+        let time = index as f32 / SYNTH_SAMPLE_RATE as f32;
+        let mut gain = if time < instrument.attack {
+            time / instrument.attack
+        } else {
+            1.0
+        };
+        if instrument.decay > 0.0 {
+            gain *= (-time / instrument.decay).exp();
+        }
+        if index >= held {
+            let released = (index - held) as f32 / SYNTH_SAMPLE_RATE as f32;
+            gain *= (1.0 - released / instrument.release).max(0.0);
+        }
+        *sample *= gain * 0.5;
+    }
+    samples
+}
+
+fn render_harmonics(harmonics: &[f32], frequency: f32, vibrato: f32, length: usize) -> Vec<f32> {
+    let total: f32 = harmonics.iter().sum();
+    let mut phase = 0.0_f32;
+    (0..length)
+        .map(|index| {
+            let time = index as f32 / SYNTH_SAMPLE_RATE as f32;
+            let wobble = 1.0 + vibrato * (time * 5.5 * std::f32::consts::TAU).sin();
+            phase += frequency * wobble / SYNTH_SAMPLE_RATE as f32;
+            let sample: f32 = harmonics
+                .iter()
+                .enumerate()
+                .map(|(harmonic, strength)| {
+                    strength * (phase * (harmonic + 1) as f32 * std::f32::consts::TAU).sin()
+                })
+                .sum();
+            sample / total
+        })
+        .collect()
+}
+
+fn render_pluck(frequency: f32, brightness: f32, length: usize) -> Vec<f32> {
+    let period = ((SYNTH_SAMPLE_RATE as f32 / frequency) as usize).max(2);
+    let mut noise = Noise::new();
+    let mut delay_line: Vec<f32> = (0..period).map(|_| noise.next()).collect();
+    // Duller strings get a softer pluck.
+    for index in 1..period {
+        delay_line[index] =
+            delay_line[index] * brightness + delay_line[index - 1] * (1.0 - brightness);
+    }
+
+    let mut samples = Vec::with_capacity(length);
+    for index in 0..length {
+        let current = index % period;
+        let next = (index + 1) % period;
+        let sample = delay_line[current];
+        delay_line[current] = (sample + delay_line[next]) * 0.5 * 0.996;
+        samples.push(sample);
+    }
+    samples
+}
+
+// Drum numbers start at 0.
+pub fn render_drum(drum: usize) -> Vec<f32> {
+    let drum = &DRUMS[drum % DRUM_COUNT];
+    let hit_length = (drum.decay * 5.0 * SYNTH_SAMPLE_RATE as f32) as usize;
+    let hit_gap = (0.012 * SYNTH_SAMPLE_RATE as f32) as usize;
+    let length = hit_length + hit_gap * (drum.hits as usize - 1);
+
+    let mut samples = vec![0.0; length];
+    let mut noise = Noise::new();
+    let mut filtered_noise = 0.0;
+    for hit in 0..drum.hits as usize {
+        let mut phase = 0.0_f32;
+        for index in 0..hit_length {
+            let time = index as f32 / SYNTH_SAMPLE_RATE as f32;
+            let envelope = (-time / drum.decay).exp();
+            let mut sample = 0.0;
+
+            if let Some((start, end)) = drum.tone {
+                let sweep = (-time / (drum.decay * 0.5)).exp();
+                phase += (end + (start - end) * sweep) / SYNTH_SAMPLE_RATE as f32;
+                sample += (phase * std::f32::consts::TAU).sin();
+            }
+            for partial in drum.partials {
+                sample += (time * partial * std::f32::consts::TAU).sin() * 0.5;
+            }
+            // A one pole low pass filter, so dull noise can sound like a thud.
+            let smoothing = 0.05 + 0.95 * drum.noise_brightness;
+            filtered_noise += (noise.next() - filtered_noise) * smoothing;
+            sample += filtered_noise * drum.noise;
+
+            samples[hit * hit_gap + index] += sample * envelope * 0.4;
+        }
+    }
+    samples
+}
+
+// A tiny xorshift generator. The same noise every time is fine for instruments.
+struct Noise(u32);
+
+impl Noise {
+    fn new() -> Noise {
+        Noise(0x9e3779b9)
+    }
+
+    fn next(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0 as f32 / u32::MAX as f32 * 2.0 - 1.0
+    }
+}
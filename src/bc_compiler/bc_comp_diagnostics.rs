@@ -0,0 +1,235 @@
+use serde::{Deserialize, Serialize};
+
+use crate::ansi_codes;
+
+// What to do about blocks the compiler doesn't support. They are compiled
+// as no-ops either way, this only decides whether the project still runs.
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum UnsupportedBlocks {
+    Fail,
+    #[default]
+    Warn,
+    // Leave them out without saying anything.
+    NoOp,
+}
+
+impl UnsupportedBlocks {
+    pub fn parse(value: &str) -> Option<UnsupportedBlocks> {
+        match value {
+            "fail" => Some(UnsupportedBlocks::Fail),
+            "warn" => Some(UnsupportedBlocks::Warn),
+            "no-op" => Some(UnsupportedBlocks::NoOp),
+            _ => None,
+        }
+    }
+}
+
+// Set from the command line.
+#[derive(Clone, Default)]
+pub struct ReportOptions {
+    pub unsupported_blocks: UnsupportedBlocks,
+    // Also save the report here as JSON.
+    pub json_path: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiagnosticKind {
+    // A block, hat block or kind of input the compiler doesn't know.
+    UnsupportedBlock,
+    // A block refers to another block, input or variable that isn't there.
+    MissingInput,
+    // Something in the project isn't shaped the way Scratch saves it.
+    BadJson,
+    // An input left empty where Scratch fills in a default, which is usually a mistake.
+    EmptyInput,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+// A problem with one block, found while compiling a project.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    pub sprite: String,
+    pub block_id: Option<String>,
+    pub opcode: Option<String>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    // None if it isn't worth mentioning.
+    pub fn severity(&self, policy: UnsupportedBlocks) -> Option<Severity> {
+        match (self.kind, policy) {
+            (DiagnosticKind::UnsupportedBlock, UnsupportedBlocks::Warn) => Some(Severity::Warning),
+            (DiagnosticKind::UnsupportedBlock, UnsupportedBlocks::NoOp) => None,
+            // The project still does what it does in Scratch, so it never fails.
+            (DiagnosticKind::EmptyInput, UnsupportedBlocks::NoOp) => None,
+            (DiagnosticKind::EmptyInput, _) => Some(Severity::Warning),
+            _ => Some(Severity::Error),
+        }
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "sprite {:?}", self.sprite)?;
+        if let Some(block_id) = &self.block_id {
+            write!(f, ", block {block_id:?}")?;
+        }
+        if let Some(opcode) = &self.opcode {
+            write!(f, " ({opcode})")?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+// Everything found while compiling a project. It is saved in the cache with
+// the scripts, so the same problems come up again when it is loaded from there.
+#[derive(Default, Serialize, Deserialize)]
+pub struct CompileReport {
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl CompileReport {
+    // For problems that aren't in a block, like a sprite missing one of its fields.
+    pub fn add(&mut self, kind: DiagnosticKind, sprite: &str, message: String) {
+        self.diagnostics.push(Diagnostic {
+            kind,
+            sprite: sprite.to_owned(),
+            block_id: None,
+            opcode: None,
+            message,
+        });
+    }
+
+    fn count(&self, policy: UnsupportedBlocks, severity: Severity) -> usize {
+        self.diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.severity(policy) == Some(severity))
+            .count()
+    }
+
+    pub fn to_json(&self, policy: UnsupportedBlocks) -> String {
+        let diagnostics: Vec<serde_json::Value> = self
+            .diagnostics
+            .iter()
+            .filter_map(|diagnostic| {
+                let severity = match diagnostic.severity(policy)? {
+                    Severity::Warning => "warning",
+                    Severity::Error => "error",
+                };
+                Some(serde_json::json!({
+                    "severity": severity,
+                    "kind": diagnostic.kind,
+                    "sprite": diagnostic.sprite,
+                    "block_id": diagnostic.block_id,
+                    "opcode": diagnostic.opcode,
+                    "message": diagnostic.message,
+                }))
+            })
+            .collect();
+        let report = serde_json::json!({
+            "errors": self.count(policy, Severity::Error),
+            "warnings": self.count(policy, Severity::Warning),
+            "diagnostics": diagnostics,
+        });
+        serde_json::to_string_pretty(&report).unwrap()
+    }
+
+    // Prints the report, saves it if asked to, and gives an error if the project shouldn't run.
+    pub fn show(&self, options: &ReportOptions) -> Result<(), String> {
+        let policy = options.unsupported_blocks;
+        for diagnostic in self.diagnostics.iter() {
+            match diagnostic.severity(policy) {
+                Some(Severity::Warning) => eprintln!(
+                    "{}[warning]{} {diagnostic}",
+                    ansi_codes::YELLOW,
+                    ansi_codes::RESET
+                ),
+                Some(Severity::Error) => {
+                    eprintln!(
+                        "{}[error]{} {diagnostic}",
+                        ansi_codes::RED,
+                        ansi_codes::RESET
+                    )
+                }
+                None => {}
+            }
+        }
+        if let Some(path) = &options.json_path {
+            std::fs::write(path, self.to_json(policy))
+                .map_err(|err| format!("Could not write {path}: {err}"))?;
+        }
+        match self.count(policy, Severity::Error) {
+            0 => Ok(()),
+            errors => Err(format!(
+                "Found {errors} problems while compiling the project"
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::DiagnosticKind;
+    use crate::project::project_main::Project;
+
+    #[test]
+    fn broken_blocks_are_reported() {
+        let json = json!({ "targets": [
+            { "name": "Sprite1", "isStage": false, "variables": {}, "lists": {}, "blocks": {
+                "flag": { "opcode": "event_whenflagclicked", "parent": null, "next": "set" },
+                "set": { "opcode": "data_setvariableto", "parent": "flag", "next": "of",
+                         "inputs": { "VALUE": [1, [10, "1"]] }, "fields": { "VARIABLE": ["x", "gone"] } },
+                "of": { "opcode": "sensing_of", "parent": "set", "next": "mathop", "inputs": {}, "fields": {} },
+                "mathop": { "opcode": "operator_mathop", "next": null, "parent": "of",
+                            "inputs": {}, "fields": { "OPERATOR": [] } },
+            } },
+            { "name": "Sprite2", "isStage": false, "variables": {}, "lists": {} },
+        ] });
+        let (_, _, report) = Project::compile_scripts(&json, false).unwrap();
+        let found: Vec<_> = report
+            .diagnostics
+            .iter()
+            .map(|diagnostic| {
+                (
+                    diagnostic.kind,
+                    diagnostic.sprite.as_str(),
+                    diagnostic.block_id.as_deref(),
+                    diagnostic.opcode.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    DiagnosticKind::MissingInput,
+                    "Sprite1",
+                    Some("set"),
+                    Some("data_setvariableto")
+                ),
+                (
+                    DiagnosticKind::BadJson,
+                    "Sprite1",
+                    Some("of"),
+                    Some("sensing_of")
+                ),
+                (
+                    DiagnosticKind::BadJson,
+                    "Sprite1",
+                    Some("mathop"),
+                    Some("operator_mathop")
+                ),
+                (DiagnosticKind::BadJson, "Sprite2", None, None),
+            ]
+        );
+    }
+}
@@ -1,15 +1,33 @@
-use crate::{ansi_codes, interpreter::Instruction};
+use crate::{
+    ansi_codes,
+    blocks::block_procedures::ProcedureCall,
+    interpreter::{Instruction, Value},
+};
 
-use super::bc_comp_variable_manager::VariableCompiler;
+use super::{
+    bc_comp_diagnostics::{Diagnostic, DiagnosticKind},
+    bc_comp_variable_manager::VariableCompiler,
+};
 
 pub struct ThreadCompiler<'a> {
     pub variables: &'a mut VariableCompiler,
     pub instructions: &'a mut Vec<Instruction>,
-    pub jump_counter: i64,
-    pub if_jump_number: i64,
+    // Numbers the labels made by new_label, so every label in a thread is different.
+    label_counter: usize,
     pub temp_variables: Vec<bool>,
+    // Which registers can be read before anything is written to them, once
+    // optimize_split_registers has worked it out. Empty until then.
+    pub read_before_written: Vec<bool>,
     pub thread_number: usize,
     pub sprite: &'a serde_json::Value,
+    // Every sprite in the project, for blocks that look at other sprites.
+    pub targets: &'a [serde_json::Value],
+    // Problems with blocks in this thread, reported once the whole project is compiled.
+    pub diagnostics: Vec<Diagnostic>,
+    // Set while compiling the inside of a custom block that runs without screen refresh.
+    pub warp: bool,
+    // The custom blocks being inlined, innermost last.
+    pub procedure_calls: Vec<ProcedureCall>,
 }
 
 impl<'a> ThreadCompiler<'a> {
@@ -18,21 +36,36 @@ impl<'a> ThreadCompiler<'a> {
         instructions: &'a mut Vec<Instruction>,
         thread_number: usize,
         sprite: &'a serde_json::Value,
+        targets: &'a [serde_json::Value],
     ) -> ThreadCompiler<'a> {
         ThreadCompiler {
             variables,
             instructions,
-            jump_counter: 0,
-            if_jump_number: 0,
+            label_counter: 0,
             temp_variables: vec![],
+            read_before_written: vec![],
             thread_number,
             sprite,
+            targets,
+            diagnostics: Vec::new(),
+            warp: false,
+            procedure_calls: Vec::new(),
         }
     }
     pub fn compile_block(&mut self, current_block: &serde_json::Value) -> Option<usize> {
-        let opcode = current_block["opcode"].as_str().unwrap();
+        let Some(opcode) = current_block["opcode"].as_str() else {
+            self.report(
+                current_block,
+                DiagnosticKind::BadJson,
+                "Block has no opcode".to_owned(),
+            );
+            return None;
+        };
         match opcode {
             "data_setvariableto" => self.c_variables_set(current_block),
+            "data_changevariableby" => self.c_variables_change(current_block),
+            "data_showvariable" => self.c_variables_show(current_block),
+            "data_hidevariable" => self.c_variables_hide(current_block),
             "operator_add" => self.c_operators_add(current_block),
             "operator_subtract" => self.c_operators_subtract(current_block),
             "operator_multiply" => self.c_operators_multiply(current_block),
@@ -44,6 +77,7 @@ impl<'a> ThreadCompiler<'a> {
             "operator_mathop" => self.c_operators_mathop(current_block),
             "control_forever" => self.c_control_forever(current_block),
             "control_if" => self.c_control_if(current_block),
+            "control_if_else" => self.c_control_if_else(current_block),
             "control_repeat" => self.c_control_repeat(current_block),
             "control_repeat_until" => self.c_control_repeat_until(current_block),
             "motion_gotoxy" => self.c_motion_go_to(current_block),
@@ -64,46 +98,118 @@ impl<'a> ThreadCompiler<'a> {
             "pen_penUp" => self.c_pen_up(),
             "pen_penDown" => self.c_pen_down(),
             "pen_setPenSizeTo" => self.c_pen_set_size(current_block),
+            "pen_changePenSizeBy" => self.c_pen_change_size(current_block),
+            "pen_setPenColorToColor" => self.c_pen_set_color(current_block),
+            "pen_setPenColorParamTo" => self.c_pen_set_color_param(current_block),
+            "pen_changePenColorParamBy" => self.c_pen_change_color_param(current_block),
+            "pen_setPenHueToNumber" => self.c_pen_set_hue(current_block),
+            "pen_setPenShadeToNumber" => self.c_pen_set_shade(current_block),
+            "sound_play" => self.c_sound_play(current_block),
+            "sound_playuntildone" => self.c_sound_play_until_done(current_block),
+            "sound_stopallsounds" => self.c_sound_stop_all(),
+            "sound_seteffectto" => self.c_sound_set_effect(current_block),
+            "sound_changeeffectby" => self.c_sound_change_effect(current_block),
+            "sound_cleareffects" => self.c_sound_clear_effects(),
+            "sound_setvolumeto" => self.c_sound_set_volume(current_block),
+            "sound_changevolumeby" => self.c_sound_change_volume(current_block),
+            "sound_volume" => self.c_sound_get_volume(),
+            "music_playDrumForBeats" => self.c_music_play_drum(current_block),
+            "music_playNoteForBeats" => self.c_music_play_note(current_block),
+            "music_restForBeats" => self.c_music_rest(current_block),
+            "music_setTempo" => self.c_music_set_tempo(current_block),
+            "music_changeTempo" => self.c_music_change_tempo(current_block),
+            "music_getTempo" => self.c_music_get_tempo(),
+            "music_setInstrument" => self.c_music_set_instrument(current_block),
             "sensing_timer" => self.c_sensing_timer(),
+            "sensing_askandwait" => self.c_sensing_ask_and_wait(current_block),
+            "sensing_answer" => self.c_sensing_answer(),
+            "sensing_resettimer" => self.c_sensing_reset_timer(),
+            "sensing_distanceto" => self.c_sensing_distance_to(current_block),
+            "sensing_of" => self.c_sensing_of(current_block),
+            "sensing_current" => self.c_sensing_current(current_block),
+            "sensing_dayssince2000" => self.c_sensing_days_since_2000(),
+            "sensing_username" => self.c_sensing_username(),
+            "sensing_loudness" => self.c_sensing_loudness(),
+            "sensing_setdragmode" => self.c_sensing_set_drag_mode(),
+            "procedures_call" => self.c_procedures_call(current_block),
+            "argument_reporter_string_number" | "argument_reporter_boolean" => {
+                self.c_argument_reporter(current_block)
+            }
             _ => {
-                eprintln!(
-                    "{}[unimplemented block]{} {opcode}",
-                    ansi_codes::RED,
-                    ansi_codes::RESET
+                self.report(
+                    current_block,
+                    DiagnosticKind::UnsupportedBlock,
+                    "Unsupported block".to_owned(),
                 );
                 None
             }
         }
     }
 
+    // Records a problem with a block, which is left out or compiled as far as it can be.
+    pub fn report(&mut self, block: &serde_json::Value, kind: DiagnosticKind, message: String) {
+        // Blocks don't hold their own ID, so it is looked up. Only done when something is wrong.
+        let block_id = self.sprite["blocks"].as_object().and_then(|blocks| {
+            blocks
+                .iter()
+                .find(|(_, block_data)| *block_data == block)
+                .map(|(block_id, _)| block_id.clone())
+        });
+        self.diagnostics.push(Diagnostic {
+            kind,
+            sprite: self.sprite["name"].as_str().unwrap_or_default().to_owned(),
+            block_id,
+            opcode: block["opcode"].as_str().map(str::to_owned),
+            message,
+        });
+    }
+
     pub fn compile_substack(&mut self, current_block: &serde_json::Value) {
-        let block_input = &current_block["inputs"]["SUBSTACK"];
+        self.compile_substack_input(current_block, "SUBSTACK");
+    }
+
+    // For blocks with more than one substack, like the else part of if/else.
+    pub fn compile_substack_input(&mut self, current_block: &serde_json::Value, input: &str) {
+        let block_input = &current_block["inputs"][input];
         if let serde_json::Value::Null = block_input {
             return;
         }
-        let block_id = match block_input.as_array().unwrap()[1] {
-            serde_json::Value::Null => return,
-            serde_json::Value::String(ref id) => id.as_str(),
-            _ => panic!("Weird json in SUBSTACK"),
+        let block_id = match block_input.as_array().and_then(|input| input.get(1)) {
+            Some(serde_json::Value::Null) => return,
+            Some(serde_json::Value::String(id)) => id.as_str(),
+            _ => {
+                self.report(
+                    current_block,
+                    DiagnosticKind::BadJson,
+                    format!("{input} input should name a block, not {block_input}"),
+                );
+                return;
+            }
         };
-        let mut block = self.get_block(block_id).unwrap();
-
-        self.compile_block(&block);
+        self.compile_stack(current_block, block_id);
+    }
 
-        while block["next"] != serde_json::Value::Null {
-            let next = block["next"].as_str().unwrap();
-            block = self.get_block(next).unwrap();
+    // Compiles a block and every block after it, given the block they hang off.
+    pub fn compile_stack(&mut self, parent: &serde_json::Value, first_block_id: &str) {
+        let mut parent = parent.clone();
+        let mut block_id = Some(first_block_id.to_owned());
+        while let Some(id) = block_id {
+            let Some(block) = self.get_block(&id) else {
+                self.report(
+                    &parent,
+                    DiagnosticKind::MissingInput,
+                    format!("Block {id:?} after this one doesn't exist"),
+                );
+                return;
+            };
             self.compile_block(&block);
+            block_id = block["next"].as_str().map(str::to_owned);
+            parent = block;
         }
     }
 
     pub fn get_block(&self, next: &str) -> Option<serde_json::Value> {
-        for (block_id, block_data) in self.sprite["blocks"].as_object().unwrap() {
-            if block_id == next {
-                return Some(block_data.clone());
-            }
-        }
-        None
+        self.sprite["blocks"].get(next).cloned()
     }
 
     pub fn dump(&self) {
@@ -121,20 +227,21 @@ impl<'a> ThreadCompiler<'a> {
         );
         for (index, variable) in self.temp_variables.iter().enumerate() {
             if *variable {
-                println!("    thread{}tempvar{}", self.thread_number, index,);
+                println!("    r{index}");
             }
         }
         println!("}}");
-        println!(
-            "{}[instruction dump]{} {{",
-            ansi_codes::GREEN,
-            ansi_codes::RESET
-        );
-        for instruction in self.instructions.iter() {
+        self.dump_instructions("instruction dump");
+    }
+
+    pub fn dump_instructions(&self, title: &str) {
+        println!("{}[{title}]{} {{", ansi_codes::GREEN, ansi_codes::RESET);
+        // Numbered, since jumps point at instruction indices.
+        for (index, instruction) in self.instructions.iter().enumerate() {
             println!(
-                "    {}{}{}",
+                "    {index:>4} {}{}{}",
                 ansi_codes::WHITE,
-                instruction.print(Some(&self.variables)),
+                instruction.print(Some(self.variables)),
                 ansi_codes::RESET
             );
         }
@@ -142,22 +249,116 @@ impl<'a> ThreadCompiler<'a> {
     }
 
     pub fn get_input_bool(&mut self, current_block: &serde_json::Value) -> Option<usize> {
-        let condition = self
-            .get_block(
-                current_block["inputs"]["CONDITION"].as_array().unwrap()[1]
-                    .as_str()
-                    .unwrap(),
-            )
-            .unwrap();
+        let condition_id = current_block["inputs"]["CONDITION"]
+            .as_array()
+            .and_then(|input| input.get(1))
+            .and_then(|id| id.as_str());
+        let Some(condition_id) = condition_id else {
+            self.report(
+                current_block,
+                DiagnosticKind::UnsupportedBlock,
+                "Empty conditions aren't supported".to_owned(),
+            );
+            return None;
+        };
+        let Some(condition) = self.get_block(condition_id) else {
+            self.report(
+                current_block,
+                DiagnosticKind::MissingInput,
+                format!("Condition block {condition_id:?} doesn't exist"),
+            );
+            return None;
+        };
         self.compile_block(&condition)
     }
 
+    // Menu inputs hold a shadow block with the chosen option in a field,
+    // unless a reporter has been dropped on top of them.
+    pub fn get_menu_input(
+        &mut self,
+        current_block: &serde_json::Value,
+        input: &str,
+        field: &str,
+    ) -> (Value, Option<usize>) {
+        let menu_block = current_block["inputs"][input]
+            .as_array()
+            .and_then(|input| input.get(1))
+            .and_then(|id| id.as_str())
+            .and_then(|id| self.get_block(id));
+        let Some(menu_block) = menu_block else {
+            self.report(
+                current_block,
+                DiagnosticKind::MissingInput,
+                format!("Menu input {input} is missing"),
+            );
+            return (Value::String(String::new()), None);
+        };
+        if menu_block["fields"][field].is_array() {
+            let option = self.get_field(&menu_block, field).unwrap_or_default();
+            return (Value::String(option), None);
+        }
+        match self.compile_block(&menu_block) {
+            Some(register) => (Value::Register(register), Some(register)),
+            None => (Value::String(String::new()), None),
+        }
+    }
+
+    // The option chosen in a dropdown that is part of the block itself.
+    pub fn get_field(&mut self, current_block: &serde_json::Value, field: &str) -> Option<String> {
+        let option = current_block["fields"][field]
+            .as_array()
+            .and_then(|option| option.first())
+            .and_then(|option| option.as_str());
+        let Some(option) = option else {
+            self.report(
+                current_block,
+                DiagnosticKind::BadJson,
+                format!("Field {field} has no option"),
+            );
+            return None;
+        };
+        Some(option.to_owned())
+    }
+
+    // The variable the variable blocks work on, as a pointer.
+    pub fn get_variable_field(&mut self, current_block: &serde_json::Value) -> Option<usize> {
+        let id = current_block["fields"]["VARIABLE"]
+            .as_array()
+            .and_then(|variable| variable.get(1))
+            .and_then(|id| id.as_str());
+        let Some(id) = id else {
+            self.report(
+                current_block,
+                DiagnosticKind::BadJson,
+                "Field VARIABLE has no ID".to_owned(),
+            );
+            return None;
+        };
+        let pointer = self.variables.get_id(id);
+        if pointer.is_none() {
+            self.report(
+                current_block,
+                DiagnosticKind::MissingInput,
+                format!("Variable {id:?} doesn't exist"),
+            );
+        }
+        pointer
+    }
+
     pub fn finish(&mut self) {
         self.instructions.push(Instruction::ThreadKill)
     }
 
+    // A label no other block in this thread uses, for FlowDefinePlace and jumps to it.
+    pub fn new_label(&mut self, name: &str) -> String {
+        self.label_counter += 1;
+        format!("{name}{}", self.label_counter)
+    }
+
     pub fn pause(&mut self) {
-        self.instructions.push(Instruction::ThreadPause);
-        self.instructions.push(Instruction::ThreadPause);
+        self.instructions.push(match self.warp {
+            true => Instruction::ThreadWarpPause,
+            false => Instruction::ThreadPause,
+        });
     }
 }
@@ -1,11 +1,221 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::interpreter::{Instruction, Memory, Operand, Value};
+
 use super::bc_comp_main::ThreadCompiler;
 
 impl<'a> ThreadCompiler<'a> {
-    pub fn optimize(&mut self) {
-        self.optimize_flatten_places()
+    pub fn optimize(&mut self) -> Result<(), String> {
+        self.verify_places()?;
+
+        self.optimize_propagate_values();
+        self.optimize_forward_results();
+        self.optimize_dead_stores();
+        self.optimize_split_registers();
+        self.optimize_specialize_types();
+        self.optimize_pauses();
+
+        self.optimize_flatten_places();
+        self.verify_jumps()
+    }
+
+    // Links places into instruction indices without optimizing anything,
+    // for bytecode that was written by hand.
+    #[cfg(any(feature = "tools", test))]
+    pub fn link(&mut self) -> Result<(), String> {
+        self.verify_places()?;
+        self.optimize_flatten_places();
+        self.verify_jumps()
+    }
+
+    // Copy propagation and constant folding. Reads of a register are replaced with
+    // whatever was last stored in it, and operators with only constant inputs are worked out.
+    // Only straight line code is tracked, everything is forgotten at a place jumps can go to.
+    fn optimize_propagate_values(&mut self) {
+        let mut known: HashMap<usize, Value> = HashMap::new();
+        for instruction in self.instructions.iter_mut() {
+            if let Instruction::FlowDefinePlace(_) = instruction {
+                known.clear();
+                continue;
+            }
+
+            let mut written = None;
+            let mut all_constant = true;
+            for (operand, value) in instruction.operands_mut() {
+                match operand {
+                    Operand::Read => {
+                        if let Value::Register(register) = value {
+                            if let Some(known_value) = known.get(register) {
+                                *value = known_value.clone();
+                            }
+                        }
+                        all_constant &= !matches!(
+                            value,
+                            Value::Pointer(_) | Value::Register(_) | Value::List(_)
+                        );
+                    }
+                    Operand::Write => written = Some(value.clone()),
+                    Operand::Variable => {}
+                }
+            }
+            if all_constant {
+                if let Some((location, result)) = instruction.evaluate_operator(&Memory::empty()) {
+                    *instruction = Instruction::MemoryStore(location.clone(), result);
+                }
+            }
+
+            if let Some(written) = written {
+                known.retain(|&register, value| {
+                    written != Value::Register(register) && *value != written
+                });
+                if let (Instruction::MemoryStore(_, value), Value::Register(register)) =
+                    (&*instruction, &written)
+                {
+                    if *value != written {
+                        known.insert(*register, value.clone());
+                    }
+                }
+            }
+            // Other threads can change variables while this one waits.
+            if instruction.may_yield() {
+                known.retain(|_, value| !matches!(value, Value::Pointer(_)));
+            }
+        }
     }
 
+    // An instruction writing to a register that is straight away copied somewhere
+    // else and never read again can write to the final place itself.
+    fn optimize_forward_results(&mut self) {
+        let live_after = self.registers_live_after();
+        let mut removed = vec![false; self.instructions.len()];
+        for index in 1..self.instructions.len() {
+            let Instruction::MemoryStore(target, Value::Register(register)) =
+                &self.instructions[index]
+            else {
+                continue;
+            };
+            let (target, register) = (target.clone(), *register);
+            if removed[index - 1]
+                || target == Value::Register(register)
+                || live_after[index].contains(&register)
+            {
+                continue;
+            }
+            for (operand, value) in self.instructions[index - 1].operands_mut() {
+                if operand == Operand::Write && *value == Value::Register(register) {
+                    *value = target.clone();
+                    removed[index] = true;
+                }
+            }
+        }
+        let mut removed = removed.into_iter();
+        self.instructions.retain(|_| !removed.next().unwrap());
+    }
+
+    // Removes writes to registers that are never read afterwards. Anything with an
+    // output has no other side effects, so the whole instruction can go.
+    fn optimize_dead_stores(&mut self) {
+        loop {
+            let live_after = self.registers_live_after();
+            let mut dead = Vec::with_capacity(self.instructions.len());
+            for (instruction, live) in self.instructions.iter_mut().zip(live_after.iter()) {
+                let written = instruction
+                    .operands_mut()
+                    .into_iter()
+                    .find(|(operand, _)| *operand == Operand::Write)
+                    .map(|(_, value)| value.clone());
+                dead.push(matches!(written, Some(Value::Register(n)) if !live.contains(&n)));
+            }
+            if !dead.contains(&true) {
+                break;
+            }
+            let mut dead = dead.into_iter();
+            self.instructions.retain(|_| !dead.next().unwrap());
+        }
+    }
+
+    // A pause straight after another one doesn't do anything useful.
+    fn optimize_pauses(&mut self) {
+        let mut last_pause = None;
+        self.instructions.retain(|instruction| {
+            let pause = match instruction {
+                Instruction::ThreadPause => Some(false),
+                Instruction::ThreadWarpPause => Some(true),
+                _ => None,
+            };
+            let redundant = pause.is_some() && pause == last_pause;
+            last_pause = pause;
+            !redundant
+        });
+    }
+
+    // Which registers might still be read after each instruction. Jumps are assumed
+    // to go anywhere, so any register read somewhere in the thread is live there.
+    fn registers_live_after(&mut self) -> Vec<HashSet<usize>> {
+        let mut read_anywhere = HashSet::new();
+        for instruction in self.instructions.iter_mut() {
+            for (operand, value) in instruction.operands_mut() {
+                if let (Operand::Read, Value::Register(register)) = (operand, &*value) {
+                    read_anywhere.insert(*register);
+                }
+            }
+        }
+
+        let mut live_after = vec![HashSet::new(); self.instructions.len()];
+        let mut live = HashSet::new();
+        for (index, instruction) in self.instructions.iter_mut().enumerate().rev() {
+            match instruction {
+                Instruction::FlowIfJump(..)
+                | Instruction::FlowIfJumpToPlace(..)
+                | Instruction::FlowIfNotJump(..)
+                | Instruction::FlowIfNotJumpToPlace(..) => live = read_anywhere.clone(),
+                Instruction::ThreadKill => live.clear(),
+                _ => {}
+            }
+            live_after[index] = live.clone();
+            // The output comes first, so an instruction reading and writing the same register keeps it live.
+            for (operand, value) in instruction.operands_mut() {
+                match (operand, &*value) {
+                    (Operand::Write, Value::Register(register)) => {
+                        live.remove(register);
+                    }
+                    (Operand::Read, Value::Register(register)) => {
+                        live.insert(*register);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        live_after
+    }
+
+    // Turns jumps to named places into jumps to instruction indices and removes the
+    // place markers, so taking a jump doesn't have to search the thread for its label.
     fn optimize_flatten_places(&mut self) {
-        // TODO: Implement compiler optimization
+        let mut places: HashMap<String, usize> = HashMap::new();
+        let mut index = 0;
+        for instruction in self.instructions.iter() {
+            match instruction {
+                Instruction::FlowDefinePlace(place) => {
+                    places.entry(place.clone()).or_insert(index);
+                }
+                _ => index += 1,
+            }
+        }
+
+        let instructions = std::mem::take(self.instructions);
+        for instruction in instructions {
+            let instruction = match instruction {
+                Instruction::FlowDefinePlace(_) => continue,
+                Instruction::FlowIfJumpToPlace(condition, place) => {
+                    Instruction::FlowIfJump(condition, places[&place])
+                }
+                Instruction::FlowIfNotJumpToPlace(condition, place) => {
+                    Instruction::FlowIfNotJump(condition, places[&place])
+                }
+                instruction => instruction,
+            };
+            self.instructions.push(instruction);
+        }
     }
 }
@@ -1,101 +1,285 @@
-use crate::{
-    ansi_codes,
-    interpreter::{Instruction, Value},
-};
+use std::collections::{HashMap, HashSet};
 
-use super::bc_comp_main::ThreadCompiler;
+use crate::interpreter::{Instruction, Operand, Value};
+
+use super::{bc_comp_diagnostics::DiagnosticKind, bc_comp_main::ThreadCompiler};
 
 impl<'a> ThreadCompiler<'a> {
+    // Registers live in the thread's own register file, not in the project's variables.
     pub fn register_malloc(&mut self) -> usize {
-        let index = {
-            if let Some(index) = self.temp_variables.iter().position(|&x| !x) {
-                // There is an unallocated register, so allocate to it.
-                self.temp_variables[index] = true;
-                index
-            } else {
-                // No unallocated register found, creating new one.
-                self.temp_variables.push(true);
-                // Return the index of the new register.
-                self.temp_variables.len() - 1
-            }
-        };
-        let temp_var_name = format!("thread{}tempvar{}", self.thread_number, index);
-
-        self.variables.push(temp_var_name, Value::Number(0.0));
-        index
+        if let Some(index) = self.temp_variables.iter().position(|&x| !x) {
+            // There is an unallocated register, so allocate to it.
+            self.temp_variables[index] = true;
+            index
+        } else {
+            // No unallocated register found, creating new one.
+            self.temp_variables.push(true);
+            // Return the index of the new register.
+            self.temp_variables.len() - 1
+        }
     }
 
     pub fn register_free(&mut self, index: usize) {
         self.temp_variables[index] = false;
     }
 
-    pub fn register_get_variable_id(&self, index: usize) -> usize {
-        self.variables
-            .get_id(&format!("thread{}tempvar{}", self.thread_number, index))
-            .unwrap()
+    // How big the thread's register file has to be.
+    pub fn register_count(&self) -> usize {
+        self.temp_variables.len()
+    }
+
+    // Gives every separate use of a register a register of its own. Blocks get the
+    // same registers one after another, so a register could otherwise hold a number
+    // in one block and a string in the next, and type inference couldn't say anything
+    // about it. Writes that can reach the same read have to keep sharing one.
+    pub fn optimize_split_registers(&mut self) {
+        let successors = self.successors();
+        let length = self.instructions.len();
+        let mut reads = vec![Vec::new(); length];
+        let mut writes = vec![Vec::new(); length];
+        for (index, instruction) in self.instructions.iter_mut().enumerate() {
+            for (operand, value) in instruction.operands_mut() {
+                match (operand, &*value) {
+                    (Operand::Read, Value::Register(register)) => reads[index].push(*register),
+                    (Operand::Write, Value::Register(register)) => writes[index].push(*register),
+                    _ => {}
+                }
+            }
+        }
+
+        let mut read_names: Vec<HashMap<usize, usize>> = vec![HashMap::new(); length];
+        let mut write_names: Vec<HashMap<usize, usize>> = vec![HashMap::new(); length];
+        let mut count = 0;
+        let mut zeroed = Vec::new();
+        for register in 0..self.register_count() {
+            // Write 0 stands for the start of the thread, where every register is 0.
+            let written_at: Vec<usize> = (0..length)
+                .filter(|index| writes[*index].contains(&register))
+                .collect();
+            let write_at = |index: usize| written_at.binary_search(&index).ok().map(|n| n + 1);
+
+            // Which writes each instruction can see.
+            let mut reaching: Vec<HashSet<usize>> = vec![HashSet::new(); length];
+            if let Some(first) = reaching.first_mut() {
+                first.insert(0);
+            }
+            let mut work: Vec<usize> = (0..length).collect();
+            while let Some(index) = work.pop() {
+                let out = match write_at(index) {
+                    Some(write) => HashSet::from([write]),
+                    None => reaching[index].clone(),
+                };
+                for &next in successors[index].iter() {
+                    let before = reaching[next].len();
+                    reaching[next].extend(out.iter().copied());
+                    if reaching[next].len() != before {
+                        work.push(next);
+                    }
+                }
+            }
+
+            // Writes read by the same instruction end up in the same group.
+            let mut group: Vec<usize> = (0..=written_at.len()).collect();
+            fn find(group: &mut [usize], write: usize) -> usize {
+                let mut root = write;
+                while group[root] != root {
+                    root = group[root];
+                }
+                group[write] = root;
+                root
+            }
+            for index in 0..length {
+                if !reads[index].contains(&register) {
+                    continue;
+                }
+                let mut seen = reaching[index].iter().copied();
+                if let Some(first) = seen.next() {
+                    for write in seen {
+                        let (a, b) = (find(&mut group, first), find(&mut group, write));
+                        group[a] = b;
+                    }
+                }
+            }
+
+            // Each group gets its own register.
+            let mut names: HashMap<usize, usize> = HashMap::new();
+            let mut name = |write: usize, group: &mut [usize]| {
+                *names.entry(find(group, write)).or_insert_with(|| {
+                    count += 1;
+                    count - 1
+                })
+            };
+            for index in 0..length {
+                if reads[index].contains(&register) {
+                    // Code nothing jumps to reads what the thread starts with.
+                    let write = reaching[index].iter().next().copied().unwrap_or(0);
+                    read_names[index].insert(register, name(write, &mut group));
+                    if write == 0 || reaching[index].contains(&0) {
+                        zeroed.push(read_names[index][&register]);
+                    }
+                }
+                if let Some(write) = write_at(index) {
+                    write_names[index].insert(register, name(write, &mut group));
+                }
+            }
+        }
+
+        for (index, instruction) in self.instructions.iter_mut().enumerate() {
+            for (operand, value) in instruction.operands_mut() {
+                let Value::Register(register) = value else {
+                    continue;
+                };
+                let names = match operand {
+                    Operand::Write => &write_names[index],
+                    _ => &read_names[index],
+                };
+                if let Some(name) = names.get(register) {
+                    *register = *name;
+                }
+            }
+        }
+        self.temp_variables = vec![false; count];
+        self.read_before_written = vec![false; count];
+        for register in zeroed {
+            self.read_before_written[register] = true;
+        }
+    }
+
+    // Where each instruction can go next. Jumps that aren't always
+    // taken could go either way.
+    fn successors(&self) -> Vec<Vec<usize>> {
+        let places: HashMap<&String, usize> = self
+            .instructions
+            .iter()
+            .enumerate()
+            .filter_map(|(index, instruction)| match instruction {
+                Instruction::FlowDefinePlace(place) => Some((place, index)),
+                _ => None,
+            })
+            .collect();
+        let length = self.instructions.len();
+        self.instructions
+            .iter()
+            .enumerate()
+            .map(|(index, instruction)| {
+                let next = (index + 1 < length).then_some(index + 1);
+                let target = match instruction {
+                    Instruction::FlowIfJumpToPlace(_, place)
+                    | Instruction::FlowIfNotJumpToPlace(_, place) => places.get(place).copied(),
+                    Instruction::FlowIfJump(_, target) | Instruction::FlowIfNotJump(_, target) => {
+                        Some(*target)
+                    }
+                    Instruction::ThreadKill => return Vec::new(),
+                    _ => return next.into_iter().collect(),
+                };
+                match instruction {
+                    Instruction::FlowIfJumpToPlace(Value::Boolean(true), _)
+                    | Instruction::FlowIfJump(Value::Boolean(true), _) => {
+                        target.into_iter().collect()
+                    }
+                    _ => next.into_iter().chain(target).collect(),
+                }
+            })
+            .collect()
     }
 
     pub fn register_set_to_input(
         &mut self,
         current_block: &serde_json::Value,
         register: usize,
-        input: &str,
+        input_name: &str,
     ) {
-        let input = &current_block["inputs"][input].as_array().unwrap()[1];
+        let value = self.input_value(current_block, input_name);
+        self.instructions
+            .push(Instruction::MemoryStore(Value::Register(register), value));
+    }
+
+    // What an input holds. Anything that can't be compiled is reported
+    // and reads as an empty string, like an empty input in Scratch.
+    fn input_value(&mut self, current_block: &serde_json::Value, input_name: &str) -> Value {
+        let empty = Value::String(String::new());
+        let Some(input) = current_block["inputs"][input_name]
+            .as_array()
+            .and_then(|input| input.get(1))
+        else {
+            self.report(
+                current_block,
+                DiagnosticKind::MissingInput,
+                format!("Input {input_name} is missing"),
+            );
+            return empty;
+        };
         match input {
             serde_json::Value::String(n) => {
-                let block = self.get_block(n.as_str()).unwrap();
+                let Some(block) = self.get_block(n.as_str()) else {
+                    self.report(
+                        current_block,
+                        DiagnosticKind::MissingInput,
+                        format!("Block {n:?} in input {input_name} doesn't exist"),
+                    );
+                    return empty;
+                };
                 match self.compile_block(&block) {
-                    None => {
-                        eprintln!(
-                            "{}[unimplemented block]{} {} (inside expression)",
-                            ansi_codes::RED,
-                            ansi_codes::RESET,
-                            block["opcode"].as_str().unwrap()
-                        );
-                    }
+                    // Already reported by compile_block.
+                    None => empty,
                     Some(n) => {
-                        self.instructions.push(Instruction::MemoryStore(
-                            Value::Pointer(self.register_get_variable_id(register)),
-                            Value::Pointer(self.register_get_variable_id(n)),
-                        ));
                         self.register_free(n);
+                        Value::Register(n)
                     }
                 }
             }
             serde_json::Value::Array(input_array) => {
-                match input_array[0].as_number().unwrap().as_i64().unwrap() {
-                    4..=9 => {
-                        self.instructions.push(Instruction::MemoryStore(
-                            Value::Pointer(self.register_get_variable_id(register)),
-                            {
-                                match input_array[1].as_str().unwrap().parse::<f64>() {
-                                    Ok(n) => Value::Number(n),
-                                    Err(_) => Value::Number(0.0),
-                                }
-                            },
-                        ));
-                    }
-                    10 => {
-                        self.instructions.push(Instruction::MemoryStore(
-                            Value::Pointer(self.register_get_variable_id(register)),
-                            Value::String(input_array[1].as_str().unwrap().to_owned()),
-                        ));
-                    }
-                    12 => {
-                        self.instructions.push(Instruction::MemoryStore(
-                            Value::Pointer(self.register_get_variable_id(register)),
-                            Value::Pointer(
-                                self.variables
-                                    .get_id(&input_array[2].as_str().unwrap().to_owned())
-                                    .unwrap(),
-                            ),
-                        ));
+                match input_array.first().and_then(|kind| kind.as_i64()) {
+                    Some(4..=8) => match input_array.get(1) {
+                        Some(serde_json::Value::String(n)) => {
+                            Value::Number(n.parse().unwrap_or(0.0))
+                        }
+                        n => Value::Number(n.and_then(|n| n.as_f64()).unwrap_or(0.0)),
+                    },
+                    // Colours are stored as "#rrggbb" strings.
+                    Some(9 | 10) => match input_array.get(1).and_then(|color| color.as_str()) {
+                        Some(color) => Value::String(color.to_owned()),
+                        None => empty,
+                    },
+                    Some(12) => match input_array.get(2).and_then(|id| id.as_str()) {
+                        Some(id) => match self.variables.get_id(id) {
+                            Some(pointer) => Value::Pointer(pointer),
+                            None => {
+                                self.report(
+                                    current_block,
+                                    DiagnosticKind::MissingInput,
+                                    format!("Variable {id:?} doesn't exist"),
+                                );
+                                empty
+                            }
+                        },
+                        None => {
+                            self.report(
+                                current_block,
+                                DiagnosticKind::BadJson,
+                                format!("Variable input {input_name} has no ID"),
+                            );
+                            empty
+                        }
+                    },
+                    _ => {
+                        self.report(
+                            current_block,
+                            DiagnosticKind::UnsupportedBlock,
+                            format!("Unsupported input {input}"),
+                        );
+                        empty
                     }
-                    _ => eprintln!("[unimplemented input block] {}", input),
                 }
             }
-            _ => panic!(),
+            _ => {
+                self.report(
+                    current_block,
+                    DiagnosticKind::BadJson,
+                    format!("Input {input_name} should be a block or a value, not {input}"),
+                );
+                empty
+            }
         }
     }
 }
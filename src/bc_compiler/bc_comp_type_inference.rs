@@ -0,0 +1,332 @@
+use crate::interpreter::{Instruction, NumberOp, Operand, Value};
+
+use super::bc_comp_main::ThreadCompiler;
+
+#[derive(Clone, Copy, PartialEq)]
+enum ValueType {
+    Number,
+    Boolean,
+    String,
+    // Could be more than one type, or anything at all, like a variable.
+    Unknown,
+    // Nothing is ever stored in it.
+    Unset,
+}
+
+impl ValueType {
+    fn join(self, other: ValueType) -> ValueType {
+        match (self, other) {
+            (ValueType::Unset, other) => other,
+            (this, ValueType::Unset) => this,
+            _ if self == other => self,
+            _ => ValueType::Unknown,
+        }
+    }
+}
+
+impl<'a> ThreadCompiler<'a> {
+    // Moves registers that only ever hold numbers or only ever hold booleans to their
+    // own banks, and swaps operators for ones that skip type conversions, wherever
+    // both of their inputs are known to always be numbers. Comparisons then write
+    // straight into the bool bank, which is where jumps read their conditions from.
+    pub fn optimize_specialize_types(&mut self) {
+        let types = self.infer_register_types();
+        let is_number =
+            |value: &Value| matches!(value, Value::Number(_) | Value::NumberRegister(_));
+
+        for instruction in self.instructions.iter_mut() {
+            for (_, value) in instruction.operands_mut() {
+                if let Value::Register(register) = *value {
+                    match types[register] {
+                        ValueType::Number => *value = Value::NumberRegister(register),
+                        ValueType::Boolean => *value = Value::BooleanRegister(register),
+                        _ => {}
+                    }
+                }
+            }
+
+            let (op, l, a, b) = match instruction {
+                Instruction::OperatorAdd(l, a, b) => (NumberOp::Add, l, a, b),
+                Instruction::OperatorSubtract(l, a, b) => (NumberOp::Subtract, l, a, b),
+                Instruction::OperatorMultiply(l, a, b) => (NumberOp::Multiply, l, a, b),
+                Instruction::OperatorDivide(l, a, b) => (NumberOp::Divide, l, a, b),
+                Instruction::OperatorModulo(l, a, b) => (NumberOp::Modulo, l, a, b),
+                Instruction::OperatorPower(l, a, b) => (NumberOp::Power, l, a, b),
+                Instruction::OperatorLesser(l, a, b) => (NumberOp::Lesser, l, a, b),
+                Instruction::OperatorGreater(l, a, b) => (NumberOp::Greater, l, a, b),
+                Instruction::OperatorEquals(l, a, b) => (NumberOp::Equals, l, a, b),
+                _ => continue,
+            };
+            if is_number(a) && is_number(b) {
+                *instruction = Instruction::OperatorNumbers(op, l.clone(), a.clone(), b.clone());
+            }
+        }
+    }
+
+    // What every register can hold, going by everything this thread writes to it.
+    // Registers start out as 0, so ones that can be read before they are written
+    // are numbers until something else is stored in them.
+    fn infer_register_types(&mut self) -> Vec<ValueType> {
+        let mut types: Vec<ValueType> = (0..self.register_count())
+            .map(|register| match self.read_before_written.get(register) {
+                Some(false) => ValueType::Unset,
+                _ => ValueType::Number,
+            })
+            .collect();
+
+        // Types only ever widen, so this settles quickly.
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for instruction in self.instructions.iter_mut() {
+                let output = output_type(instruction, &types);
+                for (operand, value) in instruction.operands_mut() {
+                    let (Operand::Write, Value::Register(register)) = (operand, &*value) else {
+                        continue;
+                    };
+                    let joined = types[*register].join(output);
+                    if joined != types[*register] {
+                        types[*register] = joined;
+                        changed = true;
+                    }
+                }
+            }
+        }
+        types
+    }
+}
+
+fn output_type(instruction: &Instruction, types: &[ValueType]) -> ValueType {
+    match instruction {
+        Instruction::MemoryStore(_, value) => match value {
+            Value::Number(_) => ValueType::Number,
+            Value::Boolean(_) => ValueType::Boolean,
+            Value::String(_) => ValueType::String,
+            Value::Register(register) => types[*register],
+            Value::NumberRegister(_) => ValueType::Number,
+            Value::BooleanRegister(_) => ValueType::Boolean,
+            Value::Pointer(_) | Value::List(_) => ValueType::Unknown,
+        },
+        Instruction::OperatorNumbers(op, ..) => match op {
+            NumberOp::Lesser | NumberOp::Greater | NumberOp::Equals => ValueType::Boolean,
+            _ => ValueType::Number,
+        },
+        Instruction::OperatorLesser(..)
+        | Instruction::OperatorGreater(..)
+        | Instruction::OperatorEquals(..) => ValueType::Boolean,
+        Instruction::OperatorModulo(..)
+        | Instruction::OperatorAdd(..)
+        | Instruction::OperatorSubtract(..)
+        | Instruction::OperatorMultiply(..)
+        | Instruction::OperatorDivide(..)
+        | Instruction::OperatorPower(..)
+        | Instruction::OperatorERaised(..)
+        | Instruction::OperatorSin(..)
+        | Instruction::OperatorCos(..)
+        | Instruction::OperatorTan(..)
+        | Instruction::OperatorAbs(..)
+        | Instruction::OperatorASin(..)
+        | Instruction::OperatorACos(..)
+        | Instruction::OperatorATan(..)
+        | Instruction::OperatorSqrt(..)
+        | Instruction::OperatorLn(..)
+        | Instruction::OperatorLog(..)
+        | Instruction::OperatorFloor(..)
+        | Instruction::OperatorCeiling(..)
+        | Instruction::SensingTimer(..)
+        | Instruction::SensingDistanceTo(..)
+        | Instruction::SensingCurrent(..)
+        | Instruction::SensingDaysSince2000(..)
+        | Instruction::SoundGetVolume(..)
+        | Instruction::MusicGetTempo(..)
+        | Instruction::MotionGetX(..)
+        | Instruction::MotionGetY(..)
+        | Instruction::LooksGetCostumeNumber(..) => ValueType::Number,
+        Instruction::SensingAnswer(..) | Instruction::SensingUsername(..) => ValueType::String,
+        _ => ValueType::Unknown,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use serde_json::json;
+
+    use crate::{
+        bc_compiler::{bc_comp_main::ThreadCompiler, bc_comp_variable_manager::VariableCompiler},
+        interpreter::{Instruction, Memory, NumberOp, Registers, Value},
+        project::project_main::Project,
+        thread::run_memory_instruction,
+    };
+
+    // The general instruction a specialized one was made from, with its registers untyped.
+    fn unspecialize(instruction: &Instruction) -> Instruction {
+        let mut instruction = instruction.clone();
+        for (_, value) in instruction.operands_mut() {
+            if let Value::NumberRegister(register) | Value::BooleanRegister(register) = *value {
+                *value = Value::Register(register);
+            }
+        }
+        let Instruction::OperatorNumbers(op, l, a, b) = instruction else {
+            return instruction;
+        };
+        match op {
+            NumberOp::Add => Instruction::OperatorAdd(l, a, b),
+            NumberOp::Subtract => Instruction::OperatorSubtract(l, a, b),
+            NumberOp::Multiply => Instruction::OperatorMultiply(l, a, b),
+            NumberOp::Divide => Instruction::OperatorDivide(l, a, b),
+            NumberOp::Modulo => Instruction::OperatorModulo(l, a, b),
+            NumberOp::Power => Instruction::OperatorPower(l, a, b),
+            NumberOp::Lesser => Instruction::OperatorLesser(l, a, b),
+            NumberOp::Greater => Instruction::OperatorGreater(l, a, b),
+            NumberOp::Equals => Instruction::OperatorEquals(l, a, b),
+        }
+    }
+
+    #[test]
+    fn only_number_registers_move_to_the_f64_bank() {
+        let sprite = json!({ "name": "Sprite1", "variables": { "v": ["x", 0] }, "blocks": {
+            "a": { "opcode": "data_setvariableto", "next": "d", "fields": { "VARIABLE": ["x", "v"] },
+                   "inputs": { "VALUE": [3, "b", [10, ""]] } },
+            "b": { "opcode": "operator_add", "next": null,
+                   "inputs": { "NUM1": [3, "c", [4, ""]], "NUM2": [1, [4, "1"]] } },
+            "c": { "opcode": "motion_xposition", "next": null },
+            "d": { "opcode": "data_setvariableto", "next": null, "fields": { "VARIABLE": ["x", "v"] },
+                   "inputs": { "VALUE": [3, "e", [10, ""]] } },
+            "e": { "opcode": "operator_add", "next": null,
+                   "inputs": { "NUM1": [3, "f", [4, ""]], "NUM2": [1, [4, "1"]] } },
+            "f": { "opcode": "sensing_answer", "next": null },
+        } });
+        let mut variables = VariableCompiler::new();
+        variables.load_from_json(&sprite);
+        let mut instructions = Vec::new();
+        let mut compiler = ThreadCompiler::new(
+            &mut variables,
+            &mut instructions,
+            0,
+            &sprite,
+            std::slice::from_ref(&sprite),
+        );
+        compiler.compile_stack(&json!({}), "a");
+        compiler.finish();
+        compiler.optimize().unwrap();
+
+        let x = instructions
+            .iter()
+            .find_map(|instruction| match instruction {
+                Instruction::MotionGetX(x) => Some(x),
+                _ => None,
+            })
+            .unwrap();
+        assert!(matches!(x, Value::NumberRegister(_)), "{x:?}");
+        let answer = instructions
+            .iter()
+            .find_map(|instruction| match instruction {
+                Instruction::SensingAnswer(answer) => Some(answer),
+                _ => None,
+            })
+            .unwrap();
+        assert!(matches!(answer, Value::Register(_)), "{answer:?}");
+        assert!(instructions.iter().any(|instruction| matches!(
+            instruction,
+            Instruction::OperatorNumbers(NumberOp::Add, _, Value::NumberRegister(_), _)
+        )));
+    }
+
+    #[test]
+    fn conditions_move_to_the_bool_bank() {
+        let sprite = json!({ "name": "Sprite1", "variables": { "v": ["x", 0] }, "blocks": {
+            "a": { "opcode": "control_if", "next": null,
+                   "inputs": { "CONDITION": [2, "b"], "SUBSTACK": [2, "d"] } },
+            "b": { "opcode": "operator_lt", "next": null,
+                   "inputs": { "OPERAND1": [3, "c", [10, ""]], "OPERAND2": [1, [4, "5"]] } },
+            "c": { "opcode": "motion_xposition", "next": null },
+            "d": { "opcode": "data_setvariableto", "next": null, "fields": { "VARIABLE": ["x", "v"] },
+                   "inputs": { "VALUE": [1, [10, "1"]] } },
+        } });
+        let mut variables = VariableCompiler::new();
+        variables.load_from_json(&sprite);
+        let mut instructions = Vec::new();
+        let mut compiler = ThreadCompiler::new(
+            &mut variables,
+            &mut instructions,
+            0,
+            &sprite,
+            std::slice::from_ref(&sprite),
+        );
+        compiler.compile_stack(&json!({}), "a");
+        compiler.finish();
+        compiler.optimize().unwrap();
+
+        let written = instructions
+            .iter()
+            .find_map(|instruction| match instruction {
+                Instruction::OperatorNumbers(NumberOp::Lesser, location, ..) => Some(location),
+                _ => None,
+            });
+        let Some(Value::BooleanRegister(register)) = written else {
+            panic!("{written:?}");
+        };
+        assert!(instructions.iter().any(|instruction| matches!(
+            instruction,
+            Instruction::FlowIfNotJump(Value::BooleanRegister(read), _) if read == register
+        )));
+    }
+
+    // Runs a script the way a thread does, leaving out what needs a running project.
+    // For the pi calculator, that is only the block it times itself with.
+    fn run(instructions: &[Instruction], variables: &[Value], register_count: usize) -> Vec<Value> {
+        let mut variables = variables.to_vec();
+        let mut registers = Registers::new(register_count);
+        let mut memory = Memory::new(&mut variables, &mut registers, &mut []);
+        let mut counter = 0;
+        while let Some(instruction) = instructions.get(counter) {
+            counter += 1;
+            if let Instruction::ThreadKill = instruction {
+                break;
+            }
+            run_memory_instruction(instruction, &mut memory, &mut counter);
+        }
+        variables
+    }
+
+    // Not run by default, since it takes a while and only means something in release mode:
+    //
+    //     cargo test --release benchmark_pi_calculator -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn benchmark_pi_calculator() {
+        let archive = std::fs::read("tests/pi calculator.sb3").unwrap();
+        let (_directory, path) = Project::extract_zip_file(archive).unwrap();
+        let (variables, scripts, _) =
+            Project::compile_scripts(&Project::load_json(&path), false).unwrap();
+        let variables: Vec<Value> = variables
+            .entries()
+            .map(|(_, value)| value.clone())
+            .collect();
+        let script = scripts.iter().flatten().next().unwrap();
+        let specialized = &script.instructions;
+        let general: Vec<Instruction> = specialized.iter().map(unspecialize).collect();
+
+        let time = |instructions: &[Instruction]| {
+            let mut fastest = Duration::MAX;
+            let mut result = Vec::new();
+            for _ in 0..5 {
+                let started = Instant::now();
+                result = run(instructions, &variables, script.register_count);
+                fastest = fastest.min(started.elapsed());
+            }
+            (fastest, result)
+        };
+        let (general_time, general_result) = time(&general);
+        let (specialized_time, specialized_result) = time(specialized);
+        assert_eq!(general_result, specialized_result);
+        println!(
+            "worked out {}",
+            specialized_result[0].get_string(&Memory::empty())
+        );
+        println!("general operators:     {general_time:?}");
+        println!("specialized operators: {specialized_time:?}");
+    }
+}
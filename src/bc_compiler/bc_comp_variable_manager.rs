@@ -1,16 +1,20 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
 
 use crate::{ansi_codes, interpreter::Value};
 
+#[derive(Serialize, Deserialize)]
 pub struct VariableCompiler {
-    lookup: HashMap<String, usize>,
+    // Sorted, so a project is always saved to the cache the same way.
+    lookup: BTreeMap<String, usize>,
     data: Vec<Value>,
 }
 
 impl VariableCompiler {
     pub fn new() -> Self {
         Self {
-            lookup: HashMap::new(),
+            lookup: BTreeMap::new(),
             data: Vec::new(),
         }
     }
@@ -22,24 +26,37 @@ impl VariableCompiler {
                     serde_json::Value::Bool(n) => Value::Boolean(*n),
                     serde_json::Value::Number(n) => Value::Number(n.as_f64().unwrap()),
                     serde_json::Value::String(n) => Value::String(n.clone()),
-                    _ => panic!(),
+                    other => Value::String(other.to_string()),
                 }
             });
             self.lookup
                 .insert(variable_hash.clone(), self.data.len() - 1);
         }
+
+        let Some(lists) = sprite_json["lists"].as_object() else {
+            return;
+        };
+        for (list_hash, list_data) in lists {
+            let items = list_data.as_array().unwrap()[1]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|item| match item {
+                    serde_json::Value::Bool(n) => Value::Boolean(*n),
+                    serde_json::Value::Number(n) => Value::Number(n.as_f64().unwrap()),
+                    serde_json::Value::String(n) => Value::String(n.clone()),
+                    _ => Value::String(item.to_string()),
+                })
+                .collect();
+            self.data.push(Value::List(items));
+            self.lookup.insert(list_hash.clone(), self.data.len() - 1);
+        }
     }
 
     pub fn finish_processing(self) -> Box<[Value]> {
         self.data.into_boxed_slice()
     }
 
-    pub fn push(&mut self, name: String, value: Value) {
-        let var_number = self.lookup.len();
-        self.lookup.entry(name).or_insert(var_number);
-        self.data.push(value);
-    }
-
     pub fn get_id(&self, name: &str) -> Option<usize> {
         self.lookup.get(name).copied()
     }
@@ -48,6 +65,14 @@ impl VariableCompiler {
         self.lookup.iter().find(|(_, &v)| v == id).map(|(k, _)| k)
     }
 
+    // Every variable and list in pointer order, with its ID and starting value.
+    pub fn entries(&self) -> impl Iterator<Item = (Option<&String>, &Value)> {
+        self.data
+            .iter()
+            .enumerate()
+            .map(|(index, value)| (self.get_name(index), value))
+    }
+
     pub fn dump(&self) {
         for (variable, i) in self.lookup.iter() {
             println!(
@@ -55,7 +80,7 @@ impl VariableCompiler {
                 ansi_codes::YELLOW,
                 ansi_codes::WHITE,
                 ansi_codes::RESET,
-                self.data[*i].print(Some(&self))
+                self.data[*i].print(Some(self))
             );
         }
     }
@@ -0,0 +1,64 @@
+use std::collections::HashSet;
+
+use crate::interpreter::Instruction;
+
+use super::bc_comp_main::ThreadCompiler;
+
+// Checks on the compiled bytecode, so broken jumps are reported
+// when the project loads instead of crashing it while it runs.
+impl<'a> ThreadCompiler<'a> {
+    // Before linking: every place is defined once, and every jump goes to one of them.
+    pub fn verify_places(&self) -> Result<(), String> {
+        let mut places: HashSet<&String> = HashSet::new();
+        for instruction in self.instructions.iter() {
+            if let Instruction::FlowDefinePlace(place) = instruction {
+                if !places.insert(place) {
+                    return Err(format!(
+                        "Thread {} defines \"{place}\" more than once",
+                        self.thread_number
+                    ));
+                }
+            }
+        }
+
+        for instruction in self.instructions.iter() {
+            if let Instruction::FlowIfJumpToPlace(_, place)
+            | Instruction::FlowIfNotJumpToPlace(_, place) = instruction
+            {
+                if !places.contains(place) {
+                    return Err(format!(
+                        "Thread {} jumps to \"{place}\", which is never defined",
+                        self.thread_number
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // After linking: no labels are left and every jump lands inside the thread.
+    pub fn verify_jumps(&self) -> Result<(), String> {
+        for (index, instruction) in self.instructions.iter().enumerate() {
+            match instruction {
+                Instruction::FlowIfJump(_, location) | Instruction::FlowIfNotJump(_, location)
+                    if *location >= self.instructions.len() =>
+                {
+                    return Err(format!(
+                        "Thread {} jumps out of bounds to {location} at {index}",
+                        self.thread_number
+                    ));
+                }
+                Instruction::FlowIfJumpToPlace(..)
+                | Instruction::FlowIfNotJumpToPlace(..)
+                | Instruction::FlowDefinePlace(_) => {
+                    return Err(format!(
+                        "Thread {} still has an unlinked label at {index}",
+                        self.thread_number
+                    ));
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,33 +1,22 @@
 use crate::{
+    bc_compiler::{bc_comp_diagnostics::DiagnosticKind, bc_comp_main::ThreadCompiler},
     interpreter::{Instruction, Value},
-    bc_compiler::bc_comp_main::ThreadCompiler,
 };
 
 impl<'a> ThreadCompiler<'a> {
     pub fn c_control_forever(&mut self, current_block: &serde_json::Value) -> Option<usize> {
-        self.jump_counter += 1;
-        self.instructions.push(Instruction::FlowDefinePlace(format!(
-            "forever{}",
-            self.jump_counter
-        )));
-        if !current_block["inputs"].as_object().unwrap().is_empty() {
-            self.compile_substack(current_block);
-        }
+        let start = self.new_label("forever");
+        self.instructions
+            .push(Instruction::FlowDefinePlace(start.clone()));
+        self.compile_substack(current_block);
         self.pause();
-        self.instructions.push(Instruction::FlowIfJumpToPlace(
-            Value::Boolean(true),
-            format!("forever{}", self.jump_counter),
-        ));
-        self.jump_counter -= 1;
+        self.instructions
+            .push(Instruction::FlowIfJumpToPlace(Value::Boolean(true), start));
 
         None
     }
 
     pub fn c_control_if(&mut self, current_block: &serde_json::Value) -> Option<usize> {
-        // If no condition and blocks. if() {}
-        if current_block["inputs"].as_object().unwrap().is_empty() {
-            return None;
-        }
         // If no blocks. if(condition) {}
         if current_block["inputs"]["SUBSTACK"] == serde_json::Value::Null {
             return None;
@@ -37,43 +26,67 @@ impl<'a> ThreadCompiler<'a> {
             return None;
         }
 
-        let result = self.get_input_bool(current_block);
-        result?;
+        let result = self.get_input_bool(current_block)?;
 
+        let end = self.new_label("if");
         self.instructions.push(Instruction::FlowIfNotJumpToPlace(
-            Value::Pointer(self.register_get_variable_id(result.unwrap())),
-            format!("if{}", self.if_jump_number),
+            Value::Register(result),
+            end.clone(),
         ));
         self.compile_substack(current_block);
-        self.instructions.push(Instruction::FlowDefinePlace(format!(
-            "if{}",
-            self.if_jump_number
-        )));
-        self.register_free(result.unwrap());
-        self.if_jump_number += 1;
+        self.instructions.push(Instruction::FlowDefinePlace(end));
+        self.register_free(result);
+
+        None
+    }
+
+    pub fn c_control_if_else(&mut self, current_block: &serde_json::Value) -> Option<usize> {
+        // An empty condition is false, so only the else part runs.
+        if current_block["inputs"]["CONDITION"] == serde_json::Value::Null {
+            self.compile_substack_input(current_block, "SUBSTACK2");
+            return None;
+        }
+
+        let result = self.get_input_bool(current_block)?;
+
+        let else_place = self.new_label("if_else_else");
+        let end = self.new_label("if_else_end");
+        self.instructions.push(Instruction::FlowIfNotJumpToPlace(
+            Value::Register(result),
+            else_place.clone(),
+        ));
+        self.compile_substack(current_block);
+        self.instructions.push(Instruction::FlowIfJumpToPlace(
+            Value::Boolean(true),
+            end.clone(),
+        ));
+        self.instructions
+            .push(Instruction::FlowDefinePlace(else_place));
+        self.compile_substack_input(current_block, "SUBSTACK2");
+        self.instructions.push(Instruction::FlowDefinePlace(end));
+        self.register_free(result);
 
         None
     }
 
     pub fn c_control_repeat(&mut self, current_block: &serde_json::Value) -> Option<usize> {
-        println!("{}", current_block);
         let num_iters = self.register_malloc();
         let temp_result = self.register_malloc();
 
         self.register_set_to_input(current_block, num_iters, "TIMES");
-        self.instructions.push(Instruction::FlowDefinePlace(format!(
-            "repeat_start{}",
-            self.if_jump_number
-        )));
+        let start = self.new_label("repeat_start");
+        let end = self.new_label("repeat_end");
+        self.instructions
+            .push(Instruction::FlowDefinePlace(start.clone()));
 
         self.instructions.push(Instruction::OperatorLesser(
-            Value::Pointer(self.register_get_variable_id(temp_result)),
-            Value::Pointer(self.register_get_variable_id(num_iters)),
+            Value::Register(temp_result),
+            Value::Register(num_iters),
             Value::Number(1.0),
         ));
         self.instructions.push(Instruction::FlowIfJumpToPlace(
-            Value::Pointer(self.register_get_variable_id(temp_result)),
-            format!("repeat_end{}", self.if_jump_number),
+            Value::Register(temp_result),
+            end.clone(),
         ));
 
         // The actual code in the loop.
@@ -81,19 +94,13 @@ impl<'a> ThreadCompiler<'a> {
         self.pause();
 
         self.instructions.push(Instruction::OperatorSubtract(
-            Value::Pointer(self.register_get_variable_id(num_iters)),
-            Value::Pointer(self.register_get_variable_id(num_iters)),
+            Value::Register(num_iters),
+            Value::Register(num_iters),
             Value::Number(1.0),
         ));
-        self.instructions.push(Instruction::FlowIfJumpToPlace(
-            Value::Boolean(true),
-            format!("repeat_start{}", self.if_jump_number),
-        ));
-        self.instructions.push(Instruction::FlowDefinePlace(format!(
-            "repeat_end{}",
-            self.if_jump_number
-        )));
-        self.if_jump_number += 1;
+        self.instructions
+            .push(Instruction::FlowIfJumpToPlace(Value::Boolean(true), start));
+        self.instructions.push(Instruction::FlowDefinePlace(end));
 
         self.register_free(num_iters);
         self.register_free(temp_result);
@@ -102,33 +109,210 @@ impl<'a> ThreadCompiler<'a> {
     }
 
     pub fn c_control_repeat_until(&mut self, current_block: &serde_json::Value) -> Option<usize> {
-        self.instructions.push(Instruction::FlowDefinePlace(format!(
-            "repeat_until_start{}",
-            self.jump_counter
-        )));
+        let start = self.new_label("repeat_until_start");
+        let end = self.new_label("repeat_until_end");
+        self.instructions
+            .push(Instruction::FlowDefinePlace(start.clone()));
 
-        let condition = self.get_input_bool(current_block);
-        condition?;
-
-        self.instructions.push(Instruction::FlowIfJumpToPlace(
-            Value::Pointer(self.register_get_variable_id(condition.unwrap())),
-            format!("repeat_until_end{}", self.jump_counter),
-        ));
+        // An empty condition is false in Scratch, so the loop never ends. So does one
+        // that couldn't be compiled, which has already been reported.
+        let condition = if current_block["inputs"]["CONDITION"].is_null() {
+            self.report(
+                current_block,
+                DiagnosticKind::EmptyInput,
+                "Repeat until has no condition, so it repeats forever".to_owned(),
+            );
+            None
+        } else {
+            self.get_input_bool(current_block)
+        };
+        if let Some(condition) = condition {
+            self.instructions.push(Instruction::FlowIfJumpToPlace(
+                Value::Register(condition),
+                end.clone(),
+            ));
+        }
         self.compile_substack(current_block);
         self.pause();
 
-        self.instructions.push(Instruction::FlowIfJumpToPlace(
-            Value::Boolean(true),
-            format!("repeat_until_start{}", self.jump_counter),
-        ));
-        self.instructions.push(Instruction::FlowDefinePlace(format!(
-            "repeat_until_end{}",
-            self.jump_counter
-        )));
-        self.jump_counter += 1;
+        self.instructions
+            .push(Instruction::FlowIfJumpToPlace(Value::Boolean(true), start));
+        self.instructions.push(Instruction::FlowDefinePlace(end));
 
-        self.register_free(condition.unwrap());
+        if let Some(condition) = condition {
+            self.register_free(condition);
+        }
 
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use serde_json::json;
+
+    use crate::{
+        bc_compiler::{
+            bc_comp_diagnostics::DiagnosticKind, bc_comp_main::ThreadCompiler,
+            bc_comp_variable_manager::VariableCompiler,
+        },
+        interpreter::{Instruction, Value},
+    };
+
+    fn number(value: &str) -> serde_json::Value {
+        json!([1, [4, value]])
+    }
+
+    fn compare(opcode: &str, left: &str, right: &str) -> serde_json::Value {
+        json!({
+            "opcode": opcode,
+            "next": null,
+            "inputs": { "OPERAND1": number(left), "OPERAND2": number(right) },
+        })
+    }
+
+    // Compiles the stack starting at block "a" and checks its labels, both before and after linking.
+    fn compile_and_check(blocks: serde_json::Value) {
+        let sprite = json!({ "name": "Sprite1", "blocks": blocks });
+        let mut variables = VariableCompiler::new();
+        let mut instructions = Vec::new();
+        let mut compiler = ThreadCompiler::new(
+            &mut variables,
+            &mut instructions,
+            0,
+            &sprite,
+            std::slice::from_ref(&sprite),
+        );
+        compiler.compile_stack(&json!({}), "a");
+        compiler.finish();
+        assert!(
+            compiler.diagnostics.is_empty(),
+            "{:?}",
+            compiler.diagnostics
+        );
+
+        let mut places = HashSet::new();
+        for instruction in compiler.instructions.iter() {
+            if let Instruction::FlowDefinePlace(place) = instruction {
+                assert!(places.insert(place.clone()), "{place} is defined twice");
+            }
+        }
+        for instruction in compiler.instructions.iter() {
+            if let Instruction::FlowIfJumpToPlace(_, place)
+            | Instruction::FlowIfNotJumpToPlace(_, place) = instruction
+            {
+                assert!(places.contains(place), "{place} is never defined");
+            }
+        }
+        compiler.verify_places().unwrap();
+
+        compiler.link().unwrap();
+        compiler.verify_jumps().unwrap();
+        for instruction in compiler.instructions.iter() {
+            if let Instruction::FlowIfJump(_, location) | Instruction::FlowIfNotJump(_, location) =
+                instruction
+            {
+                assert!(*location < compiler.instructions.len());
+            }
+        }
+    }
+
+    #[test]
+    fn nested_loops_and_ifs() {
+        compile_and_check(json!({
+            "a": { "opcode": "control_forever", "next": null,
+                   "inputs": { "SUBSTACK": [2, "b"] } },
+            "b": { "opcode": "control_repeat", "next": "f",
+                   "inputs": { "TIMES": number("3"), "SUBSTACK": [2, "c"] } },
+            "c": { "opcode": "control_if_else", "next": null,
+                   "inputs": { "CONDITION": [2, "d"], "SUBSTACK": [2, "e"], "SUBSTACK2": [2, "g"] } },
+            "d": compare("operator_lt", "1", "2"),
+            "e": { "opcode": "control_repeat_until", "next": null,
+                   "inputs": { "CONDITION": [2, "h"], "SUBSTACK": [2, "i"] } },
+            "h": compare("operator_gt", "1", "2"),
+            "i": { "opcode": "control_repeat", "next": null,
+                   "inputs": { "TIMES": number("2") } },
+            "g": { "opcode": "control_if", "next": null,
+                   "inputs": { "CONDITION": [2, "j"], "SUBSTACK": [2, "k"] } },
+            "j": compare("operator_equals", "1", "1"),
+            "k": { "opcode": "control_repeat", "next": null,
+                   "inputs": { "TIMES": number("4"), "SUBSTACK": [2, "l"] } },
+            "l": { "opcode": "control_if_else", "next": null,
+                   "inputs": { "CONDITION": [2, "m"], "SUBSTACK": [2, "n"] } },
+            "m": compare("operator_lt", "2", "1"),
+            "n": { "opcode": "control_repeat_until", "next": null,
+                   "inputs": { "CONDITION": [2, "o"] } },
+            "o": compare("operator_equals", "1", "2"),
+            "f": { "opcode": "control_repeat", "next": null,
+                   "inputs": { "TIMES": number("5"), "SUBSTACK": [2, "p"] } },
+            "p": { "opcode": "control_repeat", "next": null,
+                   "inputs": { "TIMES": number("5") } },
+        }));
+    }
+
+    #[test]
+    fn sibling_loops_get_their_own_labels() {
+        compile_and_check(json!({
+            "a": { "opcode": "control_repeat", "next": "b",
+                   "inputs": { "TIMES": number("2"), "SUBSTACK": [2, "c"] } },
+            "b": { "opcode": "control_repeat", "next": "d",
+                   "inputs": { "TIMES": number("2"), "SUBSTACK": [2, "e"] } },
+            "c": { "opcode": "control_if_else", "next": null,
+                   "inputs": { "CONDITION": [2, "f"] } },
+            "f": compare("operator_lt", "1", "2"),
+            "e": { "opcode": "control_if_else", "next": null,
+                   "inputs": { "CONDITION": [2, "g"] } },
+            "g": compare("operator_lt", "1", "2"),
+            "d": { "opcode": "control_if_else", "next": null,
+                   "inputs": { "SUBSTACK2": [2, "h"] } },
+            "h": { "opcode": "control_forever", "next": null, "inputs": {} },
+        }));
+    }
+
+    #[test]
+    fn empty_repeat_until_repeats_forever() {
+        let sprite = json!({
+            "name": "Sprite1",
+            "variables": { "counter": ["counter", 0] },
+            "blocks": {
+                "a": { "opcode": "control_repeat_until", "next": null,
+                       "inputs": { "SUBSTACK": [2, "b"] } },
+                "b": { "opcode": "data_changevariableby", "next": null,
+                       "inputs": { "VALUE": number("1") },
+                       "fields": { "VARIABLE": ["counter", "counter"] } },
+            },
+        });
+        let mut variables = VariableCompiler::new();
+        variables.load_from_json(&sprite);
+        let mut instructions = Vec::new();
+        let mut compiler = ThreadCompiler::new(
+            &mut variables,
+            &mut instructions,
+            0,
+            &sprite,
+            std::slice::from_ref(&sprite),
+        );
+        compiler.compile_stack(&json!({}), "a");
+        compiler.finish();
+        let kinds: Vec<_> = compiler.diagnostics.iter().map(|d| d.kind).collect();
+        assert_eq!(kinds, [DiagnosticKind::EmptyInput]);
+        compiler.link().unwrap();
+
+        // The body is still there, and nothing jumps out of the loop.
+        assert!(compiler.instructions.iter().any(|instruction| matches!(
+            instruction,
+            Instruction::OperatorAdd(Value::Pointer(0), Value::Pointer(0), _)
+        )));
+        for instruction in compiler.instructions.iter() {
+            match instruction {
+                Instruction::FlowIfJump(condition, _) => {
+                    assert_eq!(*condition, Value::Boolean(true))
+                }
+                Instruction::FlowIfNotJump(..) => panic!("{instruction:?} can leave the loop"),
+                _ => {}
+            }
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use crate::{
+    bc_compiler::{bc_comp_diagnostics::DiagnosticKind, bc_comp_main::ThreadCompiler},
     interpreter::{Instruction, Value},
-    bc_compiler::bc_comp_main::ThreadCompiler,
 };
 
 impl<'a> ThreadCompiler<'a> {
@@ -8,58 +8,39 @@ impl<'a> ThreadCompiler<'a> {
         let register = self.register_malloc();
         self.register_set_to_input(current_block, register, "SIZE");
         self.instructions
-            .push(Instruction::LooksSetSize(Value::Pointer(
-                self.register_get_variable_id(register),
-            )));
+            .push(Instruction::LooksSetSize(Value::Register(register)));
         self.register_free(register);
         None
     }
 
     pub fn c_looks_switch_costume(&mut self, current_block: &serde_json::Value) -> Option<usize> {
-        let sub_block = self
-            .get_block(
-                current_block["inputs"]["COSTUME"].as_array().unwrap()[1]
-                    .as_str()
-                    .unwrap(),
-            )
-            .unwrap();
-
-        let costume_value: Value;
-
-        if sub_block["opcode"].as_str().unwrap() == "looks_costume" {
-            let costume = sub_block["fields"]["COSTUME"].as_array().unwrap()[0]
-                .as_str()
-                .unwrap()
-                .to_string();
-            costume_value = Value::String(costume);
-        } else {
-            match self.compile_block(&sub_block) {
-                None => costume_value = Value::Number(0.0),
-                Some(n) => {
-                    costume_value = Value::Pointer(self.register_get_variable_id(n));
-                    self.register_free(n)
-                }
-            }
-        }
-
+        let (costume, costume_register) = self.get_menu_input(current_block, "COSTUME", "COSTUME");
         self.instructions
-            .push(Instruction::LooksSetCostume(costume_value));
+            .push(Instruction::LooksSetCostume(costume));
+        if let Some(costume_register) = costume_register {
+            self.register_free(costume_register);
+        }
         None
     }
 
     pub fn c_looks_get_costume(&mut self, current_block: &serde_json::Value) -> Option<usize> {
+        let number_name = self.get_field(current_block, "NUMBER_NAME")?;
         let register = self.register_malloc();
-        if current_block["fields"]["NUMBER_NAME"].as_array().unwrap()[0]
-            .as_str()
-            .unwrap()
-            == "number"
-        {
+        if number_name == "number" {
             self.instructions
-                .push(Instruction::LooksGetCostumeNumber(Value::Pointer(
-                    self.register_get_variable_id(register),
+                .push(Instruction::LooksGetCostumeNumber(Value::Register(
+                    register,
                 )))
         } else {
-            todo!()
+            self.report(
+                current_block,
+                DiagnosticKind::UnsupportedBlock,
+                format!("Costume {number_name:?} isn't supported"),
+            );
+            self.instructions.push(Instruction::MemoryStore(
+                Value::Register(register),
+                Value::String(String::new()),
+            ));
         }
         Some(register)
     }
@@ -1,6 +1,6 @@
 use crate::{
-    interpreter::{Instruction, Value},
     bc_compiler::bc_comp_main::ThreadCompiler,
+    interpreter::{Instruction, Value},
 };
 
 impl<'a> ThreadCompiler<'a> {
@@ -12,8 +12,8 @@ impl<'a> ThreadCompiler<'a> {
         self.register_set_to_input(current_block, y, "Y");
 
         self.instructions.push(Instruction::MotionSetXY(
-            Value::Pointer(self.register_get_variable_id(x)),
-            Value::Pointer(self.register_get_variable_id(y)),
+            Value::Register(x),
+            Value::Register(y),
         ));
 
         self.register_free(x);
@@ -25,9 +25,7 @@ impl<'a> ThreadCompiler<'a> {
         let x = self.register_malloc();
         self.register_set_to_input(current_block, x, "X");
         self.instructions
-            .push(Instruction::MotionSetX(Value::Pointer(
-                self.register_get_variable_id(x),
-            )));
+            .push(Instruction::MotionSetX(Value::Register(x)));
         self.register_free(x);
         None
     }
@@ -36,9 +34,7 @@ impl<'a> ThreadCompiler<'a> {
         let y = self.register_malloc();
         self.register_set_to_input(current_block, y, "Y");
         self.instructions
-            .push(Instruction::MotionSetY(Value::Pointer(
-                self.register_get_variable_id(y),
-            )));
+            .push(Instruction::MotionSetY(Value::Register(y)));
         self.register_free(y);
         None
     }
@@ -47,9 +43,7 @@ impl<'a> ThreadCompiler<'a> {
         let x = self.register_malloc();
         self.register_set_to_input(current_block, x, "DX");
         self.instructions
-            .push(Instruction::MotionChangeX(Value::Pointer(
-                self.register_get_variable_id(x),
-            )));
+            .push(Instruction::MotionChangeX(Value::Register(x)));
         self.register_free(x);
         None
     }
@@ -58,9 +52,7 @@ impl<'a> ThreadCompiler<'a> {
         let y = self.register_malloc();
         self.register_set_to_input(current_block, y, "DY");
         self.instructions
-            .push(Instruction::MotionChangeY(Value::Pointer(
-                self.register_get_variable_id(y),
-            )));
+            .push(Instruction::MotionChangeY(Value::Register(y)));
         self.register_free(y);
         None
     }
@@ -68,18 +60,14 @@ impl<'a> ThreadCompiler<'a> {
     pub fn c_motion_get_x(&mut self) -> Option<usize> {
         let register = self.register_malloc();
         self.instructions
-            .push(Instruction::MotionGetX(Value::Pointer(
-                self.register_get_variable_id(register),
-            )));
+            .push(Instruction::MotionGetX(Value::Register(register)));
         Some(register)
     }
 
     pub fn c_motion_get_y(&mut self) -> Option<usize> {
         let register = self.register_malloc();
         self.instructions
-            .push(Instruction::MotionGetY(Value::Pointer(
-                self.register_get_variable_id(register),
-            )));
+            .push(Instruction::MotionGetY(Value::Register(register)));
         Some(register)
     }
 }
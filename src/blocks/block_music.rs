@@ -0,0 +1,77 @@
+use crate::{
+    bc_compiler::bc_comp_main::ThreadCompiler,
+    interpreter::{Instruction, Value},
+};
+
+impl<'a> ThreadCompiler<'a> {
+    pub fn c_music_play_drum(&mut self, current_block: &serde_json::Value) -> Option<usize> {
+        let (drum, drum_register) = self.get_menu_input(current_block, "DRUM", "DRUM");
+        let beats = self.register_malloc();
+        self.register_set_to_input(current_block, beats, "BEATS");
+        self.instructions
+            .push(Instruction::MusicPlayDrum(drum, Value::Register(beats)));
+        self.register_free(beats);
+        if let Some(drum_register) = drum_register {
+            self.register_free(drum_register);
+        }
+        None
+    }
+
+    pub fn c_music_play_note(&mut self, current_block: &serde_json::Value) -> Option<usize> {
+        // The note picker is a shadow block with the note number in a field.
+        let (note, note_register) = self.get_menu_input(current_block, "NOTE", "NOTE");
+        let beats = self.register_malloc();
+        self.register_set_to_input(current_block, beats, "BEATS");
+        self.instructions
+            .push(Instruction::MusicPlayNote(note, Value::Register(beats)));
+        self.register_free(beats);
+        if let Some(note_register) = note_register {
+            self.register_free(note_register);
+        }
+        None
+    }
+
+    pub fn c_music_rest(&mut self, current_block: &serde_json::Value) -> Option<usize> {
+        let beats = self.register_malloc();
+        self.register_set_to_input(current_block, beats, "BEATS");
+        self.instructions
+            .push(Instruction::MusicRest(Value::Register(beats)));
+        self.register_free(beats);
+        None
+    }
+
+    pub fn c_music_set_tempo(&mut self, current_block: &serde_json::Value) -> Option<usize> {
+        let tempo = self.register_malloc();
+        self.register_set_to_input(current_block, tempo, "TEMPO");
+        self.instructions
+            .push(Instruction::MusicSetTempo(Value::Register(tempo)));
+        self.register_free(tempo);
+        None
+    }
+
+    pub fn c_music_change_tempo(&mut self, current_block: &serde_json::Value) -> Option<usize> {
+        let tempo = self.register_malloc();
+        self.register_set_to_input(current_block, tempo, "TEMPO");
+        self.instructions
+            .push(Instruction::MusicChangeTempo(Value::Register(tempo)));
+        self.register_free(tempo);
+        None
+    }
+
+    pub fn c_music_get_tempo(&mut self) -> Option<usize> {
+        let register = self.register_malloc();
+        self.instructions
+            .push(Instruction::MusicGetTempo(Value::Register(register)));
+        Some(register)
+    }
+
+    pub fn c_music_set_instrument(&mut self, current_block: &serde_json::Value) -> Option<usize> {
+        let (instrument, register) = self.get_menu_input(current_block, "INSTRUMENT", "INSTRUMENT");
+        self.instructions
+            .push(Instruction::MusicSetInstrument(instrument));
+        if let Some(register) = register {
+            self.register_free(register);
+        }
+        None
+    }
+}
@@ -1,7 +1,6 @@
 use crate::{
-    ansi_codes,
+    bc_compiler::{bc_comp_diagnostics::DiagnosticKind, bc_comp_main::ThreadCompiler},
     interpreter::{Instruction, Value},
-    bc_compiler::bc_comp_main::ThreadCompiler,
 };
 
 impl<'a> ThreadCompiler<'a> {
@@ -18,9 +17,9 @@ impl<'a> ThreadCompiler<'a> {
         let (register1, register2) = self.get_operator_registers(current_block);
 
         self.instructions.push(Instruction::OperatorAdd(
-            Value::Pointer(self.register_get_variable_id(register1)),
-            Value::Pointer(self.register_get_variable_id(register1)),
-            Value::Pointer(self.register_get_variable_id(register2)),
+            Value::Register(register1),
+            Value::Register(register1),
+            Value::Register(register2),
         ));
 
         self.register_free(register2);
@@ -31,9 +30,9 @@ impl<'a> ThreadCompiler<'a> {
         let (register1, register2) = self.get_operator_registers(current_block);
 
         self.instructions.push(Instruction::OperatorSubtract(
-            Value::Pointer(self.register_get_variable_id(register1)),
-            Value::Pointer(self.register_get_variable_id(register1)),
-            Value::Pointer(self.register_get_variable_id(register2)),
+            Value::Register(register1),
+            Value::Register(register1),
+            Value::Register(register2),
         ));
 
         self.register_free(register2);
@@ -44,9 +43,9 @@ impl<'a> ThreadCompiler<'a> {
         let (register1, register2) = self.get_operator_registers(current_block);
 
         self.instructions.push(Instruction::OperatorMultiply(
-            Value::Pointer(self.register_get_variable_id(register1)),
-            Value::Pointer(self.register_get_variable_id(register1)),
-            Value::Pointer(self.register_get_variable_id(register2)),
+            Value::Register(register1),
+            Value::Register(register1),
+            Value::Register(register2),
         ));
 
         self.register_free(register2);
@@ -57,9 +56,9 @@ impl<'a> ThreadCompiler<'a> {
         let (register1, register2) = self.get_operator_registers(current_block);
 
         self.instructions.push(Instruction::OperatorDivide(
-            Value::Pointer(self.register_get_variable_id(register1)),
-            Value::Pointer(self.register_get_variable_id(register1)),
-            Value::Pointer(self.register_get_variable_id(register2)),
+            Value::Register(register1),
+            Value::Register(register1),
+            Value::Register(register2),
         ));
 
         self.register_free(register2);
@@ -70,9 +69,9 @@ impl<'a> ThreadCompiler<'a> {
         let (register1, register2) = self.get_operator_registers(current_block);
 
         self.instructions.push(Instruction::OperatorModulo(
-            Value::Pointer(self.register_get_variable_id(register1)),
-            Value::Pointer(self.register_get_variable_id(register1)),
-            Value::Pointer(self.register_get_variable_id(register2)),
+            Value::Register(register1),
+            Value::Register(register1),
+            Value::Register(register2),
         ));
 
         self.register_free(register2);
@@ -87,9 +86,9 @@ impl<'a> ThreadCompiler<'a> {
         self.register_set_to_input(current_block, register2, "OPERAND2");
 
         self.instructions.push(Instruction::OperatorGreater(
-            Value::Pointer(self.register_get_variable_id(register1)),
-            Value::Pointer(self.register_get_variable_id(register1)),
-            Value::Pointer(self.register_get_variable_id(register2)),
+            Value::Register(register1),
+            Value::Register(register1),
+            Value::Register(register2),
         ));
 
         self.register_free(register2);
@@ -104,9 +103,9 @@ impl<'a> ThreadCompiler<'a> {
         self.register_set_to_input(current_block, register2, "OPERAND2");
 
         self.instructions.push(Instruction::OperatorLesser(
-            Value::Pointer(self.register_get_variable_id(register1)),
-            Value::Pointer(self.register_get_variable_id(register1)),
-            Value::Pointer(self.register_get_variable_id(register2)),
+            Value::Register(register1),
+            Value::Register(register1),
+            Value::Register(register2),
         ));
 
         self.register_free(register2);
@@ -121,9 +120,9 @@ impl<'a> ThreadCompiler<'a> {
         self.register_set_to_input(current_block, register2, "OPERAND2");
 
         self.instructions.push(Instruction::OperatorEquals(
-            Value::Pointer(self.register_get_variable_id(register1)),
-            Value::Pointer(self.register_get_variable_id(register1)),
-            Value::Pointer(self.register_get_variable_id(register2)),
+            Value::Register(register1),
+            Value::Register(register1),
+            Value::Register(register2),
         ));
 
         self.register_free(register2);
@@ -131,74 +130,69 @@ impl<'a> ThreadCompiler<'a> {
     }
 
     pub fn c_operators_mathop(&mut self, current_block: &serde_json::Value) -> Option<usize> {
+        let operator = self.get_field(current_block, "OPERATOR")?;
         let register = self.register_malloc();
         let num_register = self.register_malloc();
         self.register_set_to_input(current_block, num_register, "NUM");
-        let operator = current_block["fields"]["OPERATOR"].as_array().unwrap()[0]
-            .as_str()
-            .unwrap();
-        match operator {
+        match operator.as_str() {
             "e ^" => self.instructions.push(Instruction::OperatorERaised(
-                Value::Pointer(self.register_get_variable_id(register)),
-                Value::Pointer(num_register),
+                Value::Register(register),
+                Value::Register(num_register),
             )),
             "10 ^" => self.instructions.push(Instruction::OperatorPower(
-                Value::Pointer(self.register_get_variable_id(register)),
+                Value::Register(register),
                 Value::Number(10.0),
-                Value::Pointer(num_register),
+                Value::Register(num_register),
             )),
             "sin" => self.instructions.push(Instruction::OperatorSin(
-                Value::Pointer(self.register_get_variable_id(register)),
-                Value::Pointer(num_register),
+                Value::Register(register),
+                Value::Register(num_register),
             )),
             "cos" => self.instructions.push(Instruction::OperatorCos(
-                Value::Pointer(self.register_get_variable_id(register)),
-                Value::Pointer(num_register),
+                Value::Register(register),
+                Value::Register(num_register),
             )),
             "tan" => self.instructions.push(Instruction::OperatorTan(
-                Value::Pointer(self.register_get_variable_id(register)),
-                Value::Pointer(num_register),
+                Value::Register(register),
+                Value::Register(num_register),
             )),
             "abs" => self.instructions.push(Instruction::OperatorAbs(
-                Value::Pointer(self.register_get_variable_id(register)),
-                Value::Pointer(num_register),
+                Value::Register(register),
+                Value::Register(num_register),
             )),
             "sqrt" => self.instructions.push(Instruction::OperatorSqrt(
-                Value::Pointer(self.register_get_variable_id(register)),
-                Value::Pointer(num_register),
+                Value::Register(register),
+                Value::Register(num_register),
             )),
             "asin" => self.instructions.push(Instruction::OperatorASin(
-                Value::Pointer(self.register_get_variable_id(register)),
-                Value::Pointer(num_register),
+                Value::Register(register),
+                Value::Register(num_register),
             )),
             "acos" => self.instructions.push(Instruction::OperatorACos(
-                Value::Pointer(self.register_get_variable_id(register)),
-                Value::Pointer(num_register),
+                Value::Register(register),
+                Value::Register(num_register),
             )),
             "ln" => self.instructions.push(Instruction::OperatorLn(
-                Value::Pointer(self.register_get_variable_id(register)),
-                Value::Pointer(num_register),
+                Value::Register(register),
+                Value::Register(num_register),
             )),
             "log" => self.instructions.push(Instruction::OperatorLog(
-                Value::Pointer(self.register_get_variable_id(register)),
-                Value::Pointer(num_register),
+                Value::Register(register),
+                Value::Register(num_register),
             )),
             "floor" => self.instructions.push(Instruction::OperatorFloor(
-                Value::Pointer(self.register_get_variable_id(register)),
-                Value::Pointer(num_register),
+                Value::Register(register),
+                Value::Register(num_register),
             )),
             "ceiling" => self.instructions.push(Instruction::OperatorCeiling(
-                Value::Pointer(self.register_get_variable_id(register)),
-                Value::Pointer(num_register),
+                Value::Register(register),
+                Value::Register(num_register),
             )),
-            _ => {
-                eprintln!(
-                    "{}[unimplemented mathop]{} {}",
-                    ansi_codes::RED,
-                    ansi_codes::RESET,
-                    operator
-                )
-            }
+            _ => self.report(
+                current_block,
+                DiagnosticKind::UnsupportedBlock,
+                format!("Unsupported operator {operator:?}"),
+            ),
         }
         self.register_free(num_register);
         Some(register)
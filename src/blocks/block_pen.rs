@@ -1,4 +1,7 @@
-use crate::{interpreter::Instruction, bc_compiler::bc_comp_main::ThreadCompiler};
+use crate::{
+    bc_compiler::bc_comp_main::ThreadCompiler,
+    interpreter::{Instruction, Value},
+};
 
 impl<'a> ThreadCompiler<'a> {
     pub fn c_pen_clear(&mut self) -> Option<usize> {
@@ -24,10 +27,75 @@ impl<'a> ThreadCompiler<'a> {
     pub fn c_pen_set_size(&mut self, current_block: &serde_json::Value) -> Option<usize> {
         let size = self.register_malloc();
         self.register_set_to_input(current_block, size, "SIZE");
-        self.instructions.push(Instruction::PenSetRadius(
-            crate::interpreter::Value::Pointer(self.register_get_variable_id(size)),
-        ));
+        self.instructions
+            .push(Instruction::PenSetRadius(Value::Register(size)));
+        self.register_free(size);
+        None
+    }
+
+    pub fn c_pen_change_size(&mut self, current_block: &serde_json::Value) -> Option<usize> {
+        let size = self.register_malloc();
+        self.register_set_to_input(current_block, size, "SIZE");
+        self.instructions
+            .push(Instruction::PenChangeRadius(Value::Register(size)));
         self.register_free(size);
         None
     }
+
+    pub fn c_pen_set_color(&mut self, current_block: &serde_json::Value) -> Option<usize> {
+        let color = self.register_malloc();
+        self.register_set_to_input(current_block, color, "COLOR");
+        self.instructions
+            .push(Instruction::PenSetColor(Value::Register(color)));
+        self.register_free(color);
+        None
+    }
+
+    pub fn c_pen_set_color_param(&mut self, current_block: &serde_json::Value) -> Option<usize> {
+        let (param, param_register) =
+            self.get_menu_input(current_block, "COLOR_PARAM", "colorParam");
+        let value = self.register_malloc();
+        self.register_set_to_input(current_block, value, "VALUE");
+        self.instructions
+            .push(Instruction::PenSetColorParam(param, Value::Register(value)));
+        self.register_free(value);
+        if let Some(param_register) = param_register {
+            self.register_free(param_register);
+        }
+        None
+    }
+
+    pub fn c_pen_change_color_param(&mut self, current_block: &serde_json::Value) -> Option<usize> {
+        let (param, param_register) =
+            self.get_menu_input(current_block, "COLOR_PARAM", "colorParam");
+        let value = self.register_malloc();
+        self.register_set_to_input(current_block, value, "VALUE");
+        self.instructions.push(Instruction::PenChangeColorParam(
+            param,
+            Value::Register(value),
+        ));
+        self.register_free(value);
+        if let Some(param_register) = param_register {
+            self.register_free(param_register);
+        }
+        None
+    }
+
+    pub fn c_pen_set_hue(&mut self, current_block: &serde_json::Value) -> Option<usize> {
+        let hue = self.register_malloc();
+        self.register_set_to_input(current_block, hue, "HUE");
+        self.instructions
+            .push(Instruction::PenSetHue(Value::Register(hue)));
+        self.register_free(hue);
+        None
+    }
+
+    pub fn c_pen_set_shade(&mut self, current_block: &serde_json::Value) -> Option<usize> {
+        let shade = self.register_malloc();
+        self.register_set_to_input(current_block, shade, "SHADE");
+        self.instructions
+            .push(Instruction::PenSetShade(Value::Register(shade)));
+        self.register_free(shade);
+        None
+    }
 }
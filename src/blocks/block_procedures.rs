@@ -0,0 +1,225 @@
+use crate::{
+    bc_compiler::{bc_comp_diagnostics::DiagnosticKind, bc_comp_main::ThreadCompiler},
+    interpreter::{Instruction, Value},
+};
+
+// A custom block being inlined, with the registers holding its arguments.
+pub struct ProcedureCall {
+    pub proccode: String,
+    pub arguments: Vec<(String, usize)>,
+}
+
+impl<'a> ThreadCompiler<'a> {
+    // Custom blocks are compiled into every script that calls them, so each
+    // call gets its own copy of the definition with the arguments in registers.
+    pub fn c_procedures_call(&mut self, current_block: &serde_json::Value) -> Option<usize> {
+        let mutation = &current_block["mutation"];
+        let Some(proccode) = mutation["proccode"].as_str() else {
+            self.report(
+                current_block,
+                DiagnosticKind::BadJson,
+                "Custom block call has no proccode".to_owned(),
+            );
+            return None;
+        };
+        if self
+            .procedure_calls
+            .iter()
+            .any(|call| call.proccode == proccode)
+        {
+            self.report(
+                current_block,
+                DiagnosticKind::UnsupportedBlock,
+                format!("Custom block {proccode:?} calls itself, which isn't supported"),
+            );
+            return None;
+        }
+        let Some(prototype) = self.find_prototype(proccode) else {
+            self.report(
+                current_block,
+                DiagnosticKind::MissingInput,
+                format!("Custom block {proccode:?} has no definition"),
+            );
+            return None;
+        };
+        let definition = prototype["parent"]
+            .as_str()
+            .and_then(|definition_id| self.get_block(definition_id));
+        let Some(definition) = definition else {
+            self.report(
+                current_block,
+                DiagnosticKind::BadJson,
+                format!("Custom block {proccode:?} has a prototype but no definition block"),
+            );
+            return None;
+        };
+
+        let ids = parse_json_list(&prototype["mutation"]["argumentids"]);
+        let names = parse_json_list(&prototype["mutation"]["argumentnames"]);
+        let mut arguments = Vec::with_capacity(ids.len());
+        for (id, name) in ids.iter().zip(names) {
+            let register = self.register_malloc();
+            // Empty inputs, like a boolean input with nothing in it.
+            if current_block["inputs"][id].is_null() {
+                self.instructions.push(Instruction::MemoryStore(
+                    Value::Register(register),
+                    Value::String(String::new()),
+                ));
+            } else {
+                self.register_set_to_input(current_block, register, id);
+            }
+            arguments.push((name, register));
+        }
+
+        // Anything called from a warp mode custom block runs in warp mode too.
+        let was_warp = self.warp;
+        self.warp |= is_true(&prototype["mutation"]["warp"]);
+        self.procedure_calls.push(ProcedureCall {
+            proccode: proccode.to_owned(),
+            arguments,
+        });
+        if let Some(first_block_id) = definition["next"].as_str() {
+            self.compile_stack(&definition, first_block_id);
+        }
+        let call = self.procedure_calls.pop().unwrap();
+        self.warp = was_warp;
+        for (_, register) in call.arguments {
+            self.register_free(register);
+        }
+
+        None
+    }
+
+    pub fn c_argument_reporter(&mut self, current_block: &serde_json::Value) -> Option<usize> {
+        let name = current_block["fields"]["VALUE"][0]
+            .as_str()
+            .unwrap_or_default();
+        let argument = self.procedure_calls.last().and_then(|call| {
+            call.arguments
+                .iter()
+                .find(|(argument_name, _)| argument_name == name)
+                .map(|(_, register)| *register)
+        });
+        // Copied, since whoever uses the result frees its register.
+        let register = self.register_malloc();
+        self.instructions.push(Instruction::MemoryStore(
+            Value::Register(register),
+            match argument {
+                Some(argument) => Value::Register(argument),
+                // Outside of its custom block, like in Scratch.
+                None => Value::String(String::new()),
+            },
+        ));
+        Some(register)
+    }
+
+    fn find_prototype(&self, proccode: &str) -> Option<serde_json::Value> {
+        self.sprite["blocks"]
+            .as_object()?
+            .values()
+            .find(|block| {
+                block["opcode"] == "procedures_prototype"
+                    && block["mutation"]["proccode"] == proccode
+            })
+            .cloned()
+    }
+}
+
+// Mutations hold lists as JSON inside a string.
+fn parse_json_list(value: &serde_json::Value) -> Vec<String> {
+    value
+        .as_str()
+        .and_then(|list| serde_json::from_str(list).ok())
+        .unwrap_or_default()
+}
+
+// Saved as either a boolean or a string, depending on what saved the project.
+fn is_true(value: &serde_json::Value) -> bool {
+    value.as_bool().unwrap_or(value == "true")
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::{
+        assembler,
+        bc_compiler::{bc_comp_main::ThreadCompiler, bc_comp_variable_manager::VariableCompiler},
+        interpreter::{Memory, Registers, Value},
+    };
+
+    // A script calling a custom block that adds its argument to a variable 100 times.
+    fn counting_sprite(warp: bool) -> serde_json::Value {
+        json!({
+            "name": "Sprite1",
+            "variables": { "counter": ["counter", 0] },
+            "blocks": {
+                "a": { "opcode": "procedures_call", "next": "b",
+                       "inputs": { "arg1": [1, [4, "2"]] },
+                       "mutation": { "proccode": "count by %s" } },
+                "b": { "opcode": "procedures_call", "next": null, "inputs": {},
+                       "mutation": { "proccode": "count by %s" } },
+                "definition": { "opcode": "procedures_definition", "next": "repeat",
+                                "inputs": { "custom_block": [1, "prototype"] } },
+                "prototype": { "opcode": "procedures_prototype", "parent": "definition",
+                               "mutation": {
+                                   "proccode": "count by %s",
+                                   "argumentids": "[\"arg1\"]",
+                                   "argumentnames": "[\"step\"]",
+                                   "warp": if warp { "true" } else { "false" },
+                               } },
+                "repeat": { "opcode": "control_repeat", "next": null,
+                            "inputs": { "TIMES": [1, [4, "100"]], "SUBSTACK": [2, "change"] } },
+                "change": { "opcode": "data_changevariableby", "next": null,
+                            "inputs": { "VALUE": [3, "step", [4, "1"]] },
+                            "fields": { "VARIABLE": ["counter", "counter"] } },
+                "step": { "opcode": "argument_reporter_string_number", "next": null,
+                          "fields": { "VALUE": ["step", null] } },
+            },
+        })
+    }
+
+    // Compiles the stack starting at block "a", runs it and returns the counter and how many frames it took.
+    fn run_counting_script(warp: bool) -> (Value, usize) {
+        let sprite = counting_sprite(warp);
+        let mut variables = VariableCompiler::new();
+        variables.load_from_json(&sprite);
+        let mut instructions = Vec::new();
+        let mut compiler = ThreadCompiler::new(
+            &mut variables,
+            &mut instructions,
+            0,
+            &sprite,
+            std::slice::from_ref(&sprite),
+        );
+        compiler.compile_stack(&json!({}), "a");
+        compiler.finish();
+        assert!(
+            compiler.diagnostics.is_empty(),
+            "{:?}",
+            compiler.diagnostics
+        );
+        compiler.link().unwrap();
+        let mut registers = Registers::new(compiler.temp_variables.len());
+
+        let mut variables = variables.finish_processing();
+        let mut memory = Memory::new(&mut variables, &mut registers, &mut []);
+        let frames = assembler::run(&instructions, &mut memory).unwrap();
+        (variables[0].clone(), frames)
+    }
+
+    #[test]
+    fn warp_loops_finish_within_a_frame() {
+        // 100 times 2, then 100 times the empty argument, which counts as 0.
+        let (counter, frames) = run_counting_script(true);
+        assert_eq!(counter, Value::Number(200.0));
+        assert_eq!(frames, 0);
+    }
+
+    #[test]
+    fn other_loops_take_a_frame_per_repeat() {
+        let (counter, frames) = run_counting_script(false);
+        assert_eq!(counter, Value::Number(200.0));
+        assert_eq!(frames, 200);
+    }
+}
@@ -1,15 +1,165 @@
 use crate::{
+    bc_compiler::{bc_comp_diagnostics::DiagnosticKind, bc_comp_main::ThreadCompiler},
     interpreter::{Instruction, Value},
-    bc_compiler::bc_comp_main::ThreadCompiler,
 };
 
 impl<'a> ThreadCompiler<'a> {
     pub fn c_sensing_timer(&mut self) -> Option<usize> {
         let register = self.register_malloc();
         self.instructions
-            .push(Instruction::SensingTimer(Value::Pointer(
-                self.register_get_variable_id(register),
-            )));
+            .push(Instruction::SensingTimer(Value::Register(register)));
         Some(register)
     }
+
+    pub fn c_sensing_ask_and_wait(&mut self, current_block: &serde_json::Value) -> Option<usize> {
+        let question = self.register_malloc();
+        self.register_set_to_input(current_block, question, "QUESTION");
+        // The stage can't show a speech bubble, so it always asks in the prompt.
+        let is_stage = self.sprite["isStage"].as_bool().unwrap_or(false);
+        self.instructions.push(Instruction::SensingAskAndWait(
+            Value::Register(question),
+            !is_stage,
+        ));
+        self.register_free(question);
+        None
+    }
+
+    pub fn c_sensing_answer(&mut self) -> Option<usize> {
+        let register = self.register_malloc();
+        self.instructions
+            .push(Instruction::SensingAnswer(Value::Register(register)));
+        Some(register)
+    }
+
+    pub fn c_sensing_reset_timer(&mut self) -> Option<usize> {
+        self.instructions.push(Instruction::SensingResetTimer);
+        None
+    }
+
+    pub fn c_sensing_distance_to(&mut self, current_block: &serde_json::Value) -> Option<usize> {
+        let register = self.register_malloc();
+        if self.sprite["isStage"].as_bool().unwrap_or(false) {
+            // The stage is never near anything.
+            self.instructions.push(Instruction::MemoryStore(
+                Value::Register(register),
+                Value::Number(10000.0),
+            ));
+            return Some(register);
+        }
+        let (target, target_register) =
+            self.get_menu_input(current_block, "DISTANCETOMENU", "DISTANCETOMENU");
+        self.instructions.push(Instruction::SensingDistanceTo(
+            Value::Register(register),
+            target,
+        ));
+        if let Some(target_register) = target_register {
+            self.register_free(target_register);
+        }
+        Some(register)
+    }
+
+    pub fn c_sensing_of(&mut self, current_block: &serde_json::Value) -> Option<usize> {
+        let property = self.get_field(current_block, "PROPERTY")?;
+        let register = self.register_malloc();
+        let (object, object_register) = self.get_menu_input(current_block, "OBJECT", "OBJECT");
+
+        let is_builtin_property = matches!(
+            property.as_str(),
+            "x position"
+                | "y position"
+                | "direction"
+                | "costume #"
+                | "costume name"
+                | "size"
+                | "volume"
+                | "backdrop #"
+                | "backdrop name"
+        );
+        if is_builtin_property {
+            self.instructions.push(Instruction::SensingOf(
+                Value::Register(register),
+                property,
+                object,
+            ));
+        } else {
+            // Anything else is a variable of the sprite, which we can look up right now.
+            let variable = match &object {
+                Value::String(sprite_name) => self.get_variable_of_sprite(sprite_name, &property),
+                _ => {
+                    self.report(
+                        current_block,
+                        DiagnosticKind::UnsupportedBlock,
+                        format!(
+                            "Variable {property:?} of a sprite from a reporter isn't supported"
+                        ),
+                    );
+                    None
+                }
+            };
+            self.instructions.push(Instruction::MemoryStore(
+                Value::Register(register),
+                match variable {
+                    Some(id) => Value::Pointer(id),
+                    None => Value::Number(0.0),
+                },
+            ));
+        }
+
+        if let Some(object_register) = object_register {
+            self.register_free(object_register);
+        }
+        Some(register)
+    }
+
+    pub fn c_sensing_current(&mut self, current_block: &serde_json::Value) -> Option<usize> {
+        let menu = self.get_field(current_block, "CURRENTMENU")?;
+        let register = self.register_malloc();
+        self.instructions
+            .push(Instruction::SensingCurrent(Value::Register(register), menu));
+        Some(register)
+    }
+
+    pub fn c_sensing_days_since_2000(&mut self) -> Option<usize> {
+        let register = self.register_malloc();
+        self.instructions
+            .push(Instruction::SensingDaysSince2000(Value::Register(register)));
+        Some(register)
+    }
+
+    pub fn c_sensing_username(&mut self) -> Option<usize> {
+        let register = self.register_malloc();
+        self.instructions
+            .push(Instruction::SensingUsername(Value::Register(register)));
+        Some(register)
+    }
+
+    pub fn c_sensing_loudness(&mut self) -> Option<usize> {
+        // There's no microphone support, Scratch reports -1 when it can't get one.
+        let register = self.register_malloc();
+        self.instructions.push(Instruction::MemoryStore(
+            Value::Register(register),
+            Value::Number(-1.0),
+        ));
+        Some(register)
+    }
+
+    pub fn c_sensing_set_drag_mode(&mut self) -> Option<usize> {
+        // Sprites can't be dragged with the mouse yet, so there's nothing to change.
+        None
+    }
+
+    fn get_variable_of_sprite(&self, sprite_name: &str, variable_name: &str) -> Option<usize> {
+        let target = self.targets.iter().find(|target| {
+            if sprite_name == "_stage_" {
+                target["isStage"].as_bool().unwrap_or(false)
+            } else {
+                target["name"].as_str() == Some(sprite_name)
+            }
+        })?;
+        let (variable_id, _) = target["variables"]
+            .as_object()?
+            .iter()
+            .find(|(_, variable)| variable[0].as_str() == Some(variable_name))?;
+        self.variables.get_id(variable_id)
+    }
 }
@@ -0,0 +1,90 @@
+use crate::{
+    bc_compiler::bc_comp_main::ThreadCompiler,
+    interpreter::{Instruction, Value},
+};
+
+impl<'a> ThreadCompiler<'a> {
+    pub fn c_sound_play(&mut self, current_block: &serde_json::Value) -> Option<usize> {
+        let (sound, register) = self.get_menu_input(current_block, "SOUND_MENU", "SOUND_MENU");
+        self.instructions.push(Instruction::SoundPlay(sound));
+        if let Some(register) = register {
+            self.register_free(register);
+        }
+        None
+    }
+
+    pub fn c_sound_play_until_done(&mut self, current_block: &serde_json::Value) -> Option<usize> {
+        let (sound, register) = self.get_menu_input(current_block, "SOUND_MENU", "SOUND_MENU");
+        self.instructions
+            .push(Instruction::SoundPlayUntilDone(sound));
+        if let Some(register) = register {
+            self.register_free(register);
+        }
+        None
+    }
+
+    pub fn c_sound_stop_all(&mut self) -> Option<usize> {
+        self.instructions.push(Instruction::SoundStopAll);
+        None
+    }
+
+    pub fn c_sound_set_effect(&mut self, current_block: &serde_json::Value) -> Option<usize> {
+        let effect = get_effect_field(current_block);
+        let value = self.register_malloc();
+        self.register_set_to_input(current_block, value, "VALUE");
+        self.instructions
+            .push(Instruction::SoundSetEffect(effect, Value::Register(value)));
+        self.register_free(value);
+        None
+    }
+
+    pub fn c_sound_change_effect(&mut self, current_block: &serde_json::Value) -> Option<usize> {
+        let effect = get_effect_field(current_block);
+        let value = self.register_malloc();
+        self.register_set_to_input(current_block, value, "VALUE");
+        self.instructions.push(Instruction::SoundChangeEffect(
+            effect,
+            Value::Register(value),
+        ));
+        self.register_free(value);
+        None
+    }
+
+    pub fn c_sound_clear_effects(&mut self) -> Option<usize> {
+        self.instructions.push(Instruction::SoundClearEffects);
+        None
+    }
+
+    pub fn c_sound_set_volume(&mut self, current_block: &serde_json::Value) -> Option<usize> {
+        let volume = self.register_malloc();
+        self.register_set_to_input(current_block, volume, "VOLUME");
+        self.instructions
+            .push(Instruction::SoundSetVolume(Value::Register(volume)));
+        self.register_free(volume);
+        None
+    }
+
+    pub fn c_sound_change_volume(&mut self, current_block: &serde_json::Value) -> Option<usize> {
+        let volume = self.register_malloc();
+        self.register_set_to_input(current_block, volume, "VOLUME");
+        self.instructions
+            .push(Instruction::SoundChangeVolume(Value::Register(volume)));
+        self.register_free(volume);
+        None
+    }
+
+    pub fn c_sound_get_volume(&mut self) -> Option<usize> {
+        let register = self.register_malloc();
+        self.instructions
+            .push(Instruction::SoundGetVolume(Value::Register(register)));
+        Some(register)
+    }
+}
+
+// "PITCH" or "PAN".
+fn get_effect_field(current_block: &serde_json::Value) -> String {
+    current_block["fields"]["EFFECT"][0]
+        .as_str()
+        .unwrap_or_default()
+        .to_uppercase()
+}
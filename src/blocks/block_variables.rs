@@ -1,25 +1,50 @@
 use crate::{
-    interpreter::{Instruction, Value},
     bc_compiler::bc_comp_main::ThreadCompiler,
+    interpreter::{Instruction, Value},
 };
 
 impl<'a> ThreadCompiler<'a> {
     pub fn c_variables_set(&mut self, current_block: &serde_json::Value) -> Option<usize> {
-        // Get the internal name of the variable.
-        let var_name = current_block["fields"]["VARIABLE"].as_array().unwrap()[1]
-            .as_str()
-            .unwrap();
         // Get the id of the variable in the Rash VM.
-        let id: usize = self.variables.get_id(var_name).unwrap();
+        let id = self.get_variable_field(current_block)?;
 
         let register = self.register_malloc();
         self.register_set_to_input(current_block, register, "VALUE");
         self.instructions.push(Instruction::MemoryStore(
             Value::Pointer(id),
-            Value::Pointer(self.register_get_variable_id(register)),
+            Value::Register(register),
         ));
         self.register_free(register);
 
         None
     }
+
+    pub fn c_variables_change(&mut self, current_block: &serde_json::Value) -> Option<usize> {
+        let id = self.get_variable_field(current_block)?;
+
+        let register = self.register_malloc();
+        self.register_set_to_input(current_block, register, "VALUE");
+        self.instructions.push(Instruction::OperatorAdd(
+            Value::Pointer(id),
+            Value::Pointer(id),
+            Value::Register(register),
+        ));
+        self.register_free(register);
+
+        None
+    }
+
+    pub fn c_variables_show(&mut self, current_block: &serde_json::Value) -> Option<usize> {
+        let id = self.get_variable_field(current_block)?;
+        self.instructions
+            .push(Instruction::DataShowVariable(Value::Pointer(id)));
+        None
+    }
+
+    pub fn c_variables_hide(&mut self, current_block: &serde_json::Value) -> Option<usize> {
+        let id = self.get_variable_field(current_block)?;
+        self.instructions
+            .push(Instruction::DataHideVariable(Value::Pointer(id)));
+        None
+    }
 }
@@ -0,0 +1,87 @@
+use std::time::{Duration, Instant};
+
+// Where waits, the timer and frame budgets get the time from. Headless runs and
+// audio captures count frames instead of reading the wall clock, so a project does
+// the same thing however fast the machine running it is.
+pub struct Clock {
+    started: Instant,
+    // The time when counting frames, only moved on by end_frame.
+    frame_counted_time: Option<Duration>,
+}
+
+impl Clock {
+    pub fn real() -> Clock {
+        Clock {
+            started: Instant::now(),
+            frame_counted_time: None,
+        }
+    }
+
+    pub fn frame_counted() -> Clock {
+        Clock {
+            started: Instant::now(),
+            frame_counted_time: Some(Duration::ZERO),
+        }
+    }
+
+    // Time since the clock started.
+    pub fn now(&self) -> Duration {
+        match self.frame_counted_time {
+            Some(time) => time,
+            None => self.started.elapsed(),
+        }
+    }
+
+    pub fn end_frame(&mut self, frame_time: Duration) {
+        if let Some(time) = &mut self.frame_counted_time {
+            *time += frame_time;
+        }
+    }
+
+    // Whether something that started at the given time has gone over a time limit.
+    // Time stands still during a frame when counting frames, so then the limit
+    // is how many turns it has had instead.
+    pub fn is_over(
+        &self,
+        started: Duration,
+        time_limit: Duration,
+        turns: usize,
+        turn_limit: usize,
+    ) -> bool {
+        match self.frame_counted_time {
+            Some(_) => turns >= turn_limit,
+            None => self.now().saturating_sub(started) >= time_limit,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::Clock;
+
+    #[test]
+    fn frame_counted_time_only_moves_between_frames() {
+        let mut clock = Clock::frame_counted();
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(clock.now(), Duration::ZERO);
+        for _ in 0..30 {
+            clock.end_frame(Duration::from_secs_f64(1.0 / 30.0));
+        }
+        assert!((clock.now().as_secs_f64() - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn frame_counted_limits_count_turns() {
+        let clock = Clock::frame_counted();
+        let limit = Duration::from_millis(1);
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(!clock.is_over(Duration::ZERO, limit, 9, 10));
+        assert!(clock.is_over(Duration::ZERO, limit, 10, 10));
+
+        let clock = Clock::real();
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(clock.is_over(Duration::ZERO, limit, 0, 10));
+    }
+}
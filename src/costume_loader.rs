@@ -1,31 +1,35 @@
 use sdl2::image::LoadTexture;
 
-use crate::project::project_main::Project;
-
 pub fn load_png<'a>(
     texture_creator: &'a sdl2::render::TextureCreator<sdl2::video::WindowContext>,
-    temp_project: &Project<'_>,
-    costume: &serde_json::Value,
+    png: &[u8],
 ) -> Result<sdl2::render::Texture<'a>, String> {
-    texture_creator.load_texture(
-        temp_project
-            .path
-            .join(costume["assetId"].as_str().unwrap().to_string() + ".png"),
-    )
+    texture_creator.load_texture_bytes(png)
+}
+
+// Gives the costume as a PNG, drawing it first if it is an SVG.
+pub fn rasterize(
+    costume_json: &serde_json::Value,
+    project_path: &std::path::Path,
+    font_database: &usvg_text_layout::fontdb::Database,
+) -> Result<Vec<u8>, String> {
+    if costume_json["dataFormat"].as_str().unwrap() == "svg" {
+        convert_svg_to_png(costume_json, project_path, font_database)?;
+    }
+    let path = project_path.join(costume_json["assetId"].as_str().unwrap().to_string() + ".png");
+    std::fs::read(&path).map_err(|err| format!("Could not read {}: {err}", path.display()))
 }
 
 pub fn convert_svg_to_png(
     costume_json: &serde_json::Value,
-    project: &Project<'_>,
+    project_path: &std::path::Path,
     font_database: &usvg_text_layout::fontdb::Database,
 ) -> Result<(), String> {
     crate::third_party::svg_to_png::render(
-        project
-            .path
+        project_path
             .join(costume_json["md5ext"].as_str().unwrap())
             .as_ref(),
-        project
-            .path
+        project_path
             .join(costume_json["assetId"].as_str().unwrap().to_string() + ".png")
             .as_ref(),
         font_database,
@@ -1,11 +1,17 @@
+use std::borrow::Cow;
+
+use serde::{Deserialize, Serialize};
+
 use crate::bc_compiler::bc_comp_variable_manager::VariableCompiler;
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Instruction {
     MemoryStore(Value, Value),
     MemoryDump,
     ThreadKill,
     ThreadPause,
+    // A loop ending in warp mode, which only yields if the thread has been running too long.
+    ThreadWarpPause,
     OperatorModulo(Value, Value, Value),
     OperatorAdd(Value, Value, Value),
     OperatorSubtract(Value, Value, Value),
@@ -28,11 +34,37 @@ pub enum Instruction {
     OperatorCeiling(Value, Value),
     OperatorGreater(Value, Value, Value),
     OperatorEquals(Value, Value, Value),
+    // An operator whose inputs the compiler proved are always numbers.
+    OperatorNumbers(NumberOp, Value, Value, Value),
     SensingTimer(Value),
-    FlowIfJump(Value, Value),
+    SensingAskAndWait(Value, bool),
+    SensingAnswer(Value),
+    SensingResetTimer,
+    SensingDistanceTo(Value, Value),
+    SensingOf(Value, String, Value),
+    SensingCurrent(Value, String),
+    SensingDaysSince2000(Value),
+    SensingUsername(Value),
+    SoundPlay(Value),
+    SoundPlayUntilDone(Value),
+    SoundStopAll,
+    SoundSetEffect(String, Value),
+    SoundChangeEffect(String, Value),
+    SoundClearEffects,
+    SoundSetVolume(Value),
+    SoundChangeVolume(Value),
+    SoundGetVolume(Value),
+    MusicPlayDrum(Value, Value),
+    MusicPlayNote(Value, Value),
+    MusicRest(Value),
+    MusicSetTempo(Value),
+    MusicChangeTempo(Value),
+    MusicGetTempo(Value),
+    MusicSetInstrument(Value),
+    FlowIfJump(Value, usize),
     FlowIfJumpToPlace(Value, String),
     FlowDefinePlace(String),
-    FlowIfNotJump(Value, Value),
+    FlowIfNotJump(Value, usize),
     FlowIfNotJumpToPlace(Value, String),
     MotionChangeX(Value),
     MotionChangeY(Value),
@@ -52,9 +84,19 @@ pub enum Instruction {
     PenUp,
     PenDown,
     PenSetRadius(Value),
+    PenChangeRadius(Value),
+    PenSetColor(Value),
+    PenSetColorParam(Value, Value),
+    PenChangeColorParam(Value, Value),
+    PenSetHue(Value),
+    PenSetShade(Value),
+    DataShowVariable(Value),
+    DataHideVariable(Value),
 }
 
 impl Instruction {
+    // This is also the assembly syntax, so anything printed
+    // differently has to be parsed differently in assembler.rs.
     pub fn print(&self, variables: Option<&VariableCompiler>) -> String {
         match &self {
             Instruction::MemoryStore(at, n) => {
@@ -63,6 +105,16 @@ impl Instruction {
             Instruction::MemoryDump => "dump_memory();".to_owned(),
             Instruction::ThreadKill => "return;".to_owned(),
             Instruction::ThreadPause => "render_frame()".to_owned(),
+            Instruction::ThreadWarpPause => "warp_render_frame()".to_owned(),
+            Instruction::OperatorNumbers(op, l, a, b) => {
+                format!(
+                    "{} = {} {} {} (numbers)",
+                    l.print(variables),
+                    a.print(variables),
+                    op.symbol(),
+                    b.print(variables)
+                )
+            }
             Instruction::OperatorModulo(l, a, b) => {
                 format!(
                     "{} = {} % {}",
@@ -177,23 +229,95 @@ impl Instruction {
             Instruction::SensingTimer(location) => {
                 format!("{} = timer()", location.print(variables))
             }
-            Instruction::FlowIfJump(condition, l) => {
+            Instruction::SensingAskAndWait(question, can_show_bubble) => {
+                format!(
+                    "ask_and_wait({}, {can_show_bubble})",
+                    question.print(variables)
+                )
+            }
+            Instruction::SensingAnswer(location) => {
+                format!("{} = answer()", location.print(variables))
+            }
+            Instruction::SensingResetTimer => "reset_timer()".to_owned(),
+            Instruction::SensingDistanceTo(location, target) => {
+                format!(
+                    "{} = distance_to({})",
+                    location.print(variables),
+                    target.print(variables)
+                )
+            }
+            Instruction::SensingOf(location, property, object) => {
+                format!(
+                    "{} = {property:?} of {}",
+                    location.print(variables),
+                    object.print(variables)
+                )
+            }
+            Instruction::SensingCurrent(location, menu) => {
+                format!("{} = current({menu:?})", location.print(variables))
+            }
+            Instruction::SensingDaysSince2000(location) => {
+                format!("{} = days_since_2000()", location.print(variables))
+            }
+            Instruction::SensingUsername(location) => {
+                format!("{} = username()", location.print(variables))
+            }
+            Instruction::SoundPlay(sound) => format!("sound_play({})", sound.print(variables)),
+            Instruction::SoundPlayUntilDone(sound) => {
+                format!("sound_play_until_done({})", sound.print(variables))
+            }
+            Instruction::SoundStopAll => "sound_stop_all()".to_owned(),
+            Instruction::SoundSetEffect(effect, value) => {
+                format!("sound_set_effect({effect:?}, {})", value.print(variables))
+            }
+            Instruction::SoundChangeEffect(effect, value) => {
                 format!(
-                    "if {} jump to {}",
-                    condition.print(variables),
-                    l.print(variables)
+                    "sound_change_effect({effect:?}, {})",
+                    value.print(variables)
                 )
             }
+            Instruction::SoundClearEffects => "sound_clear_effects()".to_owned(),
+            Instruction::SoundSetVolume(volume) => {
+                format!("sound_set_volume({})", volume.print(variables))
+            }
+            Instruction::SoundChangeVolume(volume) => {
+                format!("sound_change_volume({})", volume.print(variables))
+            }
+            Instruction::SoundGetVolume(location) => {
+                format!("{} = sound_volume()", location.print(variables))
+            }
+            Instruction::MusicPlayDrum(drum, beats) => format!(
+                "music_play_drum({}, {})",
+                drum.print(variables),
+                beats.print(variables)
+            ),
+            Instruction::MusicPlayNote(note, beats) => format!(
+                "music_play_note({}, {})",
+                note.print(variables),
+                beats.print(variables)
+            ),
+            Instruction::MusicRest(beats) => format!("music_rest({})", beats.print(variables)),
+            Instruction::MusicSetTempo(tempo) => {
+                format!("music_set_tempo({})", tempo.print(variables))
+            }
+            Instruction::MusicChangeTempo(tempo) => {
+                format!("music_change_tempo({})", tempo.print(variables))
+            }
+            Instruction::MusicGetTempo(location) => {
+                format!("{} = music_tempo()", location.print(variables))
+            }
+            Instruction::MusicSetInstrument(instrument) => {
+                format!("music_set_instrument({})", instrument.print(variables))
+            }
+            Instruction::FlowIfJump(condition, l) => {
+                format!("if {} jump to {l}", condition.print(variables))
+            }
             Instruction::FlowIfJumpToPlace(condition, l) => {
                 format!("if {} goto {l}", condition.print(variables))
             }
             Instruction::FlowDefinePlace(place) => format!("{place}:"),
             Instruction::FlowIfNotJump(condition, location) => {
-                format!(
-                    "if !{} jump to {}",
-                    condition.print(variables),
-                    location.print(variables)
-                )
+                format!("if !{} jump to {location}", condition.print(variables))
             }
             Instruction::FlowIfNotJumpToPlace(condition, location) => {
                 format!("if !{} goto {location}", condition.print(variables))
@@ -219,19 +343,446 @@ impl Instruction {
             Instruction::PenUp => "pen_up()".to_owned(),
             Instruction::PenDown => "pen_down()".to_owned(),
             Instruction::PenSetRadius(value) => format!("pen_set_size({})", value.print(variables)),
+            Instruction::PenChangeRadius(value) => {
+                format!("pen_change_size({})", value.print(variables))
+            }
+            Instruction::PenSetColor(color) => {
+                format!("pen_set_color({})", color.print(variables))
+            }
+            Instruction::PenSetColorParam(param, value) => format!(
+                "pen_set_color_param({}, {})",
+                param.print(variables),
+                value.print(variables)
+            ),
+            Instruction::PenChangeColorParam(param, value) => format!(
+                "pen_change_color_param({}, {})",
+                param.print(variables),
+                value.print(variables)
+            ),
+            Instruction::PenSetHue(hue) => format!("pen_set_hue({})", hue.print(variables)),
+            Instruction::PenSetShade(shade) => format!("pen_set_shade({})", shade.print(variables)),
             Instruction::LooksHide => "looks_hide()".to_owned(),
             Instruction::LooksShow => "looks_show()".to_owned(),
             Instruction::LooksNextCostume => "looks_next_costume()".to_owned(),
+            Instruction::DataShowVariable(variable) => {
+                format!("show_variable({})", variable.print(variables))
+            }
+            Instruction::DataHideVariable(variable) => {
+                format!("hide_variable({})", variable.print(variables))
+            }
+        }
+    }
+
+    // Whether evaluate_operator can work this out.
+    pub fn is_operator(&self) -> bool {
+        matches!(
+            self,
+            Instruction::OperatorModulo(..)
+                | Instruction::OperatorAdd(..)
+                | Instruction::OperatorSubtract(..)
+                | Instruction::OperatorMultiply(..)
+                | Instruction::OperatorDivide(..)
+                | Instruction::OperatorPower(..)
+                | Instruction::OperatorLesser(..)
+                | Instruction::OperatorGreater(..)
+                | Instruction::OperatorEquals(..)
+                | Instruction::OperatorNumbers(..)
+                | Instruction::OperatorERaised(..)
+                | Instruction::OperatorSin(..)
+                | Instruction::OperatorCos(..)
+                | Instruction::OperatorTan(..)
+                | Instruction::OperatorAbs(..)
+                | Instruction::OperatorASin(..)
+                | Instruction::OperatorACos(..)
+                | Instruction::OperatorATan(..)
+                | Instruction::OperatorSqrt(..)
+                | Instruction::OperatorLn(..)
+                | Instruction::OperatorLog(..)
+                | Instruction::OperatorFloor(..)
+                | Instruction::OperatorCeiling(..)
+        )
+    }
+
+    // The result of an operator block, which only depends on its inputs. None for
+    // anything else. Shared by the interpreter and constant folding in the optimizer.
+    pub fn evaluate_operator(&self, memory: &Memory) -> Option<(&Value, Value)> {
+        let degrees = std::f64::consts::PI / 180.0;
+        let radians = 180.0 / std::f64::consts::PI;
+        let (location, result) = match self {
+            Instruction::OperatorModulo(l, a, b) => (
+                l,
+                Value::Number(a.get_number(memory).rem_euclid(b.get_number(memory))),
+            ),
+            Instruction::OperatorAdd(l, a, b) => (
+                l,
+                Value::Number(a.get_number(memory) + b.get_number(memory)),
+            ),
+            Instruction::OperatorSubtract(l, a, b) => (
+                l,
+                Value::Number(a.get_number(memory) - b.get_number(memory)),
+            ),
+            Instruction::OperatorMultiply(l, a, b) => (
+                l,
+                Value::Number(a.get_number(memory) * b.get_number(memory)),
+            ),
+            Instruction::OperatorDivide(l, a, b) => (
+                l,
+                Value::Number(a.get_number(memory) / b.get_number(memory)),
+            ),
+            Instruction::OperatorPower(l, a, b) => (
+                l,
+                Value::Number(a.get_number(memory).powf(b.get_number(memory))),
+            ),
+            Instruction::OperatorERaised(l, n) => (l, Value::Number(n.get_number(memory).exp())),
+            Instruction::OperatorSin(l, n) => {
+                (l, Value::Number((n.get_number(memory) * degrees).sin()))
+            }
+            Instruction::OperatorCos(l, n) => {
+                (l, Value::Number((n.get_number(memory) * degrees).cos()))
+            }
+            Instruction::OperatorTan(l, n) => {
+                (l, Value::Number((n.get_number(memory) * degrees).tan()))
+            }
+            Instruction::OperatorAbs(l, n) => (l, Value::Number(n.get_number(memory).abs())),
+            Instruction::OperatorASin(l, n) => {
+                (l, Value::Number(n.get_number(memory).asin() * radians))
+            }
+            Instruction::OperatorACos(l, n) => {
+                (l, Value::Number(n.get_number(memory).acos() * radians))
+            }
+            Instruction::OperatorATan(l, n) => {
+                (l, Value::Number(n.get_number(memory).atan() * radians))
+            }
+            Instruction::OperatorSqrt(l, n) => {
+                let n = n.get_number(memory);
+                (l, Value::Number(if n < 0.0 { 0.0 } else { n.sqrt() }))
+            }
+            Instruction::OperatorLn(l, n) => (l, Value::Number(n.get_number(memory).ln())),
+            Instruction::OperatorLog(l, n) => (l, Value::Number(n.get_number(memory).log10())),
+            Instruction::OperatorFloor(l, n) => (l, Value::Number(n.get_number(memory).floor())),
+            Instruction::OperatorCeiling(l, n) => (l, Value::Number(n.get_number(memory).ceil())),
+            Instruction::OperatorLesser(l, a, b) => (
+                l,
+                Value::Boolean(a.get_number(memory) < b.get_number(memory)),
+            ),
+            Instruction::OperatorGreater(l, a, b) => (
+                l,
+                Value::Boolean(a.get_number(memory) > b.get_number(memory)),
+            ),
+            Instruction::OperatorEquals(l, a, b) => (l, Value::Boolean(values_equal(a, b, memory))),
+            Instruction::OperatorNumbers(op, l, a, b) => {
+                (l, op.apply(a.get_number(memory), b.get_number(memory)))
+            }
+            _ => return None,
+        };
+        Some((location, result))
+    }
+
+    // Every value in the instruction, and what the instruction does with it.
+    pub fn operands_mut(&mut self) -> Vec<(Operand, &mut Value)> {
+        use Operand::{Read, Variable, Write};
+        match self {
+            Instruction::MemoryStore(l, value) => vec![(Write, l), (Read, value)],
+            Instruction::OperatorModulo(l, a, b)
+            | Instruction::OperatorAdd(l, a, b)
+            | Instruction::OperatorSubtract(l, a, b)
+            | Instruction::OperatorMultiply(l, a, b)
+            | Instruction::OperatorDivide(l, a, b)
+            | Instruction::OperatorLesser(l, a, b)
+            | Instruction::OperatorPower(l, a, b)
+            | Instruction::OperatorGreater(l, a, b)
+            | Instruction::OperatorEquals(l, a, b)
+            | Instruction::OperatorNumbers(_, l, a, b) => vec![(Write, l), (Read, a), (Read, b)],
+            Instruction::OperatorERaised(l, n)
+            | Instruction::OperatorSin(l, n)
+            | Instruction::OperatorCos(l, n)
+            | Instruction::OperatorTan(l, n)
+            | Instruction::OperatorAbs(l, n)
+            | Instruction::OperatorASin(l, n)
+            | Instruction::OperatorACos(l, n)
+            | Instruction::OperatorATan(l, n)
+            | Instruction::OperatorSqrt(l, n)
+            | Instruction::OperatorLn(l, n)
+            | Instruction::OperatorLog(l, n)
+            | Instruction::OperatorFloor(l, n)
+            | Instruction::OperatorCeiling(l, n)
+            | Instruction::SensingDistanceTo(l, n)
+            | Instruction::SensingOf(l, _, n) => vec![(Write, l), (Read, n)],
+            Instruction::SensingTimer(l)
+            | Instruction::SensingAnswer(l)
+            | Instruction::SensingCurrent(l, _)
+            | Instruction::SensingDaysSince2000(l)
+            | Instruction::SensingUsername(l)
+            | Instruction::SoundGetVolume(l)
+            | Instruction::MusicGetTempo(l)
+            | Instruction::MotionGetX(l)
+            | Instruction::MotionGetY(l)
+            | Instruction::LooksGetCostumeNumber(l) => vec![(Write, l)],
+            Instruction::MusicPlayDrum(a, b)
+            | Instruction::MusicPlayNote(a, b)
+            | Instruction::MotionSetXY(a, b)
+            | Instruction::PenSetColorParam(a, b)
+            | Instruction::PenChangeColorParam(a, b) => vec![(Read, a), (Read, b)],
+            Instruction::SensingAskAndWait(n, _)
+            | Instruction::SoundPlay(n)
+            | Instruction::SoundPlayUntilDone(n)
+            | Instruction::SoundSetEffect(_, n)
+            | Instruction::SoundChangeEffect(_, n)
+            | Instruction::SoundSetVolume(n)
+            | Instruction::SoundChangeVolume(n)
+            | Instruction::MusicRest(n)
+            | Instruction::MusicSetTempo(n)
+            | Instruction::MusicChangeTempo(n)
+            | Instruction::MusicSetInstrument(n)
+            | Instruction::FlowIfJump(n, _)
+            | Instruction::FlowIfJumpToPlace(n, _)
+            | Instruction::FlowIfNotJump(n, _)
+            | Instruction::FlowIfNotJumpToPlace(n, _)
+            | Instruction::MotionChangeX(n)
+            | Instruction::MotionChangeY(n)
+            | Instruction::MotionSetX(n)
+            | Instruction::MotionSetY(n)
+            | Instruction::LooksSetSize(n)
+            | Instruction::LooksSetCostume(n)
+            | Instruction::PenSetRadius(n)
+            | Instruction::PenChangeRadius(n)
+            | Instruction::PenSetColor(n)
+            | Instruction::PenSetHue(n)
+            | Instruction::PenSetShade(n) => vec![(Read, n)],
+            Instruction::DataShowVariable(variable) | Instruction::DataHideVariable(variable) => {
+                vec![(Variable, variable)]
+            }
+            Instruction::MemoryDump
+            | Instruction::ThreadKill
+            | Instruction::ThreadPause
+            | Instruction::ThreadWarpPause
+            | Instruction::SensingResetTimer
+            | Instruction::SoundStopAll
+            | Instruction::SoundClearEffects
+            | Instruction::FlowDefinePlace(_)
+            | Instruction::LooksNextCostume
+            | Instruction::LooksHide
+            | Instruction::LooksShow
+            | Instruction::PenClear
+            | Instruction::PenStamp
+            | Instruction::PenUp
+            | Instruction::PenDown => vec![],
+        }
+    }
+
+    // Whether other threads can run while this one is on this instruction,
+    // so they could change any variable.
+    pub fn may_yield(&self) -> bool {
+        matches!(
+            self,
+            Instruction::ThreadPause
+                | Instruction::ThreadWarpPause
+                | Instruction::ThreadKill
+                | Instruction::SensingAskAndWait(_, _)
+                | Instruction::SoundPlayUntilDone(_)
+                | Instruction::MusicPlayDrum(_, _)
+                | Instruction::MusicPlayNote(_, _)
+                | Instruction::MusicRest(_)
+        )
+    }
+
+    // Whether running this changes what is on screen. Moving or changing a sprite
+    // only counts when it can be seen or is drawing with the pen.
+    pub fn requests_redraw(&self, sprite_visible: bool) -> bool {
+        match self {
+            Instruction::MotionChangeX(_)
+            | Instruction::MotionChangeY(_)
+            | Instruction::MotionSetXY(_, _)
+            | Instruction::MotionSetX(_)
+            | Instruction::MotionSetY(_)
+            | Instruction::LooksSetSize(_)
+            | Instruction::LooksSetCostume(_)
+            | Instruction::LooksNextCostume => sprite_visible,
+            Instruction::LooksHide
+            | Instruction::LooksShow
+            | Instruction::PenClear
+            | Instruction::PenStamp
+            | Instruction::PenDown
+            | Instruction::SensingAskAndWait(_, _)
+            | Instruction::DataShowVariable(_)
+            | Instruction::DataHideVariable(_) => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum NumberOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Power,
+    Lesser,
+    Greater,
+    Equals,
+}
+
+impl NumberOp {
+    // Gives the same results as the general operator instructions do for numbers.
+    pub fn apply(self, a: f64, b: f64) -> Value {
+        match self {
+            NumberOp::Add => Value::Number(a + b),
+            NumberOp::Subtract => Value::Number(a - b),
+            NumberOp::Multiply => Value::Number(a * b),
+            NumberOp::Divide => Value::Number(a / b),
+            NumberOp::Modulo => Value::Number(a.rem_euclid(b)),
+            NumberOp::Power => Value::Number(a.powf(b)),
+            NumberOp::Lesser => Value::Boolean(a < b),
+            NumberOp::Greater => Value::Boolean(a > b),
+            NumberOp::Equals => Value::Boolean(a == b),
         }
     }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            NumberOp::Add => "+",
+            NumberOp::Subtract => "-",
+            NumberOp::Multiply => "*",
+            NumberOp::Divide => "/",
+            NumberOp::Modulo => "%",
+            NumberOp::Power => "^",
+            NumberOp::Lesser => "<",
+            NumberOp::Greater => ">",
+            NumberOp::Equals => "==",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Operand {
+    Read,
+    Write,
+    // Names a variable instead of reading it, like "show variable".
+    Variable,
+}
+
+fn values_equal(a: &Value, b: &Value, memory: &Memory) -> bool {
+    let a = &*memory.resolve(a);
+    let b = &*memory.resolve(b);
+    match &(a, b) {
+        (Value::Number(n1), Value::Number(n2)) => *n1 == *n2,
+        (Value::Boolean(n1), Value::Boolean(n2)) => *n1 == *n2,
+        (Value::String(n1), Value::String(n2)) => *n1 == *n2,
+        (Value::Number(_), Value::Boolean(n2)) => a.get_bool(memory) == *n2,
+        (Value::Boolean(n1), Value::Number(_)) => *n1 == b.get_bool(memory),
+        (Value::String(_), Value::Number(n2)) => a.get_number(memory) == *n2,
+        (Value::Number(n1), Value::String(_)) => *n1 == b.get_number(memory),
+        (Value::Boolean(n1), Value::String(_)) => *n1 == b.get_bool(memory),
+        (Value::String(_), Value::Boolean(n2)) => a.get_bool(memory) == *n2,
+        (Value::List(_), _) | (_, Value::List(_)) => a.get_string(memory) == b.get_string(memory),
+        _ => panic!("Unsupported types for equality comparison"),
+    }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Value {
     Number(f64),
     Boolean(bool),
     String(String),
+    // A variable in the project's memory.
     Pointer(usize),
+    // A temporary in the running thread's register file.
+    Register(usize),
+    // A register the compiler proved only ever holds numbers, kept as a plain f64.
+    NumberRegister(usize),
+    // The same for booleans, which comparisons make and jumps read.
+    BooleanRegister(usize),
+    List(Vec<Value>),
+}
+
+// A thread's temporaries. Registers that always hold numbers or always hold booleans
+// go in their own banks of f64s and bools, so reading one doesn't have to look at a
+// Value first. Every bank has a slot for every register, and each register only uses one.
+#[derive(Debug, Default)]
+pub struct Registers {
+    pub values: Box<[Value]>,
+    pub numbers: Box<[f64]>,
+    pub booleans: Box<[bool]>,
+}
+
+impl Registers {
+    pub fn new(count: usize) -> Registers {
+        Registers {
+            values: vec![Value::Number(0.0); count].into_boxed_slice(),
+            numbers: vec![0.0; count].into_boxed_slice(),
+            booleans: vec![false; count].into_boxed_slice(),
+        }
+    }
+}
+
+// What a running thread can read and write: the project's variables and its own registers.
+pub struct Memory<'m> {
+    pub variables: &'m mut [Value],
+    pub registers: &'m mut [Value],
+    pub numbers: &'m mut [f64],
+    pub booleans: &'m mut [bool],
+    // Goes up every time a variable holding a list is written,
+    // so list monitors only re-render when their list changed.
+    pub list_versions: &'m mut [u64],
+}
+
+impl<'m> Memory<'m> {
+    pub fn new(
+        variables: &'m mut [Value],
+        registers: &'m mut Registers,
+        list_versions: &'m mut [u64],
+    ) -> Memory<'m> {
+        Memory {
+            variables,
+            registers: &mut registers.values,
+            numbers: &mut registers.numbers,
+            booleans: &mut registers.booleans,
+            list_versions,
+        }
+    }
+
+    // For reading values that are already known not to be pointers or registers.
+    pub fn empty() -> Memory<'static> {
+        Memory {
+            variables: &mut [],
+            registers: &mut [],
+            numbers: &mut [],
+            booleans: &mut [],
+            list_versions: &mut [],
+        }
+    }
+
+    // Follows a pointer or register to the value it holds. Typed registers
+    // aren't stored as Values, so theirs is made on the spot.
+    pub fn resolve<'v>(&'v self, value: &'v Value) -> Cow<'v, Value> {
+        match value {
+            Value::Pointer(n) => Cow::Borrowed(&self.variables[*n]),
+            Value::Register(n) => Cow::Borrowed(&self.registers[*n]),
+            Value::NumberRegister(n) => Cow::Owned(Value::Number(self.numbers[*n])),
+            Value::BooleanRegister(n) => Cow::Owned(Value::Boolean(self.booleans[*n])),
+            _ => Cow::Borrowed(value),
+        }
+    }
+
+    pub fn set(&mut self, location: &Value, value: Value) {
+        match location {
+            Value::Pointer(n) => {
+                if matches!(value, Value::List(_)) || matches!(self.variables[*n], Value::List(_)) {
+                    if let Some(version) = self.list_versions.get_mut(*n) {
+                        *version += 1;
+                    }
+                }
+                self.variables[*n] = value
+            }
+            Value::Register(n) => self.registers[*n] = value,
+            // Only numbers and booleans are ever stored in these, this just makes sure.
+            Value::NumberRegister(n) => self.numbers[*n] = value.get_number(&Memory::empty()),
+            Value::BooleanRegister(n) => self.booleans[*n] = value.get_bool(&Memory::empty()),
+            _ => panic!("Can only store values in variables and registers"),
+        }
+    }
 }
 
 /*impl std::fmt::Display for Value {
@@ -250,23 +801,27 @@ impl Value {
         match &self {
             Value::Number(n) => format!("{}", n),
             Value::Boolean(n) => format!("{}", n),
-            Value::String(n) => format!("\"{}\"", n),
+            Value::String(n) => format!("{:?}", n),
             Value::Pointer(pointer) => {
                 let pointer_number = pointer.to_string();
                 format!(
                     "*{}",
-                    match variables {
-                        Some(variables) => {
-                            variables.get_name(*pointer).unwrap()
-                        }
-                        None => &pointer_number,
-                    }
+                    variables
+                        .and_then(|variables| variables.get_name(*pointer))
+                        .unwrap_or(&pointer_number)
                 )
             }
+            Value::Register(register) => format!("r{register}"),
+            Value::NumberRegister(register) => format!("f{register}"),
+            Value::BooleanRegister(register) => format!("b{register}"),
+            Value::List(items) => {
+                let items: Vec<String> = items.iter().map(|item| item.print(variables)).collect();
+                format!("[{}]", items.join(", "))
+            }
         }
     }
 
-    pub fn get_number(&self, memory: &[Value]) -> f64 {
+    pub fn get_number(&self, memory: &Memory) -> f64 {
         match self {
             Value::Number(n) => *n,
             Value::Boolean(n) => {
@@ -277,20 +832,22 @@ impl Value {
                 }
             }
             Value::String(n) => n.parse().unwrap_or(0.0),
-            Value::Pointer(n) => memory[*n].get_number(memory),
+            Value::NumberRegister(n) => memory.numbers[*n],
+            Value::BooleanRegister(n) => memory.booleans[*n] as u8 as f64,
+            Value::Pointer(n) => memory.variables[*n].get_number(memory),
+            Value::Register(n) => memory.registers[*n].get_number(memory),
+            Value::List(_) => self.get_string(memory).parse().unwrap_or(0.0),
         }
     }
 
-    pub fn get_pointer(&self) -> usize {
+    pub fn get_pointer(&self) -> Option<usize> {
         match self {
-            Value::Pointer(n) => *n,
-            _ => {
-                panic!()
-            }
+            Value::Pointer(n) => Some(*n),
+            _ => None,
         }
     }
 
-    pub fn get_bool(&self, memory: &[Value]) -> bool {
+    pub fn get_bool(&self, memory: &Memory) -> bool {
         match self {
             Value::Boolean(n) => *n,
             Value::Number(n) => *n == 1.0,
@@ -305,11 +862,15 @@ impl Value {
                 }
                 false
             }
-            Value::Pointer(n) => memory[*n].get_bool(memory),
+            Value::BooleanRegister(n) => memory.booleans[*n],
+            Value::Pointer(n) => memory.variables[*n].get_bool(memory),
+            Value::Register(n) => memory.registers[*n].get_bool(memory),
+            Value::NumberRegister(_) => memory.resolve(self).get_bool(memory),
+            Value::List(_) => Value::String(self.get_string(memory)).get_bool(memory),
         }
     }
 
-    pub fn get_string(&self, memory: &[Value]) -> String {
+    pub fn get_string(&self, memory: &Memory) -> String {
         match self {
             Value::Number(n) => n.to_string(),
             Value::Boolean(n) => {
@@ -320,7 +881,19 @@ impl Value {
                 }
             }
             Value::String(n) => n.clone(),
-            Value::Pointer(n) => memory[*n].get_string(memory),
+            Value::Pointer(_)
+            | Value::Register(_)
+            | Value::NumberRegister(_)
+            | Value::BooleanRegister(_) => memory.resolve(self).get_string(memory),
+            Value::List(items) => {
+                let items: Vec<String> = items.iter().map(|item| item.get_string(memory)).collect();
+                // Like Scratch, lists of single letters are joined without spaces.
+                if items.iter().all(|item| item.chars().count() == 1) {
+                    items.concat()
+                } else {
+                    items.join(" ")
+                }
+            }
         }
     }
 }
@@ -0,0 +1,686 @@
+use std::rc::Rc;
+
+use cranelift_codegen::{
+    ir::{
+        condcodes::FloatCC, types, AbiParam, Block, FuncRef, InstBuilder, JumpTableData, MemFlags,
+        Signature,
+    },
+    settings::{self, Configurable},
+};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, Linkage, Module};
+
+use crate::interpreter::{Instruction, Memory, NumberOp, Value};
+
+type NativeFunction = extern "C" fn(&mut Memory, usize, *mut f64, *mut bool) -> usize;
+
+// A script compiled to machine code. It runs from wherever the thread is up to
+// and returns the index of the first instruction it can't do itself, which the
+// interpreter then runs. Anything that can yield is left to the interpreter,
+// so threads still give the scheduler control at the same places.
+pub struct NativeScript {
+    module: Option<JITModule>,
+    // The machine code points straight at the operands in here.
+    _instructions: Rc<[Instruction]>,
+    // Instructions the machine code can start from. Calling into it for
+    // anything else would just return straight away.
+    entry_points: Box<[bool]>,
+    // How many number and boolean registers the machine code reads and writes without checking.
+    number_count: usize,
+    boolean_count: usize,
+    function: NativeFunction,
+}
+
+impl NativeScript {
+    pub fn can_run(&self, counter: usize) -> bool {
+        self.entry_points[counter]
+    }
+
+    // Returns the counter to carry on interpreting from. Any counter is fine,
+    // the machine code returns straight away for ones past the end.
+    pub fn run(&self, memory: &mut Memory, counter: usize) -> usize {
+        // The interpreter can say what is wrong instead.
+        if memory.numbers.len() < self.number_count || memory.booleans.len() < self.boolean_count {
+            return counter;
+        }
+        // The machine code uses memory only through the helpers below, one call at
+        // a time, so this is the only reference to it while it runs. It also reads
+        // and writes the number and boolean registers through these pointers, but never
+        // while a helper is running, so the two never overlap.
+        let numbers = memory.numbers.as_mut_ptr();
+        let booleans = memory.booleans.as_mut_ptr();
+        (self.function)(memory, counter, numbers, booleans)
+    }
+}
+
+impl Drop for NativeScript {
+    fn drop(&mut self) {
+        if let Some(module) = self.module.take() {
+            // Safe because the function pointer goes away with this.
+            unsafe { module.free_memory() };
+        }
+    }
+}
+
+fn is_native(instruction: &Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::MemoryStore(..) | Instruction::FlowIfJump(..) | Instruction::FlowIfNotJump(..)
+    ) || instruction.is_operator()
+}
+
+// Gives None for scripts where there is nothing worth compiling.
+pub fn compile(instructions: &Rc<[Instruction]>) -> Result<Option<NativeScript>, String> {
+    let entry_points: Box<[bool]> = instructions.iter().map(is_native).collect();
+    if !entry_points.contains(&true) {
+        return Ok(None);
+    }
+
+    let mut flags = settings::builder();
+    flags
+        .set("use_colocated_libcalls", "false")
+        .map_err(|err| err.to_string())?;
+    flags
+        .set("is_pic", "false")
+        .map_err(|err| err.to_string())?;
+    flags
+        .set("opt_level", "speed")
+        .map_err(|err| err.to_string())?;
+    let isa = cranelift_native::builder()?
+        .finish(settings::Flags::new(flags))
+        .map_err(|err| err.to_string())?;
+
+    let mut jit_builder = JITBuilder::with_isa(isa, default_libcall_names());
+    for helper in HELPERS {
+        jit_builder.symbol(helper.name, helper.pointer);
+    }
+    let mut module = JITModule::new(jit_builder);
+
+    let pointer = module.target_config().pointer_type();
+    let mut context = module.make_context();
+    context.func.signature.params.push(AbiParam::new(pointer));
+    context.func.signature.params.push(AbiParam::new(pointer));
+    context.func.signature.params.push(AbiParam::new(pointer));
+    context.func.signature.params.push(AbiParam::new(pointer));
+    context.func.signature.returns.push(AbiParam::new(pointer));
+
+    let mut helper_ids = Vec::new();
+    for helper in HELPERS {
+        let mut signature = module.make_signature();
+        (helper.signature)(&mut signature, pointer);
+        let id = module
+            .declare_function(helper.name, Linkage::Import, &signature)
+            .map_err(|err| err.to_string())?;
+        helper_ids.push(id);
+    }
+
+    let mut builder_context = FunctionBuilderContext::new();
+    let mut builder = FunctionBuilder::new(&mut context.func, &mut builder_context);
+    let helpers: Vec<FuncRef> = helper_ids
+        .iter()
+        .map(|&id| module.declare_func_in_func(id, builder.func))
+        .collect();
+
+    // One block per instruction, plus one for running off the end.
+    let blocks: Vec<Block> = (0..=instructions.len())
+        .map(|_| builder.create_block())
+        .collect();
+
+    let entry = builder.create_block();
+    builder.append_block_params_for_function_params(entry);
+    builder.switch_to_block(entry);
+    let memory = builder.block_params(entry)[0];
+    let start = builder.block_params(entry)[1];
+    let numbers = builder.block_params(entry)[2];
+    let booleans = builder.block_params(entry)[3];
+    let start = if pointer == types::I32 {
+        start
+    } else {
+        builder.ins().ireduce(types::I32, start)
+    };
+    let table: Vec<_> = blocks[..instructions.len()]
+        .iter()
+        .map(|&block| builder.func.dfg.block_call(block, &[]))
+        .collect();
+    let end = builder.func.dfg.block_call(blocks[instructions.len()], &[]);
+    let table = builder.create_jump_table(JumpTableData::new(end, &table));
+    builder.ins().br_table(start, table);
+
+    let mut number_count = 0;
+    let mut boolean_count = 0;
+    for (index, instruction) in instructions.iter().enumerate() {
+        builder.switch_to_block(blocks[index]);
+        let next = blocks[index + 1];
+        let mut compiler = InstructionCompiler {
+            builder: &mut builder,
+            helpers: &helpers,
+            memory,
+            numbers,
+            number_count: &mut number_count,
+            booleans,
+            boolean_count: &mut boolean_count,
+            pointer,
+        };
+        match instruction {
+            Instruction::OperatorNumbers(op, location, a, b) => {
+                compiler.number_op(*op, location, a, b);
+                builder.ins().jump(next, &[]);
+            }
+            Instruction::MemoryStore(location, value) => {
+                compiler.call(Helper::Store, &[location.into(), value.into()]);
+                builder.ins().jump(next, &[]);
+            }
+            Instruction::FlowIfJump(condition, target) => {
+                let condition = compiler.read_bool(condition);
+                builder
+                    .ins()
+                    .brif(condition, blocks[*target], &[], next, &[]);
+            }
+            Instruction::FlowIfNotJump(condition, target) => {
+                let condition = compiler.read_bool(condition);
+                builder
+                    .ins()
+                    .brif(condition, next, &[], blocks[*target], &[]);
+            }
+            _ if entry_points[index] => {
+                compiler.call(Helper::Operator, &[instruction.into()]);
+                builder.ins().jump(next, &[]);
+            }
+            _ => {
+                let index = builder.ins().iconst(pointer, index as i64);
+                builder.ins().return_(&[index]);
+            }
+        }
+    }
+    builder.switch_to_block(blocks[instructions.len()]);
+    let end = builder.ins().iconst(pointer, instructions.len() as i64);
+    builder.ins().return_(&[end]);
+
+    builder.seal_all_blocks();
+    builder.finalize();
+
+    let id = module
+        .declare_function("script", Linkage::Local, &context.func.signature)
+        .map_err(|err| err.to_string())?;
+    module
+        .define_function(id, &mut context)
+        .map_err(|err| err.to_string())?;
+    module.clear_context(&mut context);
+    module
+        .finalize_definitions()
+        .map_err(|err| err.to_string())?;
+
+    let code = module.get_finalized_function(id);
+    // Safe because the code was built with the signature declared above, a pointer,
+    // a pointer sized integer and two more pointers in and one out, in the target's default calling
+    // convention, which is what extern "C" uses too. &mut Memory is passed as a
+    // plain pointer. The code lives as long as the module, and the module is only
+    // freed when the NativeScript holding this function pointer is dropped.
+    let function = unsafe { std::mem::transmute::<*const u8, NativeFunction>(code) };
+    Ok(Some(NativeScript {
+        module: Some(module),
+        _instructions: instructions.clone(),
+        entry_points,
+        number_count,
+        boolean_count,
+        function,
+    }))
+}
+
+// Something the machine code passes to a helper by address. The addresses are
+// written into the machine code as constants, so they have to stay valid for as
+// long as it can run. They always point into the instructions of the script:
+// NativeScript keeps a clone of that Rc<[Instruction]>, so the slice is never
+// freed or moved, and nothing can get a &mut to it while it is shared, so the
+// helpers can take them as & references. Memory's variables and registers are
+// separate allocations, so they never overlap the &mut Memory the helpers get.
+enum Argument {
+    Value(*const Value),
+    Instruction(*const Instruction),
+}
+
+impl From<&Value> for Argument {
+    fn from(value: &Value) -> Self {
+        Argument::Value(value)
+    }
+}
+
+impl From<&Instruction> for Argument {
+    fn from(instruction: &Instruction) -> Self {
+        Argument::Instruction(instruction)
+    }
+}
+
+struct InstructionCompiler<'b, 'f> {
+    builder: &'b mut FunctionBuilder<'f>,
+    helpers: &'b [FuncRef],
+    memory: cranelift_codegen::ir::Value,
+    // The start of the bank of number registers.
+    numbers: cranelift_codegen::ir::Value,
+    // One more than the highest number register used so far.
+    number_count: &'b mut usize,
+    // The same for boolean registers.
+    booleans: cranelift_codegen::ir::Value,
+    boolean_count: &'b mut usize,
+    pointer: types::Type,
+}
+
+impl InstructionCompiler<'_, '_> {
+    // Calls a helper with the memory and the addresses of its arguments.
+    fn call(&mut self, helper: Helper, arguments: &[Argument]) -> cranelift_codegen::ir::Inst {
+        let mut values = vec![self.memory];
+        for argument in arguments {
+            let address = match argument {
+                Argument::Value(value) => *value as i64,
+                Argument::Instruction(instruction) => *instruction as i64,
+            };
+            values.push(self.builder.ins().iconst(self.pointer, address));
+        }
+        self.builder
+            .ins()
+            .call(self.helpers[helper as usize], &values)
+    }
+
+    // Where a number register is kept.
+    fn number_slot(&mut self, register: usize) -> cranelift_codegen::ir::Value {
+        *self.number_count = (*self.number_count).max(register + 1);
+        self.builder
+            .ins()
+            .iadd_imm(self.numbers, (register * std::mem::size_of::<f64>()) as i64)
+    }
+
+    // Where a boolean register is kept. A bool is one byte, 0 or 1, like the I8s
+    // Cranelift's comparisons give.
+    fn boolean_slot(&mut self, register: usize) -> cranelift_codegen::ir::Value {
+        *self.boolean_count = (*self.boolean_count).max(register + 1);
+        self.builder.ins().iadd_imm(self.booleans, register as i64)
+    }
+
+    fn read_number(&mut self, value: &Value) -> cranelift_codegen::ir::Value {
+        match value {
+            Value::Number(n) => return self.builder.ins().f64const(*n),
+            Value::NumberRegister(register) => {
+                let slot = self.number_slot(*register);
+                return self
+                    .builder
+                    .ins()
+                    .load(types::F64, MemFlags::trusted(), slot, 0);
+            }
+            _ => {}
+        }
+        let call = self.call(Helper::ReadNumber, &[value.into()]);
+        self.builder.inst_results(call)[0]
+    }
+
+    fn read_bool(&mut self, value: &Value) -> cranelift_codegen::ir::Value {
+        match value {
+            Value::Boolean(b) => return self.builder.ins().iconst(types::I8, *b as i64),
+            Value::BooleanRegister(register) => {
+                let slot = self.boolean_slot(*register);
+                return self
+                    .builder
+                    .ins()
+                    .load(types::I8, MemFlags::trusted(), slot, 0);
+            }
+            _ => {}
+        }
+        let call = self.call(Helper::ReadBool, &[value.into()]);
+        self.builder.inst_results(call)[0]
+    }
+
+    fn number_op(&mut self, op: NumberOp, location: &Value, a: &Value, b: &Value) {
+        let a = self.read_number(a);
+        let b = self.read_number(b);
+        let ins = self.builder.ins();
+        let (helper, result) = match op {
+            NumberOp::Add => (Helper::WriteNumber, ins.fadd(a, b)),
+            NumberOp::Subtract => (Helper::WriteNumber, ins.fsub(a, b)),
+            NumberOp::Multiply => (Helper::WriteNumber, ins.fmul(a, b)),
+            NumberOp::Divide => (Helper::WriteNumber, ins.fdiv(a, b)),
+            NumberOp::Lesser => (Helper::WriteBool, ins.fcmp(FloatCC::LessThan, a, b)),
+            NumberOp::Greater => (Helper::WriteBool, ins.fcmp(FloatCC::GreaterThan, a, b)),
+            NumberOp::Equals => (Helper::WriteBool, ins.fcmp(FloatCC::Equal, a, b)),
+            NumberOp::Modulo | NumberOp::Power => {
+                let helper = match op {
+                    NumberOp::Modulo => Helper::Modulo,
+                    _ => Helper::Power,
+                };
+                let call = ins.call(self.helpers[helper as usize], &[a, b]);
+                (Helper::WriteNumber, self.builder.inst_results(call)[0])
+            }
+        };
+        let slot = match (helper, location) {
+            (Helper::WriteNumber, Value::NumberRegister(register)) => {
+                Some(self.number_slot(*register))
+            }
+            (Helper::WriteBool, Value::BooleanRegister(register)) => {
+                Some(self.boolean_slot(*register))
+            }
+            _ => None,
+        };
+        if let Some(slot) = slot {
+            self.builder
+                .ins()
+                .store(MemFlags::trusted(), result, slot, 0);
+            return;
+        }
+        let location = self
+            .builder
+            .ins()
+            .iconst(self.pointer, location as *const Value as i64);
+        self.builder.ins().call(
+            self.helpers[helper as usize],
+            &[self.memory, location, result],
+        );
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Helper {
+    ReadNumber,
+    ReadBool,
+    WriteNumber,
+    WriteBool,
+    Store,
+    Operator,
+    Modulo,
+    Power,
+}
+
+// Rust functions the machine code calls for anything that touches memory or
+// is too fiddly to write out in Cranelift. In the same order as Helper.
+struct HelperFunction {
+    name: &'static str,
+    pointer: *const u8,
+    signature: fn(&mut Signature, types::Type),
+}
+
+const HELPERS: [HelperFunction; 8] = [
+    HelperFunction {
+        name: "rash_read_number",
+        pointer: read_number as *const u8,
+        signature: |s, pointer| {
+            s.params = vec![AbiParam::new(pointer), AbiParam::new(pointer)];
+            s.returns = vec![AbiParam::new(types::F64)];
+        },
+    },
+    HelperFunction {
+        name: "rash_read_bool",
+        pointer: read_bool as *const u8,
+        signature: |s, pointer| {
+            s.params = vec![AbiParam::new(pointer), AbiParam::new(pointer)];
+            s.returns = vec![AbiParam::new(types::I8)];
+        },
+    },
+    HelperFunction {
+        name: "rash_write_number",
+        pointer: write_number as *const u8,
+        signature: |s, pointer| {
+            s.params = vec![
+                AbiParam::new(pointer),
+                AbiParam::new(pointer),
+                AbiParam::new(types::F64),
+            ];
+        },
+    },
+    HelperFunction {
+        name: "rash_write_bool",
+        pointer: write_bool as *const u8,
+        signature: |s, pointer| {
+            s.params = vec![
+                AbiParam::new(pointer),
+                AbiParam::new(pointer),
+                AbiParam::new(types::I8),
+            ];
+        },
+    },
+    HelperFunction {
+        name: "rash_store",
+        pointer: store as *const u8,
+        signature: |s, pointer| {
+            s.params = vec![
+                AbiParam::new(pointer),
+                AbiParam::new(pointer),
+                AbiParam::new(pointer),
+            ];
+        },
+    },
+    HelperFunction {
+        name: "rash_operator",
+        pointer: operator as *const u8,
+        signature: |s, pointer| {
+            s.params = vec![AbiParam::new(pointer), AbiParam::new(pointer)];
+        },
+    },
+    HelperFunction {
+        name: "rash_modulo",
+        pointer: modulo as *const u8,
+        signature: |s, _| {
+            s.params = vec![AbiParam::new(types::F64), AbiParam::new(types::F64)];
+            s.returns = vec![AbiParam::new(types::F64)];
+        },
+    },
+    HelperFunction {
+        name: "rash_power",
+        pointer: power as *const u8,
+        signature: |s, _| {
+            s.params = vec![AbiParam::new(types::F64), AbiParam::new(types::F64)];
+            s.returns = vec![AbiParam::new(types::F64)];
+        },
+    },
+];
+
+// Called only from the machine code. The memory pointer is the &mut Memory given to
+// NativeScript::run, which the machine code passes along and doesn't use itself,
+// so each helper holds the only reference to it until it returns. Taking it as
+// & in the read helpers is fine for the same reason. The other pointers come
+// from Argument and point into the script's instructions. A panic in a helper
+// aborts instead of unwinding into the machine code.
+extern "C" fn read_number(memory: &Memory, value: &Value) -> f64 {
+    value.get_number(memory)
+}
+
+extern "C" fn read_bool(memory: &Memory, value: &Value) -> u8 {
+    value.get_bool(memory) as u8
+}
+
+extern "C" fn write_number(memory: &mut Memory, location: &Value, n: f64) {
+    memory.set(location, Value::Number(n));
+}
+
+extern "C" fn write_bool(memory: &mut Memory, location: &Value, b: u8) {
+    memory.set(location, Value::Boolean(b != 0));
+}
+
+extern "C" fn store(memory: &mut Memory, location: &Value, value: &Value) {
+    let value = memory.resolve(value).into_owned();
+    memory.set(location, value);
+}
+
+extern "C" fn operator(memory: &mut Memory, instruction: &Instruction) {
+    if let Some((location, value)) = instruction.evaluate_operator(memory) {
+        memory.set(location, value);
+    }
+}
+
+extern "C" fn modulo(a: f64, b: f64) -> f64 {
+    a.rem_euclid(b)
+}
+
+extern "C" fn power(a: f64, b: f64) -> f64 {
+    a.powf(b)
+}
+
+#[cfg(all(test, feature = "jit"))]
+mod tests {
+    use std::rc::Rc;
+
+    use crate::{
+        assembler::Assembly,
+        interpreter::{Instruction, Memory, Registers, Value},
+        thread::run_memory_instruction,
+    };
+
+    // What a script leaves in the variables and registers.
+    type Result = (Vec<Value>, Registers);
+
+    // Runs the first script in the assembly with the interpreter, and again with the JIT
+    // stepping over pauses like a thread would, and checks both end up the same.
+    fn run_both(text: &str) -> Result {
+        let assembly = Assembly::parse(text).unwrap();
+        let script = &assembly.sprites[0].scripts[0];
+        let instructions: Rc<[Instruction]> = script.instructions.clone().into();
+        let run = |jit: bool| {
+            let mut variables = assembly.variables.clone();
+            let mut registers = Registers::new(script.register_count);
+            let mut memory = Memory::new(&mut variables, &mut registers, &mut []);
+            let native = jit.then(|| super::compile(&instructions).unwrap().unwrap());
+            let mut counter = 0;
+            while let Some(instruction) = instructions.get(counter) {
+                if let Some(native) = &native {
+                    if native.can_run(counter) {
+                        counter = native.run(&mut memory, counter);
+                        continue;
+                    }
+                }
+                counter += 1;
+                if !run_memory_instruction(instruction, &mut memory, &mut counter) {
+                    match instruction {
+                        Instruction::ThreadPause => {}
+                        Instruction::ThreadKill => break,
+                        _ => panic!("{} can't run here", instruction.print(None)),
+                    }
+                }
+            }
+            (variables, registers)
+        };
+        let interpreted = run(false);
+        let native = run(true);
+        // Compared printed, so NaN matches NaN.
+        assert_eq!(format!("{native:?}"), format!("{interpreted:?}"));
+        interpreted
+    }
+
+    #[test]
+    fn arithmetic() {
+        let (variables, registers) = run_both(
+            r#"
+            .variable *0 = 7
+            .variable *1 = "2.5"
+            .variable *2 = 0
+            .variable *3 = 0
+            .sprite "Stage"
+            .script registers 4
+                r0 = *0 + *1
+                r1 = r0 * 3 (numbers)
+                f2 = r1 - 4.5 (numbers)
+                f3 = f2 / 8 (numbers)
+                *2 = f3 ^ 2 (numbers)
+                *3 = *1 - "hello" (numbers)
+                r0 = 1 / 0 (numbers)
+                f1 = f3 % 2 (numbers)
+                f0 = *1;
+            "#,
+        );
+        assert_eq!(variables[2], Value::Number(9.0));
+        assert_eq!(variables[3], Value::Number(2.5));
+        assert_eq!(registers.values[0], Value::Number(f64::INFINITY));
+        assert_eq!(*registers.numbers, [2.5, 1.0, 24.0, 3.0]);
+    }
+
+    #[test]
+    fn modulo() {
+        let (variables, _) = run_both(
+            r#"
+            .variable *0 = 0
+            .variable *1 = 0
+            .variable *2 = 0
+            .variable *3 = 0
+            .variable *4 = 0
+            .variable *5 = 0
+            .sprite "Stage"
+            .script registers 0
+                *0 = 7 % 3 (numbers)
+                *1 = -7 % 3 (numbers)
+                *2 = -7.5 % 2 (numbers)
+                *3 = "-7" % 3
+                *4 = 5 % 0 (numbers)
+                *5 = *3 % *1 (numbers)
+            "#,
+        );
+        assert_eq!(variables[0], Value::Number(1.0));
+        assert_eq!(variables[1], Value::Number(2.0));
+        assert_eq!(variables[2], Value::Number(0.5));
+        assert_eq!(variables[3], Value::Number(2.0));
+        assert!(matches!(variables[4], Value::Number(n) if n.is_nan()));
+        assert_eq!(variables[5], Value::Number(0.0));
+    }
+
+    #[test]
+    fn comparisons() {
+        let (variables, registers) = run_both(
+            r#"
+            .variable *0 = 0
+            .variable *1 = 0
+            .variable *2 = 0
+            .variable *3 = 0
+            .variable *4 = 0
+            .variable *5 = "10"
+            .variable *6 = 0
+            .sprite "Stage"
+            .script registers 3
+                *0 = 1 < 2 (numbers)
+                *1 = 1 > 2 (numbers)
+                *2 = *5 == 10 (numbers)
+                *3 = "10.0" == 10
+                *4 = NaN == NaN (numbers)
+                r0 = *5 > 9
+                if !r0 goto skip
+                *6 = 1;
+            skip:
+                if *1 goto end
+                *6 = *6 + 1 (numbers)
+                b1 = *5 < 3 (numbers)
+                b2 = *5 > "9"
+                if b1 goto end
+                if !b2 goto end
+                *6 = *6 + 1 (numbers)
+            end:
+                return;
+            "#,
+        );
+        assert_eq!(
+            variables[..5],
+            [
+                Value::Boolean(true),
+                Value::Boolean(false),
+                Value::Boolean(true),
+                Value::Boolean(true),
+                Value::Boolean(false),
+            ]
+        );
+        assert_eq!(variables[6], Value::Number(3.0));
+        assert_eq!(*registers.booleans, [false, false, true]);
+    }
+
+    #[test]
+    fn counting_loop() {
+        let (variables, _) = run_both(
+            r#"
+            .variable *0 = 0
+            .variable *1 = 0
+            .sprite "Stage"
+            .script registers 1
+            loop:
+                *0 = *0 + 1 (numbers)
+                *1 = *1 + *0 (numbers)
+                render_frame()
+                b0 = *0 < 1000 (numbers)
+                if b0 goto loop
+            "#,
+        );
+        assert_eq!(variables[0], Value::Number(1000.0));
+        assert_eq!(variables[1], Value::Number(500500.0));
+    }
+}
@@ -0,0 +1,293 @@
+use aot::CompiledProject;
+use arguments::Arguments;
+use audio::{audio_mixer::AudioMixer, audio_samples::MusicSamples};
+use clock::Clock;
+use project_state::Renderer;
+
+/**
+ *  Rash, a Scratch interpreter written in Rust
+ *  Copyright (C) 2023 Mrmayman<navneetkrishna22@gmail.com>
+ *
+ *  This program is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *  
+ *  This program is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *  
+ *  You should have received a copy of the GNU General Public License
+ *  along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+pub mod ansi_codes;
+pub mod aot;
+#[cfg(feature = "tools")]
+pub mod aot_compiler;
+pub mod arguments;
+mod ask_prompt;
+// Tests use it to run bytecode.
+#[cfg(any(feature = "tools", test))]
+pub mod assembler;
+mod clock;
+mod costume_loader;
+pub mod interpreter;
+#[cfg(feature = "jit")]
+mod jit;
+mod monitor;
+mod pen_color;
+mod pen_line;
+mod project_state;
+mod sprite;
+mod text_renderer;
+mod thread;
+mod viewport;
+
+mod audio {
+    pub mod audio_decoder;
+    pub mod audio_effects;
+    pub mod audio_mixer;
+    pub mod audio_samples;
+    pub mod audio_synth;
+}
+
+mod project {
+    pub mod project_cache;
+    pub mod project_config;
+    pub mod project_file_loader;
+    pub mod project_main;
+}
+
+mod bc_compiler {
+    pub mod bc_comp_diagnostics;
+    pub mod bc_comp_main;
+    pub mod bc_comp_optimizer;
+    pub mod bc_comp_register_allocator;
+    pub mod bc_comp_type_inference;
+    pub mod bc_comp_variable_manager;
+    pub mod bc_comp_verifier;
+}
+
+mod blocks {
+    pub mod block_control;
+    pub mod block_looks;
+    pub mod block_motion;
+    pub mod block_music;
+    pub mod block_operators;
+    pub mod block_pen;
+    pub mod block_procedures;
+    pub mod block_sensing;
+    pub mod block_sound;
+    pub mod block_variables;
+}
+
+mod third_party {
+    pub mod svg_to_png;
+}
+
+// Runs a project until its window is closed. Compiled games pass in their own
+// copy of the project along with the native code for its scripts.
+pub fn run(arguments: Arguments, compiled: Option<&CompiledProject>) {
+    // Has to be set before SDL starts. "dummy" and "disk" work without a sound card.
+    if let Some(audio_driver) = &arguments.audio_driver {
+        sdl2::hint::set("SDL_AUDIODRIVER", audio_driver);
+    }
+    if arguments.headless {
+        sdl2::hint::set("SDL_VIDEODRIVER", "dummy");
+    }
+    let sdl_context = sdl2::init().unwrap();
+    let video_subsystem = sdl_context.video().unwrap();
+    let mut window_builder = video_subsystem.window("Rash", 800, 600);
+    window_builder.position_centered().resizable();
+    if arguments.headless {
+        window_builder.hidden();
+    }
+    let window = window_builder.build().unwrap();
+    let mut canvas = window.into_canvas().build().unwrap();
+    let texture_creator = canvas.texture_creator();
+    let mut last_frame_time = std::time::Instant::now();
+    // Counted in frames rather than wall clock time, so headless runs are repeatable.
+    let mut frames_run: u64 = 0;
+
+    let archive = match compiled {
+        Some(compiled) => compiled.archive.to_vec(),
+        None => std::fs::read(&arguments.project_path).expect("Could not read .sb3 file"),
+    };
+    let mut project = project::project_main::Project::new(
+        archive,
+        &texture_creator,
+        !arguments.no_cache,
+        compiled,
+    )
+    .expect("Could not load project");
+    if let Err(err) = project.compile_report.show(&arguments.report) {
+        eprintln!("{}[error]{} {err}", ansi_codes::RED, ansi_codes::RESET);
+        std::process::exit(1);
+    }
+
+    // The command line wins over whatever the project asks for.
+    let stage_size = arguments
+        .stage_size
+        .unwrap_or((project.config.stage_width, project.config.stage_height));
+    let mut renderer = Renderer::new(
+        &texture_creator,
+        &mut canvas,
+        arguments.pen_resolution,
+        stage_size,
+        match arguments.capture_audio {
+            Some(_) => AudioMixer::new_capture(),
+            None => AudioMixer::new(&sdl_context, arguments.audio_device.as_deref()),
+        },
+    );
+    renderer.username = arguments.username;
+    renderer.tempo = project.config.tempo;
+    renderer.music_samples = MusicSamples::find(arguments.music_samples.as_deref());
+    renderer.turbo_mode = arguments.turbo || project.config.turbo;
+    if arguments.headless || arguments.capture_audio.is_some() {
+        renderer.clock = Clock::frame_counted();
+    }
+    let frame_rate = arguments.fps.unwrap_or(project.config.framerate) as f64;
+    let frame_time = std::time::Duration::from_secs_f64(1.0 / frame_rate);
+
+    project.green_flag(&mut renderer);
+
+    let mut event_pump = sdl_context.event_pump().unwrap();
+
+    'running: loop {
+        if let Some(run_for) = arguments.run_for {
+            if frames_run as f64 / frame_rate >= run_for {
+                break 'running;
+            }
+        }
+        for event in event_pump.poll_iter() {
+            match event {
+                sdl2::event::Event::Quit { .. } => break 'running,
+                sdl2::event::Event::KeyDown {
+                    keycode: Some(sdl2::keyboard::Keycode::F11),
+                    ..
+                } => toggle_fullscreen(&mut canvas),
+                sdl2::event::Event::KeyDown {
+                    keycode: Some(sdl2::keyboard::Keycode::F5),
+                    ..
+                } => project.green_flag(&mut renderer),
+                sdl2::event::Event::KeyDown {
+                    keycode: Some(sdl2::keyboard::Keycode::F6),
+                    ..
+                } => project.stop(&mut renderer),
+                sdl2::event::Event::KeyDown {
+                    keycode: Some(sdl2::keyboard::Keycode::F10),
+                    ..
+                } => renderer.turbo_mode = !renderer.turbo_mode,
+                _ => {
+                    renderer.ask_prompt.handle_event(&event);
+                    project.handle_event(&event, &mut renderer);
+                }
+            }
+        }
+        // Cheap enough to do every frame, and catches resizes and fullscreen changes.
+        renderer.viewport = renderer.viewport.resized(canvas.output_size().unwrap());
+
+        // Only capture typing while a question is being asked.
+        let text_input = video_subsystem.text_input();
+        if renderer.ask_prompt.is_active() != text_input.is_active() {
+            if renderer.ask_prompt.is_active() {
+                text_input.start();
+            } else {
+                text_input.stop();
+            }
+        }
+
+        // Letterbox the stage so it keeps its aspect ratio.
+        canvas.set_draw_color(sdl2::pixels::Color::RGB(0, 0, 0));
+        canvas.clear();
+        canvas.set_draw_color(sdl2::pixels::Color::RGB(255, 255, 255));
+        canvas.fill_rect(renderer.viewport.rect()).unwrap();
+
+        project.run(&mut canvas, &mut renderer, frame_time);
+        // Don't let sprites poke out into the black bars.
+        canvas.set_clip_rect(renderer.viewport.rect());
+        project.draw(&mut canvas, &mut renderer);
+        canvas.set_clip_rect(None);
+        canvas.present();
+        renderer.audio.capture_frame(frame_time.as_secs_f64());
+        renderer.clock.end_frame(frame_time);
+        frames_run += 1;
+
+        let elapsed = last_frame_time.elapsed();
+        last_frame_time = std::time::Instant::now();
+
+        if elapsed < frame_time {
+            std::thread::sleep(frame_time - elapsed);
+        }
+    }
+
+    if let Some(path) = &arguments.capture_audio {
+        match renderer.audio.write_capture(path) {
+            Ok(()) => println!(
+                "{}[info]{} Saved audio to {path}",
+                ansi_codes::GREEN,
+                ansi_codes::RESET
+            ),
+            Err(err) => eprintln!("{}[audio]{} {err}", ansi_codes::RED, ansi_codes::RESET),
+        }
+    }
+}
+
+// Prints a compiled project from the cache. Takes either the project,
+// which has to have been run before, or a cache file.
+#[cfg(feature = "tools")]
+pub fn inspect(path: &str) -> Result<(), String> {
+    use project::project_cache::ProjectCache;
+
+    let bytes = std::fs::read(path).map_err(|err| format!("Could not read {path}: {err}"))?;
+    let cache_path = if path.ends_with(".sb3") {
+        let cache_path = ProjectCache::path(&ProjectCache::hash(&bytes));
+        if !cache_path.exists() {
+            return Err(format!(
+                "{path} hasn't been run yet, so it isn't in the cache"
+            ));
+        }
+        cache_path
+    } else {
+        std::path::PathBuf::from(path)
+    };
+    let bytes = std::fs::read(&cache_path)
+        .map_err(|err| format!("Could not read {}: {err}", cache_path.display()))?;
+    println!(
+        "{}[info]{} {}",
+        ansi_codes::GREEN,
+        ansi_codes::RESET,
+        cache_path.display()
+    );
+    ProjectCache::decode(&bytes)?.dump();
+    Ok(())
+}
+
+fn toggle_fullscreen(canvas: &mut sdl2::render::Canvas<sdl2::video::Window>) {
+    use sdl2::video::FullscreenType;
+    let window = canvas.window_mut();
+    let new_state = match window.fullscreen_state() {
+        FullscreenType::Off => FullscreenType::Desktop,
+        _ => FullscreenType::Off,
+    };
+    if let Err(err) = window.set_fullscreen(new_state) {
+        eprintln!(
+            "{}[window]{} Could not toggle fullscreen: {err}",
+            ansi_codes::RED,
+            ansi_codes::RESET
+        );
+    }
+}
+
+fn _calculate_pi() -> f64 {
+    let mut pi = 0.0;
+    let n = 4.0;
+    let mut d = 1.0;
+    for i in 0..10_000_000 {
+        let a = (2 * (i % 2) - 1) as f64;
+        pi += a * n / d;
+        d += 2.0;
+    }
+    pi
+}
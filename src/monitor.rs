@@ -0,0 +1,428 @@
+use crate::{
+    ansi_codes,
+    bc_compiler::{
+        bc_comp_diagnostics::{CompileReport, Diagnostic, DiagnosticKind},
+        bc_comp_variable_manager::VariableCompiler,
+    },
+    interpreter::{Memory, Value},
+    text_renderer::{escape_xml, estimate_text_width, render_svg},
+    viewport::Viewport,
+};
+
+type SDLTextureCreator = sdl2::render::TextureCreator<sdl2::video::WindowContext>;
+
+const FONT: &str = "Helvetica, Arial, sans-serif";
+const LABEL_COLOR: &str = "#575e75";
+const VARIABLE_COLOR: &str = "#ff8c1a";
+const LIST_COLOR: &str = "#fc662c";
+
+const ROW_HEIGHT: f64 = 25.0;
+const SLIDER_HEIGHT: f64 = 18.0;
+const SLIDER_PADDING: f64 = 10.0;
+
+const LIST_HEADER_HEIGHT: f64 = 20.0;
+const LIST_FOOTER_HEIGHT: f64 = 20.0;
+const LIST_ROW_HEIGHT: f64 = 22.0;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum MonitorMode {
+    Default,
+    Large,
+    Slider,
+    List,
+}
+
+pub struct Monitor<'a> {
+    pub pointer: usize,
+    pub label: String,
+    pub mode: MonitorMode,
+    // Position of the top left corner, in stage pixels from the top left of the stage.
+    pub x: f64,
+    pub y: f64,
+    pub visible: bool,
+    pub slider_min: f64,
+    pub slider_max: f64,
+    pub is_discrete: bool,
+    width: f64,
+    // Size of a list monitor. Variable monitors size themselves to fit.
+    list_height: f64,
+    // Index of the first list item shown.
+    scroll: usize,
+    texture: Option<sdl2::render::Texture<'a>>,
+    // The value and scale the texture was last rendered with.
+    rendered: Option<(String, f32)>,
+}
+
+impl<'a> Monitor<'a> {
+    pub fn new(pointer: usize, label: String, x: f64, y: f64) -> Monitor<'a> {
+        Monitor {
+            pointer,
+            label,
+            mode: MonitorMode::Default,
+            x,
+            y,
+            visible: false,
+            slider_min: 0.0,
+            slider_max: 100.0,
+            is_discrete: true,
+            width: 0.0,
+            list_height: 0.0,
+            scroll: 0,
+            texture: None,
+            rendered: None,
+        }
+    }
+
+    fn from_json(monitor_json: &serde_json::Value, pointer: usize) -> Monitor<'a> {
+        let name = match monitor_json["params"]["VARIABLE"].as_str() {
+            Some(name) => name,
+            None => monitor_json["params"]["LIST"].as_str().unwrap_or(""),
+        };
+        let label = match monitor_json["spriteName"].as_str() {
+            Some(sprite_name) => format!("{sprite_name}: {name}"),
+            None => name.to_owned(),
+        };
+        let mut monitor = Monitor::new(
+            pointer,
+            label,
+            monitor_json["x"].as_f64().unwrap_or(0.0),
+            monitor_json["y"].as_f64().unwrap_or(0.0),
+        );
+        monitor.mode = match monitor_json["mode"].as_str() {
+            Some("large") => MonitorMode::Large,
+            Some("slider") => MonitorMode::Slider,
+            Some("list") => MonitorMode::List,
+            _ => MonitorMode::Default,
+        };
+        if monitor.mode == MonitorMode::List {
+            // A size of 0 means the monitor was never resized in the editor.
+            monitor.width = match monitor_json["width"].as_f64() {
+                Some(width) if width > 0.0 => width,
+                _ => 100.0,
+            };
+            monitor.list_height = match monitor_json["height"].as_f64() {
+                Some(height) if height > 0.0 => height,
+                _ => 200.0,
+            };
+        }
+        monitor.visible = monitor_json["visible"].as_bool().unwrap_or(false);
+        monitor.slider_min = monitor_json["sliderMin"].as_f64().unwrap_or(0.0);
+        monitor.slider_max = monitor_json["sliderMax"].as_f64().unwrap_or(100.0);
+        monitor.is_discrete = monitor_json["isDiscrete"].as_bool().unwrap_or(true);
+        monitor
+    }
+
+    pub fn height(&self) -> f64 {
+        match self.mode {
+            MonitorMode::Slider => ROW_HEIGHT + SLIDER_HEIGHT,
+            MonitorMode::Default | MonitorMode::Large => ROW_HEIGHT,
+            MonitorMode::List => self.list_height,
+        }
+    }
+
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        self.visible
+            && x >= self.x
+            && x <= self.x + self.width
+            && y >= self.y
+            && y <= self.y + self.height()
+    }
+
+    pub fn scroll_by(&mut self, rows: i32, memory: &[Value]) {
+        if let Value::List(items) = &memory[self.pointer] {
+            let max_scroll = items.len().saturating_sub(self.get_visible_rows());
+            self.scroll = (self.scroll as i64 + rows as i64).clamp(0, max_scroll as i64) as usize;
+        }
+    }
+
+    fn get_visible_rows(&self) -> usize {
+        ((self.list_height - LIST_HEADER_HEIGHT - LIST_FOOTER_HEIGHT) / LIST_ROW_HEIGHT).floor()
+            as usize
+    }
+
+    pub fn draw(
+        &mut self,
+        canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
+        viewport: &Viewport,
+        memory: &[Value],
+        list_versions: &[u64],
+        font_database: &usvg_text_layout::fontdb::Database,
+        texture_creator: &'a SDLTextureCreator,
+    ) {
+        if !self.visible {
+            return;
+        }
+        let scale = viewport.scale as f32;
+        let value = match &memory[self.pointer] {
+            Value::List(items) => {
+                // Keep the scroll position valid if the list shrunk.
+                let max_scroll = items.len().saturating_sub(self.get_visible_rows());
+                self.scroll = self.scroll.min(max_scroll);
+                format!("{} {}", list_versions[self.pointer], self.scroll)
+            }
+            value => get_display_value(value),
+        };
+
+        let is_outdated = match &self.rendered {
+            Some((rendered_value, rendered_scale)) => {
+                *rendered_value != value || *rendered_scale != scale
+            }
+            None => true,
+        };
+        if is_outdated {
+            let svg = self.get_svg(&value, &memory[self.pointer]);
+            match render_svg(&svg, scale, font_database, texture_creator) {
+                Ok(texture) => self.texture = Some(texture),
+                Err(err) => eprintln!("{}[monitor]{} {err}", ansi_codes::RED, ansi_codes::RESET),
+            }
+            self.rendered = Some((value, scale));
+        }
+
+        if let Some(texture) = &self.texture {
+            let query = texture.query();
+            let (x, y) = viewport.stage_pixel_to_window((self.x, self.y));
+            canvas
+                .copy(
+                    texture,
+                    None,
+                    sdl2::rect::Rect::new(x as i32, y as i32, query.width, query.height),
+                )
+                .unwrap();
+        }
+    }
+
+    fn get_svg(&mut self, value: &str, raw_value: &Value) -> String {
+        if let Value::List(items) = raw_value {
+            return self.get_list_svg(items);
+        }
+        let escaped_value = escape_xml(value);
+        if let MonitorMode::Large = self.mode {
+            self.width = (estimate_text_width(value, 15.0) + 12.0).max(40.0);
+            return format!(
+                r##"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}">
+<rect x="0.5" y="0.5" width="{rw}" height="{rh}" rx="4" fill="{VARIABLE_COLOR}" stroke="#c3ccd9"/>
+<text x="{cx}" y="17" font-family="{FONT}" font-size="15" fill="white" text-anchor="middle">{escaped_value}</text>
+</svg>"##,
+                w = self.width,
+                h = ROW_HEIGHT,
+                rw = self.width - 1.0,
+                rh = ROW_HEIGHT - 1.0,
+                cx = self.width / 2.0,
+            );
+        }
+
+        let label_width = estimate_text_width(&self.label, 11.0);
+        let value_width = (estimate_text_width(value, 11.0) + 10.0).max(40.0);
+        self.width = 5.0 + label_width + 5.0 + value_width + 5.0;
+        if self.mode == MonitorMode::Slider {
+            self.width = self.width.max(120.0);
+        }
+        let value_x = self.width - 5.0 - value_width;
+        let height = self.height();
+
+        let slider = if self.mode == MonitorMode::Slider {
+            let progress = ((raw_value.get_number(&Memory::empty()) - self.slider_min)
+                / (self.slider_max - self.slider_min))
+                .clamp(0.0, 1.0);
+            let progress = if progress.is_nan() { 0.0 } else { progress };
+            let track_width = self.width - 2.0 * SLIDER_PADDING;
+            format!(
+                r##"<rect x="{SLIDER_PADDING}" y="{ty}" width="{track_width}" height="4" rx="2" fill="#d9d9d9"/>
+<rect x="{SLIDER_PADDING}" y="{ty}" width="{filled}" height="4" rx="2" fill="{VARIABLE_COLOR}"/>
+<circle cx="{kx}" cy="{ky}" r="6" fill="white" stroke="#b3b3b3"/>"##,
+                ty = ROW_HEIGHT + 5.0,
+                filled = track_width * progress,
+                kx = SLIDER_PADDING + track_width * progress,
+                ky = ROW_HEIGHT + 7.0,
+            )
+        } else {
+            String::new()
+        };
+
+        format!(
+            r##"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{height}">
+<rect x="0.5" y="0.5" width="{rw}" height="{rh}" rx="4" fill="#e6f0ff" stroke="#c3ccd9"/>
+<text x="5" y="16" font-family="{FONT}" font-size="11" font-weight="bold" fill="{LABEL_COLOR}">{label}</text>
+<rect x="{value_x}" y="3" width="{value_width}" height="18" rx="4" fill="{VARIABLE_COLOR}"/>
+<text x="{cx}" y="16" font-family="{FONT}" font-size="11" fill="white" text-anchor="middle">{escaped_value}</text>
+{slider}
+</svg>"##,
+            w = self.width,
+            rw = self.width - 1.0,
+            rh = height - 1.0,
+            label = escape_xml(&self.label),
+            cx = value_x + value_width / 2.0,
+        )
+    }
+
+    fn get_list_svg(&self, items: &[Value]) -> String {
+        let body_height = self.list_height - LIST_HEADER_HEIGHT - LIST_FOOTER_HEIGHT;
+        let index_width = estimate_text_width(&items.len().to_string(), 10.0) + 8.0;
+        let item_width = self.width - index_width - 8.0;
+        let max_characters = ((item_width - 8.0) / (10.0 * 0.6)).max(1.0) as usize;
+
+        let mut rows = String::new();
+        if items.is_empty() {
+            rows.push_str(&format!(
+                r#"<text x="{cx}" y="{cy}" font-family="{FONT}" font-size="10" fill="{LABEL_COLOR}" text-anchor="middle">(empty)</text>"#,
+                cx = self.width / 2.0,
+                cy = LIST_HEADER_HEIGHT + body_height / 2.0,
+            ));
+        }
+        let last_row = items.len().min(self.scroll + self.get_visible_rows() + 1);
+        for (row, item) in items[self.scroll.min(last_row)..last_row]
+            .iter()
+            .enumerate()
+        {
+            let index = self.scroll + row + 1;
+            let y = LIST_HEADER_HEIGHT + 2.0 + row as f64 * LIST_ROW_HEIGHT;
+            let mut text = get_display_value(item);
+            if text.chars().count() > max_characters {
+                text = text
+                    .chars()
+                    .take(max_characters.saturating_sub(1))
+                    .collect();
+                text.push('…');
+            }
+            rows.push_str(&format!(
+                r##"<text x="4" y="{ty}" font-family="{FONT}" font-size="10" font-weight="bold" fill="{LABEL_COLOR}">{index}</text>
+<rect x="{index_width}" y="{y}" width="{item_width}" height="{rh}" rx="3" fill="{LIST_COLOR}" stroke="#e25a26"/>
+<text x="{tx}" y="{ty}" font-family="{FONT}" font-size="10" fill="white">{text}</text>
+"##,
+                ty = y + 14.0,
+                rh = LIST_ROW_HEIGHT - 2.0,
+                tx = index_width + 4.0,
+                text = escape_xml(&text),
+            ));
+        }
+
+        format!(
+            r##"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}">
+<defs><clipPath id="body"><rect x="0" y="{LIST_HEADER_HEIGHT}" width="{w}" height="{body_height}"/></clipPath></defs>
+<rect x="0.5" y="0.5" width="{rw}" height="{rh}" rx="4" fill="#e6f0ff" stroke="#c3ccd9"/>
+<text x="{cx}" y="14" font-family="{FONT}" font-size="11" font-weight="bold" fill="{LABEL_COLOR}" text-anchor="middle">{label}</text>
+<g clip-path="url(#body)">
+{rows}</g>
+<text x="{cx}" y="{fy}" font-family="{FONT}" font-size="10" fill="{LABEL_COLOR}" text-anchor="middle">length {length}</text>
+</svg>"##,
+            w = self.width,
+            h = self.list_height,
+            rw = self.width - 1.0,
+            rh = self.list_height - 1.0,
+            cx = self.width / 2.0,
+            label = escape_xml(&self.label),
+            fy = self.list_height - 6.0,
+            length = items.len(),
+        )
+    }
+
+    // Returns the value the slider should be set to if the point
+    // (in stage pixels from the top left) is on the slider.
+    pub fn get_slider_value_at(&self, x: f64, y: f64, is_dragging: bool) -> Option<f64> {
+        if !self.visible || self.mode != MonitorMode::Slider {
+            return None;
+        }
+        let track_start = self.x + SLIDER_PADDING;
+        let track_width = self.width - 2.0 * SLIDER_PADDING;
+        let is_on_slider = x >= self.x
+            && x <= self.x + self.width
+            && y >= self.y + ROW_HEIGHT
+            && y <= self.y + self.height();
+        if !is_on_slider && !is_dragging {
+            return None;
+        }
+
+        let progress = ((x - track_start) / track_width).clamp(0.0, 1.0);
+        let value = self.slider_min + progress * (self.slider_max - self.slider_min);
+        Some(if self.is_discrete {
+            value.round()
+        } else {
+            value
+        })
+    }
+}
+
+pub fn get_display_value(value: &Value) -> String {
+    match value {
+        // Scratch rounds numbers shown in monitors to 6 decimal places.
+        Value::Number(n) => {
+            let rounded = (n * 1_000_000.0).round() / 1_000_000.0;
+            if rounded.is_finite() {
+                rounded.to_string()
+            } else {
+                value.get_string(&Memory::empty())
+            }
+        }
+        _ => value.get_string(&Memory::empty()),
+    }
+}
+
+pub fn load_monitors<'a>(
+    project_json: &serde_json::Value,
+    variables: &VariableCompiler,
+    report: &mut CompileReport,
+) -> Vec<Monitor<'a>> {
+    let mut monitors: Vec<Monitor<'a>> = vec![];
+    let mut monitor_ids: Vec<&str> = vec![];
+
+    if let Some(monitors_json) = project_json["monitors"].as_array() {
+        for monitor_json in monitors_json {
+            let id = monitor_json["id"].as_str().unwrap_or("");
+            match monitor_json["opcode"].as_str() {
+                Some("data_variable") | Some("data_listcontents") => {}
+                Some(opcode) => {
+                    report.diagnostics.push(Diagnostic {
+                        kind: DiagnosticKind::UnsupportedBlock,
+                        // Null for monitors of the stage.
+                        sprite: monitor_json["spriteName"]
+                            .as_str()
+                            .unwrap_or("Stage")
+                            .to_owned(),
+                        block_id: Some(id.to_owned()),
+                        opcode: Some(opcode.to_owned()),
+                        message: "Monitors of this block aren't supported".to_owned(),
+                    });
+                    continue;
+                }
+                None => continue,
+            }
+            match variables.get_id(id) {
+                Some(pointer) => {
+                    monitors.push(Monitor::from_json(monitor_json, pointer));
+                    monitor_ids.push(id);
+                }
+                None => eprintln!(
+                    "{}[monitor]{} Could not find variable for monitor {id}",
+                    ansi_codes::RED,
+                    ansi_codes::RESET
+                ),
+            }
+        }
+    }
+
+    // Variables without a monitor entry still need one for "show variable".
+    let sprites = project_json["targets"].as_array().map(Vec::as_slice);
+    for sprite_json in sprites.unwrap_or_default() {
+        let Some(sprite_variables) = sprite_json["variables"].as_object() else {
+            continue;
+        };
+        for (variable_id, variable_data) in sprite_variables {
+            if monitor_ids.contains(&variable_id.as_str()) {
+                continue;
+            }
+            let Some(pointer) = variables.get_id(variable_id) else {
+                continue;
+            };
+            let name = variable_data[0].as_str().unwrap_or("");
+            let label = if sprite_json["isStage"].as_bool().unwrap_or(false) {
+                name.to_owned()
+            } else {
+                format!("{}: {name}", sprite_json["name"].as_str().unwrap_or(""))
+            };
+            let y = 5.0 + (monitors.len() as f64 * (ROW_HEIGHT + 2.0)) % 350.0;
+            monitors.push(Monitor::new(pointer, label, 5.0, y));
+        }
+    }
+
+    monitors
+}
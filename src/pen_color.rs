@@ -0,0 +1,213 @@
+use sdl2::pixels::Color;
+
+use crate::interpreter::{Memory, Value};
+
+// The pen colour as Scratch stores it. Every parameter goes from 0 to 100.
+#[derive(Clone)]
+pub struct PenColor {
+    pub hue: f64,
+    pub saturation: f64,
+    pub brightness: f64,
+    pub transparency: f64,
+    // Scratch 2's "shade", only used by the legacy hue and shade blocks. Goes from 0 to 200.
+    pub shade: f64,
+}
+
+impl Default for PenColor {
+    fn default() -> Self {
+        // Blue, like Scratch.
+        Self {
+            hue: 66.66,
+            saturation: 100.0,
+            brightness: 100.0,
+            transparency: 0.0,
+            shade: 50.0,
+        }
+    }
+}
+
+impl PenColor {
+    pub fn to_rgba(&self) -> Color {
+        let (r, g, b) = hsv_to_rgb(
+            self.hue * 360.0 / 100.0,
+            self.saturation / 100.0,
+            self.brightness / 100.0,
+        );
+        let alpha = (1.0 - self.transparency / 100.0) * 255.0;
+        Color::RGBA(r, g, b, alpha.round() as u8)
+    }
+
+    pub fn set_to_color(&mut self, color: &Value, memory: &Memory) {
+        let color = memory.resolve(color);
+        let (r, g, b, alpha) = match &*color {
+            Value::String(hex) if hex.starts_with('#') => {
+                let (r, g, b) = hex_to_rgb(hex).unwrap_or((0, 0, 0));
+                (r, g, b, None)
+            }
+            _ => decimal_to_rgba(color.get_number(memory)),
+        };
+
+        let (h, s, v) = rgb_to_hsv(r, g, b);
+        self.hue = h / 360.0 * 100.0;
+        self.saturation = s * 100.0;
+        self.brightness = v * 100.0;
+        self.transparency = match alpha {
+            Some(alpha) => 100.0 * (1.0 - alpha as f64 / 255.0),
+            None => 0.0,
+        };
+        // Set the legacy shade the same way Scratch 2 did.
+        self.shade = self.brightness / 2.0;
+    }
+
+    pub fn set_param(&mut self, param: &str, value: f64, is_change: bool) {
+        match param {
+            "color" => {
+                let base = if is_change { self.hue } else { 0.0 };
+                self.hue = wrap_clamp(base + value, 0.0, 100.0);
+            }
+            "saturation" => {
+                let base = if is_change { self.saturation } else { 0.0 };
+                self.saturation = (base + value).clamp(0.0, 100.0);
+            }
+            "brightness" => {
+                let base = if is_change { self.brightness } else { 0.0 };
+                self.brightness = (base + value).clamp(0.0, 100.0);
+            }
+            "transparency" => {
+                let base = if is_change { self.transparency } else { 0.0 };
+                self.transparency = (base + value).clamp(0.0, 100.0);
+            }
+            _ => {}
+        }
+    }
+
+    // Scratch 2 hues went from 0 to 200.
+    pub fn set_legacy_hue(&mut self, hue: f64) {
+        self.set_param("color", hue / 2.0, false);
+        self.set_param("transparency", 0.0, false);
+        self.update_legacy_color();
+    }
+
+    pub fn set_legacy_shade(&mut self, shade: f64) {
+        self.shade = shade.rem_euclid(200.0);
+        self.update_legacy_color();
+    }
+
+    fn update_legacy_color(&mut self) {
+        let rgb = hsv_to_rgb(self.hue * 360.0 / 100.0, 1.0, 1.0);
+        let shade = if self.shade > 100.0 {
+            200.0 - self.shade
+        } else {
+            self.shade
+        };
+        let (r, g, b) = if shade < 50.0 {
+            mix_rgb((0, 0, 0), rgb, (10.0 + shade) / 60.0)
+        } else {
+            mix_rgb(rgb, (255, 255, 255), (shade - 50.0) / 60.0)
+        };
+
+        let (h, s, v) = rgb_to_hsv(r, g, b);
+        self.hue = 100.0 * h / 360.0;
+        self.saturation = 100.0 * s;
+        self.brightness = 100.0 * v;
+    }
+}
+
+fn wrap_clamp(value: f64, min: f64, max: f64) -> f64 {
+    let range = (max - min) + 1.0;
+    value - ((value - min) / range).floor() * range
+}
+
+fn hsv_to_rgb(h: f64, s: f64, v: f64) -> (u8, u8, u8) {
+    let h = h.rem_euclid(360.0);
+    let s = s.clamp(0.0, 1.0);
+    let v = v.clamp(0.0, 1.0);
+
+    let i = (h / 60.0).floor();
+    let f = (h / 60.0) - i;
+    let p = v * (1.0 - s);
+    let q = v * (1.0 - (s * f));
+    let t = v * (1.0 - (s * (1.0 - f)));
+
+    let (r, g, b) = match i as i32 {
+        1 => (q, v, p),
+        2 => (p, v, t),
+        3 => (p, q, v),
+        4 => (t, p, v),
+        5 => (v, p, q),
+        _ => (v, t, p),
+    };
+    (
+        (r * 255.0).floor() as u8,
+        (g * 255.0).floor() as u8,
+        (b * 255.0).floor() as u8,
+    )
+}
+
+fn rgb_to_hsv(r: u8, g: u8, b: u8) -> (f64, f64, f64) {
+    let r = r as f64 / 255.0;
+    let g = g as f64 / 255.0;
+    let b = b as f64 / 255.0;
+    let x = r.min(g).min(b);
+    let v = r.max(g).max(b);
+
+    // Greys have no hue, Scratch reports it as 0.
+    if x == v {
+        return (0.0, 0.0, v);
+    }
+    let f = if r == x {
+        g - b
+    } else if g == x {
+        b - r
+    } else {
+        r - g
+    };
+    let i = if r == x {
+        3.0
+    } else if g == x {
+        5.0
+    } else {
+        1.0
+    };
+    let h = ((i - (f / (v - x))) * 60.0) % 360.0;
+    let s = (v - x) / v;
+    (h, s, v)
+}
+
+fn mix_rgb(from: (u8, u8, u8), to: (u8, u8, u8), fraction: f64) -> (u8, u8, u8) {
+    if fraction <= 0.0 {
+        return from;
+    }
+    if fraction >= 1.0 {
+        return to;
+    }
+    let mix = |a: u8, b: u8| ((1.0 - fraction) * a as f64 + fraction * b as f64) as u8;
+    (mix(from.0, to.0), mix(from.1, to.1), mix(from.2, to.2))
+}
+
+fn hex_to_rgb(hex: &str) -> Option<(u8, u8, u8)> {
+    let hex = hex.trim_start_matches('#');
+    let hex = if hex.len() == 3 {
+        // Shorthand like #f00.
+        hex.chars().flat_map(|c| [c, c]).collect()
+    } else {
+        hex.to_owned()
+    };
+    if hex.len() != 6 {
+        return None;
+    }
+    let value = u32::from_str_radix(&hex, 16).ok()?;
+    Some(((value >> 16) as u8, (value >> 8) as u8, value as u8))
+}
+
+// Numbers are treated as 0xAARRGGBB, where an alpha of 0 means opaque.
+fn decimal_to_rgba(decimal: f64) -> (u8, u8, u8, Option<u8>) {
+    let decimal = decimal as i64 as u32;
+    let alpha = (decimal >> 24) as u8;
+    (
+        (decimal >> 16) as u8,
+        (decimal >> 8) as u8,
+        decimal as u8,
+        Some(if alpha > 0 { alpha } else { 255 }),
+    )
+}
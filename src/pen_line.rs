@@ -1,97 +1,86 @@
-use sdl2::rect::{Point, Rect};
+use sdl2::rect::Rect;
 
-use crate::{
-    project::project_main::get_scaled_point, project_state::Renderer, sprite::GraphicalProperties,
-};
+use crate::{project_state::Renderer, sprite::GraphicalProperties};
 
+// Draws a line with round caps from the sprite's position to the new position.
+// The line is rasterized on the CPU like Scratch's pen shader does it: every pixel
+// is covered by how far its centre is from the line segment, which gives sub-pixel
+// endpoints and anti-aliased edges. The result is then blended onto the pen layer.
 pub fn draw(
     canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
     pen_canvas: &mut Renderer<'_>,
-    properties: &mut GraphicalProperties,
+    properties: &GraphicalProperties,
     new_x: f64,
     new_y: f64,
 ) {
     if !properties.pen_down {
         return;
     }
-    let query = canvas.output_size().unwrap();
-    canvas
-        .with_texture_canvas(&mut pen_canvas.main_canvas, |texture_canvas| {
-            let start_position = get_scaled_point((properties.x, properties.y), query);
-            let end_position = get_scaled_point((new_x, new_y), query);
+    let query = pen_canvas.main_canvas.query();
+    let (layer_width, layer_height) = (query.width, query.height);
+    let viewport = pen_canvas.viewport.resized((layer_width, layer_height));
 
-            let distance = (((end_position.0 - start_position.0).pow(2)
-                + (end_position.1 - start_position.1).pow(2)) as f64)
-                .sqrt();
+    // Scratch nudges thin lines by half a pixel so they land on pixel centres
+    // instead of being smeared across two rows.
+    let offset = if properties.pen_size == 1.0 || properties.pen_size == 3.0 {
+        0.5
+    } else {
+        0.0
+    };
+    let start = viewport.stage_to_window((properties.x + offset, properties.y + offset));
+    let end = viewport.stage_to_window((new_x + offset, new_y + offset));
+    let radius = properties.pen_size * viewport.scale / 2.0;
 
-            if properties.pen_radius > 2 {
-                draw_circle_edges(properties, texture_canvas, start_position, end_position);
-            }
+    // Only the pixels near the line need to be touched.
+    let left = (start.0.min(end.0) - radius - 1.0).floor().max(0.0) as i32;
+    let top = (start.1.min(end.1) - radius - 1.0).floor().max(0.0) as i32;
+    let right = (start.0.max(end.0) + radius + 1.0)
+        .ceil()
+        .min(layer_width as f64) as i32;
+    let bottom = (start.1.max(end.1) + radius + 1.0)
+        .ceil()
+        .min(layer_height as f64) as i32;
+    if right <= left || bottom <= top {
+        return;
+    }
+    let area = Rect::new(left, top, (right - left) as u32, (bottom - top) as u32);
 
-            if distance > properties.pen_radius as f64 {
-                let angle = calculate_direction(new_x, properties, new_y);
-                let line = get_rect(start_position, properties, distance);
+    let color = properties.pen_color.to_rgba();
+    let mut pixels: Vec<u8> = Vec::with_capacity((area.width() * area.height() * 4) as usize);
+    for pixel_y in top..bottom {
+        for pixel_x in left..right {
+            let distance =
+                distance_to_segment((pixel_x as f64 + 0.5, pixel_y as f64 + 0.5), start, end);
+            let coverage = (radius + 0.5 - distance).clamp(0.0, 1.0);
+            let alpha = (coverage * color.a as f64).round() as u8;
+            pixels.extend_from_slice(&[color.r, color.g, color.b, alpha]);
+        }
+    }
 
-                texture_canvas
-                    .copy_ex(
-                        &pen_canvas.pen_line_canvas,
-                        None,
-                        line,
-                        angle,
-                        Point::new(properties.pen_radius / 2, 0),
-                        false,
-                        false,
-                    )
-                    .unwrap();
-            }
+    pen_canvas
+        .pen_line_canvas
+        .update(area, &pixels, area.width() as usize * 4)
+        .unwrap();
+    canvas
+        .with_texture_canvas(&mut pen_canvas.main_canvas, |texture_canvas| {
+            texture_canvas
+                .copy(&pen_canvas.pen_line_canvas, area, area)
+                .unwrap();
         })
         .unwrap();
 }
 
-fn get_rect(
-    start_position: (i32, i32),
-    properties: &mut GraphicalProperties,
-    distance: f64,
-) -> Rect {
-    let line_start_x = start_position.0 - (properties.pen_radius / 2);
-    let line_start_y = start_position.1;
+fn distance_to_segment(point: (f64, f64), start: (f64, f64), end: (f64, f64)) -> f64 {
+    let (line_x, line_y) = (end.0 - start.0, end.1 - start.1);
+    let (point_x, point_y) = (point.0 - start.0, point.1 - start.1);
+    let length_squared = line_x * line_x + line_y * line_y;
 
-    Rect::new(
-        line_start_x,
-        line_start_y,
-        properties.pen_radius as u32,
-        distance as u32,
-    )
-}
-
-fn calculate_direction(new_x: f64, properties: &mut GraphicalProperties, new_y: f64) -> f64 {
-    let mut angle = 180.0
-        + (((new_x - properties.x) / (new_y - properties.y)).atan()
-            * (180.0 / std::f64::consts::PI));
-    if new_y - properties.y < 0.0 {
-        angle -= 180.0
-    }
-    angle = angle.rem_euclid(360.0);
-    angle
-}
-
-fn draw_circle_edges(
-    properties: &mut GraphicalProperties,
-    texture_canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
-    sprite_rect: (i32, i32),
-    new_scaled_rect: (i32, i32),
-) {
-    for x2 in -(properties.pen_radius / 2)..(properties.pen_radius / 2) {
-        for y2 in -(properties.pen_radius / 2)..(properties.pen_radius / 2) {
-            if (x2 * x2 + y2 * y2) < ((properties.pen_radius * properties.pen_radius) / 4) {
-                texture_canvas.set_draw_color(properties.pen_color);
-                texture_canvas
-                    .draw_point(Point::new(sprite_rect.0 + x2, sprite_rect.1 + y2))
-                    .unwrap();
-                texture_canvas
-                    .draw_point(Point::new(new_scaled_rect.0 + x2, new_scaled_rect.1 + y2))
-                    .unwrap();
-            }
-        }
-    }
+    // How far along the line the closest point is, from 0 (start) to 1 (end).
+    let along = if length_squared == 0.0 {
+        0.0
+    } else {
+        ((point_x * line_x + point_y * line_y) / length_squared).clamp(0.0, 1.0)
+    };
+    let (distance_x, distance_y) = (point_x - line_x * along, point_y - line_y * along);
+    (distance_x * distance_x + distance_y * distance_y).sqrt()
 }
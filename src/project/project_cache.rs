@@ -0,0 +1,399 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    ansi_codes,
+    bc_compiler::{
+        bc_comp_diagnostics::{CompileReport, DiagnosticKind},
+        bc_comp_variable_manager::VariableCompiler,
+    },
+    costume_loader::rasterize,
+    thread::ScriptBytecode,
+};
+
+use super::project_main::Project;
+
+// Cache files start with this, then the format version as a little endian u32,
+// then the version of Rash that made them and the ProjectCache, both encoded with bincode.
+const MAGIC: &[u8; 4] = b"RASH";
+// Has to go up whenever anything saved in the cache changes shape,
+// including the instructions, or when the compiler makes different code.
+const FORMAT_VERSION: u32 = 6;
+// Caches from any other release are compiled again, in case the format
+// version was forgotten about.
+const RASH_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// Everything that is slow to work out when loading a project: the compiled
+// scripts, the variables they refer to and the costumes drawn as PNGs.
+// Sounds and monitors still come from the project itself.
+#[derive(Serialize, Deserialize)]
+pub struct ProjectCache {
+    pub variables: VariableCompiler,
+    pub sprites: Vec<CachedSprite>,
+    pub report: CompileReport,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CachedSprite {
+    pub name: String,
+    pub is_stage: bool,
+    pub x: f64,
+    pub y: f64,
+    pub shown: bool,
+    pub direction: f32,
+    pub size: f32,
+    pub volume: f64,
+    pub costume_number: usize,
+    pub costumes: Vec<CachedCostume>,
+    pub scripts: Vec<ScriptBytecode>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CachedCostume {
+    pub name: String,
+    pub centre_x: f64,
+    pub centre_y: f64,
+    pub png: Vec<u8>,
+}
+
+// Scratch always saves these fields. A missing one goes in the report as
+// broken JSON, and the default stands in for it so the rest can be checked.
+fn required<T>(
+    value: Option<T>,
+    default: T,
+    report: &mut CompileReport,
+    sprite: &str,
+    field: &str,
+) -> T {
+    value.unwrap_or_else(|| {
+        report.add(
+            DiagnosticKind::BadJson,
+            sprite,
+            format!("Field {field:?} is missing or has the wrong type"),
+        );
+        default
+    })
+}
+
+impl ProjectCache {
+    // Takes the project.json and the directory the rest of the project was extracted to.
+    // Doesn't need a window, so compiled games are built with one of these inside.
+    pub fn build(
+        json: &serde_json::Value,
+        project_path: &std::path::Path,
+        font_database: &usvg_text_layout::fontdb::Database,
+    ) -> Result<ProjectCache, String> {
+        let (variables, scripts, mut report) = Project::compile_scripts(json, false)?;
+        let Some(sprites) = json["targets"].as_array() else {
+            return Err("JSON error: No \"targets\" list of sprites".to_owned());
+        };
+
+        let mut cached_sprites = Vec::with_capacity(sprites.len());
+        for (sprite_json, scripts) in sprites.iter().zip(scripts) {
+            let name = required(sprite_json["name"].as_str(), "", &mut report, "", "name");
+            let Some(costumes) = sprite_json["costumes"].as_array() else {
+                return Err("JSON error: Cannot find costumes field in sprite.".to_owned());
+            };
+            let costumes = costumes
+                .iter()
+                .map(|costume_json| {
+                    Ok(CachedCostume {
+                        name: required(
+                            costume_json["name"].as_str(),
+                            "",
+                            &mut report,
+                            name,
+                            "costume name",
+                        )
+                        .to_owned(),
+                        centre_x: required(
+                            costume_json["rotationCenterX"].as_f64(),
+                            0.0,
+                            &mut report,
+                            name,
+                            "costume rotationCenterX",
+                        ),
+                        centre_y: required(
+                            costume_json["rotationCenterY"].as_f64(),
+                            0.0,
+                            &mut report,
+                            name,
+                            "costume rotationCenterY",
+                        ),
+                        png: rasterize(costume_json, project_path, font_database)?,
+                    })
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+
+            let is_stage = required(
+                sprite_json["isStage"].as_bool(),
+                false,
+                &mut report,
+                name,
+                "isStage",
+            );
+            let costume_number = required(
+                sprite_json["currentCostume"].as_u64(),
+                0,
+                &mut report,
+                name,
+                "currentCostume",
+            );
+            cached_sprites.push(CachedSprite {
+                name: name.to_owned(),
+                is_stage,
+                // The stage doesn't have any of these.
+                x: sprite_json["x"].as_f64().unwrap_or(0.0),
+                y: sprite_json["y"].as_f64().unwrap_or(0.0),
+                shown: sprite_json["visible"].as_bool().unwrap_or(true),
+                direction: sprite_json["direction"].as_f64().unwrap_or(90.0) as f32,
+                size: sprite_json["size"].as_f64().unwrap_or(100.0) as f32,
+                // The stage has a volume too.
+                volume: sprite_json["volume"].as_f64().unwrap_or(100.0),
+                costume_number: costume_number as usize,
+                costumes,
+                scripts,
+            });
+        }
+
+        Ok(ProjectCache {
+            variables,
+            sprites: cached_sprites,
+            report,
+        })
+    }
+
+    // The key a project is cached under.
+    pub fn hash(archive: &[u8]) -> String {
+        Sha256::digest(archive)
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect()
+    }
+
+    pub fn path(hash: &str) -> std::path::PathBuf {
+        dirs::cache_dir()
+            .unwrap_or_else(std::env::temp_dir)
+            .join("rash")
+            .join(format!("{hash}.rashc"))
+    }
+
+    // None if the project hasn't been cached yet, or was cached by a different
+    // version of the format. It just gets compiled again in that case.
+    pub fn load(hash: &str) -> Option<ProjectCache> {
+        ProjectCache::read(&ProjectCache::path(hash))
+    }
+
+    fn read(path: &std::path::Path) -> Option<ProjectCache> {
+        let bytes = std::fs::read(path).ok()?;
+        match ProjectCache::decode(&bytes) {
+            Ok(cache) => Some(cache),
+            Err(err) => {
+                eprintln!(
+                    "{}[cache]{} Ignoring {}: {err}",
+                    ansi_codes::YELLOW,
+                    ansi_codes::RESET,
+                    path.display()
+                );
+                None
+            }
+        }
+    }
+
+    pub fn save(&self, hash: &str) -> Result<std::path::PathBuf, String> {
+        let path = ProjectCache::path(hash);
+        self.write(&path)?;
+        Ok(path)
+    }
+
+    fn write(&self, path: &std::path::Path) -> Result<(), String> {
+        if let Some(directory) = path.parent() {
+            std::fs::create_dir_all(directory)
+                .map_err(|err| format!("Could not create {}: {err}", directory.display()))?;
+        }
+        std::fs::write(path, self.encode()?)
+            .map_err(|err| format!("Could not write {}: {err}", path.display()))
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>, String> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bincode::serialize_into(&mut bytes, RASH_VERSION).map_err(|err| err.to_string())?;
+        bincode::serialize_into(&mut bytes, self).map_err(|err| err.to_string())?;
+        Ok(bytes)
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<ProjectCache, String> {
+        let Some(rest) = bytes.strip_prefix(MAGIC) else {
+            return Err("Not a compiled Rash project".to_owned());
+        };
+        let (version, rest) = rest.split_at(4.min(rest.len()));
+        let version = u32::from_le_bytes(
+            version
+                .try_into()
+                .map_err(|_| "File is cut off".to_owned())?,
+        );
+        if version != FORMAT_VERSION {
+            return Err(format!(
+                "Made with format version {version}, this is version {FORMAT_VERSION}"
+            ));
+        }
+        let mut rest = rest;
+        let rash_version: String =
+            bincode::deserialize_from(&mut rest).map_err(|err| err.to_string())?;
+        if rash_version != RASH_VERSION {
+            return Err(format!(
+                "Made by Rash {rash_version}, this is Rash {RASH_VERSION}"
+            ));
+        }
+        bincode::deserialize(rest).map_err(|err| err.to_string())
+    }
+
+    // Prints what is in the cache, for looking at compiled output without running anything.
+    #[cfg(feature = "tools")]
+    pub fn dump(&self) {
+        println!(
+            "{}[variable dump]{} {{",
+            ansi_codes::GREEN,
+            ansi_codes::RESET
+        );
+        self.variables.dump();
+        println!("}}");
+        for diagnostic in self.report.diagnostics.iter() {
+            println!(
+                "{}[diagnostic]{} {:?} in {diagnostic}",
+                ansi_codes::YELLOW,
+                ansi_codes::RESET,
+                diagnostic.kind
+            );
+        }
+        for sprite in self.sprites.iter() {
+            println!(
+                "{}[sprite]{} {}{}",
+                ansi_codes::GREEN,
+                ansi_codes::RESET,
+                sprite.name,
+                if sprite.is_stage { " (stage)" } else { "" }
+            );
+            for costume in sprite.costumes.iter() {
+                println!(
+                    "    costume {} ({} bytes, centre {}, {})",
+                    costume.name,
+                    costume.png.len(),
+                    costume.centre_x,
+                    costume.centre_y
+                );
+            }
+            for (number, script) in sprite.scripts.iter().enumerate() {
+                println!(
+                    "    script {number} ({} registers) {{",
+                    script.register_count
+                );
+                for (index, instruction) in script.instructions.iter().enumerate() {
+                    println!(
+                        "        {index:>4} {}{}{}",
+                        ansi_codes::WHITE,
+                        instruction.print(Some(&self.variables)),
+                        ansi_codes::RESET
+                    );
+                }
+                println!("    }}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{CachedCostume, CachedSprite, ProjectCache, MAGIC};
+    use crate::{
+        bc_compiler::{
+            bc_comp_diagnostics::CompileReport, bc_comp_variable_manager::VariableCompiler,
+        },
+        interpreter::{Instruction, Value},
+        thread::ScriptBytecode,
+    };
+
+    fn example() -> ProjectCache {
+        let mut variables = VariableCompiler::new();
+        variables.load_from_json(&json!({
+            "variables": { "score id": ["score", 5] },
+            "lists": { "list id": ["items", ["a", 2]] },
+        }));
+        ProjectCache {
+            variables,
+            sprites: vec![CachedSprite {
+                name: "Sprite1".to_owned(),
+                is_stage: false,
+                x: -12.5,
+                y: 40.0,
+                shown: true,
+                direction: 90.0,
+                size: 150.0,
+                volume: 80.0,
+                costume_number: 1,
+                costumes: vec![CachedCostume {
+                    name: "costume1".to_owned(),
+                    centre_x: 24.0,
+                    centre_y: 12.0,
+                    png: vec![0x89, b'P', b'N', b'G'],
+                }],
+                scripts: vec![ScriptBytecode {
+                    instructions: vec![
+                        Instruction::OperatorAdd(
+                            Value::Register(0),
+                            Value::Pointer(0),
+                            Value::String("1".to_owned()),
+                        ),
+                        Instruction::MemoryStore(Value::Pointer(0), Value::Register(0)),
+                        Instruction::ThreadWarpPause,
+                        Instruction::FlowIfJump(Value::Boolean(true), 0),
+                    ],
+                    register_count: 1,
+                }],
+            }],
+            report: CompileReport::default(),
+        }
+    }
+
+    #[test]
+    fn saved_caches_load_the_same() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("rash").join("project.rashc");
+        let cache = example();
+        cache.write(&path).unwrap();
+        let loaded = ProjectCache::read(&path).unwrap();
+
+        assert_eq!(loaded.encode().unwrap(), cache.encode().unwrap());
+        assert_eq!(loaded.variables.get_id("score id"), Some(0));
+        let sprite = &loaded.sprites[0];
+        assert_eq!(sprite.name, "Sprite1");
+        assert_eq!(sprite.x, -12.5);
+        assert_eq!(sprite.costumes[0].png, cache.sprites[0].costumes[0].png);
+        assert_eq!(
+            sprite.scripts[0].instructions,
+            cache.sprites[0].scripts[0].instructions
+        );
+    }
+
+    #[test]
+    fn caches_from_other_versions_are_ignored() {
+        let bytes = example().encode().unwrap();
+
+        let mut other_format = bytes.clone();
+        other_format[MAGIC.len()] += 1;
+        assert!(ProjectCache::decode(&other_format).is_err());
+
+        // The release number is written after its length, as a u64.
+        let release = MAGIC.len() + 4 + 8;
+        let mut other_release = bytes.clone();
+        other_release[release] = b'9';
+        let err = ProjectCache::decode(&other_release).err().unwrap();
+        assert!(err.starts_with("Made by Rash 9"), "{err}");
+
+        assert!(ProjectCache::decode(&bytes[..bytes.len() / 2]).is_err());
+        assert!(ProjectCache::decode(&bytes).is_ok());
+    }
+}
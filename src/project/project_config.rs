@@ -0,0 +1,94 @@
+use crate::ansi_codes;
+
+// Project wide settings. The stage size is stored by TurboWarp,
+// plain Scratch projects get the default 480x360.
+pub struct ProjectConfig {
+    pub stage_width: u32,
+    pub stage_height: u32,
+    // Beats per minute for the music extension, saved on the stage.
+    pub tempo: f64,
+    // Frames per second. TurboWarp lets projects ask for 60.
+    pub framerate: u32,
+    pub turbo: bool,
+}
+
+impl Default for ProjectConfig {
+    fn default() -> Self {
+        Self {
+            stage_width: 480,
+            stage_height: 360,
+            tempo: 60.0,
+            framerate: 30,
+            turbo: false,
+        }
+    }
+}
+
+impl ProjectConfig {
+    pub fn from_json(project_json: &serde_json::Value) -> ProjectConfig {
+        let mut config = ProjectConfig::default();
+        if let Some(tempo) = get_stage(project_json).and_then(|stage| stage["tempo"].as_f64()) {
+            config.tempo = tempo;
+        }
+        let Some(twconfig) = find_twconfig(project_json) else {
+            return config;
+        };
+
+        let dimension = |name: &str| {
+            twconfig[name]
+                .as_u64()
+                .filter(|size| (1..=4096).contains(size))
+                .map(|size| size as u32)
+        };
+        if let Some(width) = dimension("width") {
+            config.stage_width = width;
+        }
+        if let Some(height) = dimension("height") {
+            config.stage_height = height;
+        }
+        if let Some(framerate) = twconfig["framerate"]
+            .as_u64()
+            .filter(|framerate| (1..=240).contains(framerate))
+        {
+            config.framerate = framerate as u32;
+        }
+        if let Some(turbo) = twconfig["turbo"].as_bool() {
+            config.turbo = turbo;
+        }
+        config
+    }
+}
+
+// TurboWarp saves its settings as JSON in a comment on the stage, on a line ending with
+// "// _twconfig_". The rest of the comment is a note telling people not to edit it.
+fn find_twconfig(project_json: &serde_json::Value) -> Option<serde_json::Value> {
+    let stage = get_stage(project_json)?;
+    for comment in stage["comments"].as_object()?.values() {
+        let Some(text) = comment["text"].as_str() else {
+            continue;
+        };
+        let Some(marker) = text.find("// _twconfig_") else {
+            continue;
+        };
+        let text = &text[..marker];
+        let (Some(start), Some(end)) = (text.find('{'), text.rfind('}')) else {
+            continue;
+        };
+        match serde_json::from_str(&text[start..=end]) {
+            Ok(config) => return Some(config),
+            Err(err) => eprintln!(
+                "{}[warning]{} Could not parse TurboWarp config comment: {err}",
+                ansi_codes::YELLOW,
+                ansi_codes::RESET
+            ),
+        }
+    }
+    None
+}
+
+fn get_stage(project_json: &serde_json::Value) -> Option<&serde_json::Value> {
+    project_json["targets"]
+        .as_array()?
+        .iter()
+        .find(|target| target["isStage"].as_bool() == Some(true))
+}
@@ -14,7 +14,7 @@ impl<'a> Project<'a> {
     }
 
     pub fn extract_zip_file(
-        file_path: String,
+        archive: Vec<u8>,
     ) -> Result<(tempfile::TempDir, std::path::PathBuf), String> {
         // Create a temporary directory
         let temp_dir = match tempfile::TempDir::new() {
@@ -29,9 +29,6 @@ impl<'a> Project<'a> {
         // Get the path of the temporary directory
         let temp_dir_path: std::path::PathBuf = temp_dir.path().to_path_buf();
 
-        let archive: Vec<u8> =
-            read_file_to_bytes(file_path.as_str()).expect("Could not read .sb3 file");
-
         let target_dir = std::path::PathBuf::from(&temp_dir_path);
 
         // The third parameter allows you to strip away toplevel directories.
@@ -42,14 +39,3 @@ impl<'a> Project<'a> {
         Ok((temp_dir, temp_dir_path))
     }
 }
-
-fn read_file_to_bytes(path: &str) -> std::io::Result<Vec<u8>> {
-    // Open the file
-    let mut file = std::fs::File::open(path)?;
-
-    // Read the contents into a Vec<u8>
-    let mut buffer = Vec::new();
-    file.read_to_end(&mut buffer)?;
-
-    Ok(buffer)
-}
@@ -1,83 +1,301 @@
-use sdl2::{pixels::Color, rect::Rect};
+use sdl2::rect::Rect;
 
 type SDLTextureCreator = sdl2::render::TextureCreator<sdl2::video::WindowContext>;
 
 use crate::{
     ansi_codes,
-    interpreter::{Instruction, Value},
-    project_state::Renderer,
-    sprite::{Costume, GraphicalProperties, Sprite},
-    thread::Thread,
+    aot::CompiledProject,
+    ask_prompt::get_bubble_svg,
+    audio::audio_effects::SoundEffects,
     bc_compiler::{
-        bc_comp_main::ThreadCompiler, bc_comp_variable_manager::VariableCompiler,
+        bc_comp_diagnostics::{CompileReport, Diagnostic, DiagnosticKind},
+        bc_comp_main::ThreadCompiler,
+        bc_comp_variable_manager::VariableCompiler,
+    },
+    interpreter::{Instruction, Memory, Value},
+    monitor::{load_monitors, Monitor},
+    pen_color::PenColor,
+    project::{
+        project_cache::{CachedSprite, ProjectCache},
+        project_config::ProjectConfig,
     },
+    project_state::Renderer,
+    sprite::{Costume, GraphicalProperties, Sprite, SpriteInfo},
+    text_renderer::render_svg,
+    thread::{Script, ScriptBytecode},
+    viewport::Viewport,
 };
 
+// How many turns every thread gets in a busy frame when the clock is counting frames,
+// standing in for the frame's time budget.
+const FRAME_PASS_LIMIT: usize = 1000;
+
 pub struct Project<'a> {
     memory: Box<[Value]>,
+    // How many times each list has been written, see Memory.
+    list_versions: Box<[u64]>,
     sprites: Vec<Sprite<'a>>,
+    monitors: Vec<Monitor<'a>>,
+    dragged_monitor: Option<usize>,
     pub path: std::path::PathBuf,
     pub json: serde_json::Value,
+    pub config: ProjectConfig,
+    // Problems found while compiling the scripts, for the caller to show.
+    pub compile_report: CompileReport,
+    font_database: usvg_text_layout::fontdb::Database,
+    texture_creator: &'a SDLTextureCreator,
 }
 
 impl<'a> Project<'a> {
     pub fn new(
-        project_path: String,
+        archive: Vec<u8>,
         texture_creator: &'a SDLTextureCreator,
+        use_cache: bool,
+        compiled: Option<&CompiledProject>,
     ) -> Result<Project<'a>, String> {
+        let hash = ProjectCache::hash(&archive);
         // Extract sb3 zip to a temporary directory.
-        let (_project_directory_object, project_path) = Project::extract_zip_file(project_path)?;
+        let (_project_directory_object, project_path) = Project::extract_zip_file(archive)?;
         let json = Project::load_json(&project_path);
 
+        let mut font_database = usvg_text_layout::fontdb::Database::new();
+        font_database.load_system_fonts();
+
         // Create a temporary project. We will load the code into this and return it.
         let mut project = Project {
             memory: Box::new([]),
+            list_versions: Box::new([]),
             sprites: vec![],
+            monitors: vec![],
+            dragged_monitor: None,
             path: project_path,
+            config: ProjectConfig::from_json(&json),
             json,
+            compile_report: CompileReport::default(),
+            font_database,
+            texture_creator,
         };
 
-        let mut variables = VariableCompiler::new();
+        // Compiled games come with their project already compiled, so
+        // their native code lines up with the scripts it was made from.
+        let cache = match compiled {
+            Some(compiled) => ProjectCache::decode(compiled.cache)?,
+            None => Project::load_cache(&project, &hash, use_cache)?,
+        };
+        let ProjectCache {
+            variables,
+            sprites,
+            report,
+        } = cache;
+        project.compile_report = report;
+
+        let Some(sprite_jsons) = project.json["targets"].as_array() else {
+            return Err("JSON error: No \"targets\" list of sprites".to_owned());
+        };
+        for (index, (cached, sprite_json)) in sprites.into_iter().zip(sprite_jsons).enumerate() {
+            let mut sprite = Project::sprite_create(&cached);
+            sprite.id = project.sprites.len();
+
+            sprite.load_costumes(&cached.costumes, texture_creator)?;
+            sprite.load_sounds(sprite_json, &project)?;
+            let functions = compiled
+                .and_then(|compiled| compiled.sprites.get(index).copied())
+                .unwrap_or_default();
+            sprite.flag_scripts = cached
+                .scripts
+                .into_iter()
+                .enumerate()
+                .map(|(index, bytecode)| {
+                    let mut script = Script::new(bytecode, &cached.name);
+                    script.compiled = functions.get(index).copied();
+                    script
+                })
+                .collect();
 
-        let mut font_database = usvg_text_layout::fontdb::Database::new();
-        font_database.load_system_fonts();
+            project.sprites.push(sprite);
+        }
 
-        let sprites = project.json["targets"]
-            .as_array()
-            .expect("Malformed JSON - No \"targets\" list of sprites");
+        project.monitors = load_monitors(&project.json, &variables, &mut project.compile_report);
 
-        for sprite_json in sprites.iter() {
+        // Allocate enough memory for the variables.
+        project.memory = variables.finish_processing();
+        project.list_versions = vec![0; project.memory.len()].into_boxed_slice();
+
+        Ok(project)
+    }
+
+    // Takes the project from the cache if it is there, otherwise compiles it and saves it there.
+    fn load_cache(
+        project: &Project<'_>,
+        hash: &str,
+        use_cache: bool,
+    ) -> Result<ProjectCache, String> {
+        if let Some(cache) = use_cache.then(|| ProjectCache::load(hash)).flatten() {
             println!(
-                "{}[info]{} started compiling sprite {}",
+                "{}[info]{} Loaded compiled project from the cache",
                 ansi_codes::GREEN,
-                ansi_codes::RESET,
-                sprite_json["name"]
+                ansi_codes::RESET
             );
+            return Ok(cache);
+        }
+        let cache = ProjectCache::build(&project.json, &project.path, &project.font_database)?;
+        if use_cache {
+            match cache.save(hash) {
+                Ok(path) => println!(
+                    "{}[info]{} Saved compiled project to {}",
+                    ansi_codes::GREEN,
+                    ansi_codes::RESET,
+                    path.display()
+                ),
+                Err(err) => eprintln!("{}[cache]{} {err}", ansi_codes::YELLOW, ansi_codes::RESET),
+            }
+        }
+        Ok(cache)
+    }
 
-            let mut sprite = Project::sprite_create_from_json(sprite_json);
-
-            sprite.load_costumes(sprite_json, &project, &font_database, texture_creator)?;
+    // Compiles every sprite's scripts, in the same order as the sprites. This
+    // doesn't need a window, so it is also used to compile projects ahead of time.
+    // Problems with blocks go in the report instead of stopping the compiler.
+    // With dump set, every script is printed before and after it is optimized.
+    pub fn compile_scripts(
+        json: &serde_json::Value,
+        dump: bool,
+    ) -> Result<(VariableCompiler, Vec<Vec<ScriptBytecode>>, CompileReport), String> {
+        let mut variables = VariableCompiler::new();
+        let mut report = CompileReport::default();
 
-            variables.load_from_json(&sprite_json);
+        let Some(sprites) = json["targets"].as_array() else {
+            return Err("JSON error: No \"targets\" list of sprites".to_owned());
+        };
 
-            Project::compile_hat_blocks(&mut variables, &mut sprite, sprite_json);
+        // Load every sprite's variables first, sensing blocks can refer
+        // to the variables of sprites that haven't been compiled yet.
+        for sprite_json in sprites.iter() {
+            variables.load_from_json(sprite_json);
+        }
 
-            project.sprites.push(sprite);
+        let mut scripts = Vec::with_capacity(sprites.len());
+        for sprite_json in sprites.iter() {
+            if dump {
+                println!(
+                    "{}[info]{} started compiling sprite {}",
+                    ansi_codes::GREEN,
+                    ansi_codes::RESET,
+                    sprite_json["name"]
+                );
+            }
+            scripts.push(Project::compile_hat_blocks(
+                &mut variables,
+                sprite_json,
+                sprites,
+                &mut report,
+                dump,
+            )?);
         }
+        Ok((variables, scripts, report))
+    }
 
-        // Allocate enough memory for the variables.
-        project.memory = variables.finish_processing();
+    // Stops everything, then starts every "when green flag clicked" script from the top.
+    pub fn green_flag(&mut self, renderer: &mut Renderer) {
+        self.stop(renderer);
+        renderer.scratch_timer = renderer.clock.now();
+        for sprite in self.sprites.iter_mut() {
+            sprite.start_flag_scripts();
+        }
+    }
 
-        Ok(project)
+    pub fn stop(&mut self, renderer: &mut Renderer) {
+        for sprite in self.sprites.iter_mut() {
+            sprite.stop();
+        }
+        renderer.audio.stop_all();
+        renderer.ask_prompt.clear();
     }
 
+    // Works like the Scratch sequencer: threads take turns until they are all waiting,
+    // something needs to be redrawn, or most of the frame is used up. In turbo mode
+    // only the time limit ends the frame early.
     pub fn run(
         &mut self,
         canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
         pen_canvas: &mut Renderer,
+        frame_time: std::time::Duration,
     ) {
-        for sprite in &mut self.sprites {
-            sprite.run(&mut self.memory, canvas, pen_canvas);
+        let frame_start = pen_canvas.clock.now();
+        let budget = frame_time.mul_f64(0.75);
+        let mut passes = 0;
+        pen_canvas.redraw_requested = false;
+        pen_canvas.sprite_info = self.sprites.iter().map(SpriteInfo::new).collect();
+        loop {
+            let mut any_active = false;
+            for (index, sprite) in self.sprites.iter_mut().enumerate() {
+                let mut memory = Memory {
+                    variables: &mut self.memory,
+                    registers: &mut [],
+                    numbers: &mut [],
+                    booleans: &mut [],
+                    list_versions: &mut self.list_versions,
+                };
+                any_active |= sprite.run(&mut memory, canvas, pen_canvas, &mut self.monitors);
+                pen_canvas.sprite_info[index] = SpriteInfo::new(sprite);
+            }
+            passes += 1;
+            if !any_active
+                || (pen_canvas.redraw_requested && !pen_canvas.turbo_mode)
+                || pen_canvas
+                    .clock
+                    .is_over(frame_start, budget, passes, FRAME_PASS_LIMIT)
+            {
+                break;
+            }
+        }
+    }
+
+    pub fn handle_event(&mut self, event: &sdl2::event::Event, renderer: &mut Renderer) {
+        use sdl2::event::Event;
+        let ((x, y), is_press) = match *event {
+            Event::MouseButtonDown { x, y, .. } => ((x, y), true),
+            Event::MouseMotion { x, y, .. } => ((x, y), false),
+            Event::MouseButtonUp { .. } => {
+                self.dragged_monitor = None;
+                return;
+            }
+            Event::MouseWheel { y, .. } => {
+                let (x, y_position) = renderer
+                    .viewport
+                    .stage_to_stage_pixel(renderer.mouse_position);
+                if let Some(monitor) = self
+                    .monitors
+                    .iter_mut()
+                    .rev()
+                    .find(|monitor| monitor.contains(x, y_position))
+                {
+                    monitor.scroll_by(-y, &self.memory);
+                }
+                return;
+            }
+            _ => return,
+        };
+        renderer.mouse_position = renderer.viewport.window_to_stage((x as f64, y as f64));
+        // Monitors are positioned from the top left of the stage.
+        let (x, y) = renderer
+            .viewport
+            .window_to_stage_pixel((x as f64, y as f64));
+
+        if is_press {
+            // Topmost monitor first.
+            for (index, monitor) in self.monitors.iter().enumerate().rev() {
+                if let Some(value) = monitor.get_slider_value_at(x, y, false) {
+                    self.memory[monitor.pointer] = Value::Number(value);
+                    self.dragged_monitor = Some(index);
+                    return;
+                }
+            }
+        } else if let Some(index) = self.dragged_monitor {
+            let monitor = &self.monitors[index];
+            if let Some(value) = monitor.get_slider_value_at(x, y, true) {
+                self.memory[monitor.pointer] = Value::Number(value);
+            }
         }
     }
 
@@ -86,9 +304,9 @@ impl<'a> Project<'a> {
     }
 
     pub fn draw(
-        &self,
+        &mut self,
         canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
-        renderer: &mut Renderer,
+        renderer: &mut Renderer<'a>,
     ) {
         // TODO: Draw sprites based on their layer order.
         // Currently it just draws sprites in the order of how they were loaded.
@@ -96,46 +314,78 @@ impl<'a> Project<'a> {
             if sprite.graphics.shown {
                 // let properties = &sprite.graphics;
                 let current_costume = &sprite.costumes[sprite.graphics.costume_number];
-                let rect = get_sprite_rect(
-                    &sprite.graphics,
-                    current_costume,
-                    canvas.output_size().unwrap(),
-                );
+                let rect = get_sprite_rect(&sprite.graphics, current_costume, &renderer.viewport);
                 canvas.copy(&current_costume.data, None, rect).unwrap();
             }
 
             if sprite.name == "Stage" {
                 canvas
-                    .copy(&renderer.main_canvas, None, Rect::new(0, 0, 800, 600))
+                    .copy(&renderer.main_canvas, None, renderer.viewport.rect())
                     .unwrap();
             }
         }
+
+        for sprite in self.sprites.iter_mut() {
+            draw_bubble(
+                canvas,
+                sprite,
+                &renderer.viewport,
+                &self.font_database,
+                self.texture_creator,
+            );
+        }
+
+        for monitor in self.monitors.iter_mut() {
+            monitor.draw(
+                canvas,
+                &renderer.viewport,
+                &self.memory,
+                &self.list_versions,
+                &self.font_database,
+                self.texture_creator,
+            );
+        }
+
+        renderer.ask_prompt.draw(
+            canvas,
+            &renderer.viewport,
+            &self.font_database,
+            self.texture_creator,
+        );
     }
 
-    pub fn sprite_create_from_json(sprite: &serde_json::Value) -> Sprite<'a> {
-        Sprite::new(
-            sprite["name"].as_str().unwrap().to_string(),
-            if sprite["isStage"].as_bool().unwrap() {
+    pub fn sprite_create(sprite: &CachedSprite) -> Sprite<'a> {
+        let mut new_sprite = Sprite::new(
+            sprite.name.clone(),
+            if sprite.is_stage {
                 Default::default()
             } else {
                 GraphicalProperties {
-                    x: sprite["x"].as_f64().unwrap(),
-                    y: sprite["y"].as_f64().unwrap(),
-                    shown: sprite["visible"].as_bool().unwrap(),
-                    direction: sprite["direction"].as_f64().unwrap() as f32,
-                    size: sprite["size"].as_f64().unwrap() as f32,
+                    x: sprite.x,
+                    y: sprite.y,
+                    shown: sprite.shown,
+                    direction: sprite.direction,
+                    size: sprite.size,
                     costume_number: 0,
                     pen_down: false,
-                    pen_radius: 1,
-                    pen_color: Color::RGB(0, 0, 255),
+                    pen_size: 1.0,
+                    pen_color: PenColor::default(),
+                    bubble: None,
+                    sound_effects: SoundEffects::default(),
+                    instrument: 0,
                 }
             },
-        )
+        );
+        new_sprite.graphics.costume_number = sprite.costume_number;
+        new_sprite.graphics.sound_effects.volume = sprite.volume;
+        new_sprite
     }
 
-    fn sprite_find_hat_blocks(sprite: &serde_json::Value) -> Vec<(&String, &serde_json::Value)> {
+    fn sprite_find_hat_blocks(
+        blocks: &serde_json::Map<String, serde_json::Value>,
+    ) -> Vec<(&String, &serde_json::Value)> {
         let mut hat_blocks: Vec<(&String, &serde_json::Value)> = vec![];
-        for (block_id, block_data) in sprite["blocks"].as_object().unwrap() {
+        for (block_id, block_data) in blocks {
             if block_data.is_array() {
                 continue;
             }
@@ -149,55 +399,119 @@ impl<'a> Project<'a> {
 
     fn compile_hat_blocks(
         variables: &mut VariableCompiler,
-        temp_sprite: &mut Sprite<'_>,
         sprite_json: &serde_json::Value,
-    ) {
-        let hat_blocks = Project::sprite_find_hat_blocks(sprite_json);
+        targets: &[serde_json::Value],
+        report: &mut CompileReport,
+        dump: bool,
+    ) -> Result<Vec<ScriptBytecode>, String> {
+        let sprite_name = sprite_json["name"].as_str().unwrap_or_default();
+        let Some(blocks) = sprite_json["blocks"].as_object() else {
+            report.add(
+                DiagnosticKind::BadJson,
+                sprite_name,
+                "Sprite has no \"blocks\" object".to_owned(),
+            );
+            return Ok(vec![]);
+        };
+        let hat_blocks = Project::sprite_find_hat_blocks(blocks);
+        let mut flag_scripts = Vec::new();
 
-        for (thread_number, (_, block_json)) in hat_blocks.iter().enumerate() {
-            let opcode = block_json["opcode"].as_str().unwrap();
+        for (thread_number, (block_id, block_json)) in hat_blocks.iter().enumerate() {
+            let opcode = block_json["opcode"].as_str().unwrap_or_default();
             match opcode {
-                "event_whenflagclicked" => c_events_whenflagclicked(
+                "event_whenflagclicked" => flag_scripts.push(c_events_whenflagclicked(
                     variables,
-                    temp_sprite,
                     block_json,
                     thread_number,
                     sprite_json,
-                ),
-                _ => {
-                    eprintln!(
-                        "{}[unimplemented hat block]{} {opcode}",
-                        ansi_codes::RED,
-                        ansi_codes::RESET
-                    )
-                }
+                    targets,
+                    report,
+                    dump,
+                )?),
+                // Compiled into the scripts that call them.
+                "procedures_definition" => {}
+                _ => report.diagnostics.push(Diagnostic {
+                    kind: DiagnosticKind::UnsupportedBlock,
+                    sprite: sprite_name.to_owned(),
+                    block_id: Some(block_id.to_string()),
+                    opcode: Some(opcode.to_owned()),
+                    message: "Scripts starting with this block aren't supported".to_owned(),
+                }),
             }
         }
+        Ok(flag_scripts)
     }
 }
 
+fn draw_bubble<'a>(
+    canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
+    sprite: &mut Sprite<'a>,
+    viewport: &Viewport,
+    font_database: &usvg_text_layout::fontdb::Database,
+    texture_creator: &'a SDLTextureCreator,
+) {
+    let Some(text) = &sprite.graphics.bubble else {
+        sprite.bubble_texture = None;
+        return;
+    };
+    if !sprite.graphics.shown {
+        return;
+    }
+    let scale = viewport.scale as f32;
+
+    let is_outdated = match &sprite.bubble_texture {
+        Some((rendered_text, rendered_scale, _)) => {
+            rendered_text != text || *rendered_scale != scale
+        }
+        None => true,
+    };
+    if is_outdated {
+        match render_svg(&get_bubble_svg(text), scale, font_database, texture_creator) {
+            Ok(texture) => sprite.bubble_texture = Some((text.clone(), scale, texture)),
+            Err(err) => {
+                eprintln!("{}[bubble]{} {err}", ansi_codes::RED, ansi_codes::RESET);
+                return;
+            }
+        }
+    }
+
+    let Some((_, _, texture)) = &sprite.bubble_texture else {
+        return;
+    };
+    let query = texture.query();
+    let sprite_rect = get_sprite_rect(
+        &sprite.graphics,
+        &sprite.costumes[sprite.graphics.costume_number],
+        viewport,
+    );
+    // The tail of the bubble points at the top right of the sprite,
+    // but the bubble itself has to stay on the stage.
+    let stage_rect = viewport.rect();
+    let x = sprite_rect
+        .right()
+        .min(stage_rect.right() - query.width as i32)
+        .max(stage_rect.left());
+    let y = (sprite_rect.top() - query.height as i32).max(stage_rect.top());
+    canvas
+        .copy(texture, None, Rect::new(x, y, query.width, query.height))
+        .unwrap();
+}
+
 pub fn get_sprite_rect(
     properties: &GraphicalProperties,
     current_costume: &Costume<'_>,
-    (canvas_width, canvas_height): (u32, u32),
+    viewport: &Viewport,
 ) -> Rect {
-    let size = properties.size / 100.0;
+    let size = properties.size as f64 / 100.0;
     let query = current_costume.data.query();
 
-    let size_difference_f64 = canvas_width as f64 / 480.0;
-    let size_difference_f32 = canvas_width as f32 / 480.0;
-
-    let width = size * query.width as f32 * size_difference_f32;
-    let height = size * query.height as f32 * size_difference_f32;
-
-    let mut sprite_x = properties.x - current_costume.centre_x;
-    let mut sprite_y = properties.y + current_costume.centre_y;
+    let width = size * query.width as f64 * viewport.scale;
+    let height = size * query.height as f64 * viewport.scale;
 
-    sprite_x *= size_difference_f64;
-    sprite_y *= size_difference_f64;
-
-    sprite_x += canvas_width as f64 / 2.0;
-    sprite_y = (canvas_height as f64 / 2.0) - sprite_y;
+    let (sprite_x, sprite_y) = viewport.stage_to_window((
+        properties.x - current_costume.centre_x,
+        properties.y + current_costume.centre_y,
+    ));
 
     sdl2::rect::Rect::new(
         sprite_x as i32,
@@ -207,49 +521,41 @@ pub fn get_sprite_rect(
     )
 }
 
-pub fn get_scaled_point(
-    (x, y): (f64, f64),
-    (canvas_width, canvas_height): (u32, u32),
-) -> (i32, i32) {
-    let size_difference_f64 = canvas_width as f64 / 480.0;
-
-    let mut sprite_x = x;
-    let mut sprite_y = y;
-
-    sprite_x *= size_difference_f64;
-    sprite_y *= size_difference_f64;
-
-    sprite_x += canvas_width as f64 / 2.0;
-    sprite_y = (canvas_height as f64 / 2.0) - sprite_y;
-
-    (sprite_x as i32, sprite_y as i32)
-}
-
 fn c_events_whenflagclicked(
     variables: &mut VariableCompiler,
-    temp_sprite: &mut Sprite,
     event_block_json: &serde_json::Value,
     thread_number: usize,
     sprite: &serde_json::Value,
-) {
+    targets: &[serde_json::Value],
+    report: &mut CompileReport,
+    dump: bool,
+) -> Result<ScriptBytecode, String> {
     let mut instructions: Vec<Instruction> = vec![];
 
-    let mut compiler = ThreadCompiler::new(variables, &mut instructions, thread_number, sprite);
+    let mut compiler =
+        ThreadCompiler::new(variables, &mut instructions, thread_number, sprite, targets);
 
-    let mut block = (*event_block_json).clone();
-    while block["next"] != serde_json::Value::Null {
-        let block_id = block["next"].as_str().unwrap();
-        block = compiler.get_block(block_id).unwrap();
-        compiler.compile_block(&block);
+    if let Some(first_block_id) = event_block_json["next"].as_str() {
+        compiler.compile_stack(event_block_json, first_block_id);
     }
+    report.diagnostics.append(&mut compiler.diagnostics);
 
     compiler.finish();
-    compiler.optimize();
-    compiler.dump();
-
-    temp_sprite
-        .threads
-        .push(Thread::new(instructions.into_boxed_slice()));
+    if dump {
+        compiler.dump_instructions("unoptimized instruction dump");
+    }
+    let result = compiler.optimize();
+    if dump {
+        compiler.dump();
+    }
+    result.map_err(|err| format!("Could not compile sprite {}: {err}", sprite["name"]))?;
+    // Optimizing can split registers up.
+    let register_count = compiler.register_count();
+
+    Ok(ScriptBytecode {
+        instructions,
+        register_count,
+    })
 }
 
 fn _ls(path: &std::path::Path) {
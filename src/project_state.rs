@@ -1,44 +1,98 @@
-use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::pixels::PixelFormatEnum;
+
+use crate::{
+    ask_prompt::AskPrompt,
+    audio::{audio_mixer::AudioMixer, audio_samples::MusicSamples},
+    clock::Clock,
+    sprite::SpriteInfo,
+    viewport::Viewport,
+};
 
 pub struct Renderer<'a> {
     pub main_canvas: sdl2::render::Texture<'a>,
     pub pen_line_canvas: sdl2::render::Texture<'a>,
-    pub scratch_timer: std::time::Instant,
+    pub clock: Clock,
+    // When the timer was last reset, by the clock.
+    pub scratch_timer: std::time::Duration,
+    pub ask_prompt: AskPrompt<'a>,
+    pub audio: AudioMixer,
+    // Beats per minute for the music extension, shared by every sprite.
+    pub tempo: f64,
+    pub music_samples: MusicSamples,
+    pub username: String,
+    // In Scratch coordinates, the centre of the stage is (0, 0).
+    pub mouse_position: (f64, f64),
+    // What sprites can see of each other with sensing blocks, in the same order as the project.
+    pub sprite_info: Vec<SpriteInfo>,
+    // Where the stage is in the window, updated whenever the window is resized.
+    pub viewport: Viewport,
+    // Set by anything that changes what is on screen. Outside of turbo mode
+    // the scheduler stops running threads for the frame once this is set.
+    pub redraw_requested: bool,
+    pub turbo_mode: bool,
 }
 
 impl<'a> Renderer<'a> {
     pub fn new(
         texture_creator: &'a sdl2::render::TextureCreator<sdl2::video::WindowContext>,
         canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
+        pen_resolution: u32,
+        (stage_width, stage_height): (u32, u32),
+        audio: AudioMixer,
     ) -> Renderer<'a> {
-        let mut this = Renderer {
-            main_canvas: Renderer::create_writable_canvas(texture_creator, canvas),
-            pen_line_canvas: Renderer::create_writable_canvas(texture_creator, canvas),
-            scratch_timer: std::time::Instant::now(),
-        };
-        this.update_pen_line_properties(canvas);
-        this
+        // The pen layer covers the stage, not the window, so it
+        // doesn't need to change when the window is resized.
+        let main_canvas = Renderer::create_writable_canvas(
+            texture_creator,
+            canvas,
+            stage_width * pen_resolution,
+            stage_height * pen_resolution,
+        );
+        let query = main_canvas.query();
+        Renderer {
+            main_canvas,
+            pen_line_canvas: Renderer::create_line_buffer(
+                texture_creator,
+                query.width,
+                query.height,
+            ),
+            clock: Clock::real(),
+            scratch_timer: std::time::Duration::ZERO,
+            ask_prompt: AskPrompt::new(),
+            audio,
+            tempo: 60.0,
+            music_samples: MusicSamples::new(None),
+            username: String::new(),
+            mouse_position: (0.0, 0.0),
+            sprite_info: vec![],
+            viewport: Viewport::new(canvas.output_size().unwrap(), (stage_width, stage_height)),
+            redraw_requested: false,
+            turbo_mode: false,
+        }
     }
 
-    pub fn update_pen_line_properties(
-        &mut self,
-        canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
-    ) {
-        canvas
-            .with_texture_canvas(&mut self.pen_line_canvas, |texture_canvas| {
-                texture_canvas.set_draw_color(Color::RGB(0, 0, 255));
-                texture_canvas.clear();
-            })
+    // Lines are rasterized on the CPU into this texture, then blended onto the pen layer.
+    fn create_line_buffer(
+        texture_creator: &'a sdl2::render::TextureCreator<sdl2::video::WindowContext>,
+        width: u32,
+        height: u32,
+    ) -> sdl2::render::Texture<'a> {
+        let mut line_buffer = texture_creator
+            .create_texture_streaming(PixelFormatEnum::RGBA32, width, height)
             .unwrap();
+        line_buffer.set_blend_mode(sdl2::render::BlendMode::Blend);
+        line_buffer
     }
 
     fn create_writable_canvas(
         texture_creator: &'a sdl2::render::TextureCreator<sdl2::video::WindowContext>,
         canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
+        width: u32,
+        height: u32,
     ) -> sdl2::render::Texture<'a> {
         // Create canvas for pen.
         let mut pen_canvas = texture_creator
-            .create_texture_target(PixelFormatEnum::RGBA8888, 800, 600)
+            .create_texture_target(PixelFormatEnum::RGBA8888, width, height)
             .unwrap();
         pen_canvas.set_blend_mode(sdl2::render::BlendMode::Blend);
         // Clear the pen canvas, otherwise it will be black.
@@ -1,7 +1,12 @@
-use sdl2::pixels::Color;
-
 use crate::{
-    interpreter::Value, project::project_main::Project, project_state::Renderer, thread::Thread,
+    ansi_codes,
+    audio::{audio_decoder::Sound, audio_effects::SoundEffects},
+    interpreter::Memory,
+    monitor::Monitor,
+    pen_color::PenColor,
+    project::{project_cache::CachedCostume, project_main::Project},
+    project_state::Renderer,
+    thread::{Script, Thread},
 };
 
 pub struct GraphicalProperties {
@@ -12,8 +17,12 @@ pub struct GraphicalProperties {
     pub direction: f32,
     pub costume_number: usize,
     pub pen_down: bool,
-    pub pen_radius: i32,
-    pub pen_color: Color,
+    pub pen_size: f64,
+    pub pen_color: PenColor,
+    pub bubble: Option<String>,
+    pub sound_effects: SoundEffects,
+    // Music extension instrument, starting at 0 for piano.
+    pub instrument: usize,
 }
 
 impl Default for GraphicalProperties {
@@ -26,8 +35,41 @@ impl Default for GraphicalProperties {
             direction: 90.0,
             costume_number: 0,
             pen_down: false,
-            pen_radius: 1,
-            pen_color: Color::RGB(0, 0, 255),
+            pen_size: 1.0,
+            pen_color: PenColor::default(),
+            bubble: None,
+            sound_effects: SoundEffects::default(),
+            instrument: 0,
+        }
+    }
+}
+
+// A copy of the parts of a sprite other sprites can look at with sensing blocks.
+pub struct SpriteInfo {
+    pub name: String,
+    pub x: f64,
+    pub y: f64,
+    pub direction: f32,
+    pub size: f32,
+    pub costume_number: usize,
+    pub costume_name: String,
+    pub volume: f64,
+}
+
+impl SpriteInfo {
+    pub fn new(sprite: &Sprite) -> SpriteInfo {
+        SpriteInfo {
+            name: sprite.name.clone(),
+            x: sprite.graphics.x,
+            y: sprite.graphics.y,
+            direction: sprite.graphics.direction,
+            size: sprite.graphics.size,
+            costume_number: sprite.graphics.costume_number,
+            costume_name: match sprite.costumes.get(sprite.graphics.costume_number) {
+                Some(costume) => costume.name.clone(),
+                None => String::new(),
+            },
+            volume: sprite.graphics.sound_effects.volume,
         }
     }
 }
@@ -39,77 +81,121 @@ pub struct Costume<'a> {
     pub name: String,
 }
 
+// The costumes and sounds of a sprite, as its threads see them.
+pub struct SpriteAssets<'b, 'a> {
+    // Which sprite this is, so the mixer knows whose sounds are whose.
+    pub sprite_id: usize,
+    pub costumes: &'b [Costume<'a>],
+    pub sounds: &'b [Sound],
+}
+
 pub struct Sprite<'a> {
+    // Position in the project's list of sprites.
+    pub id: usize,
     pub threads: Vec<Thread>,
+    // Compiled "when green flag clicked" scripts, started again every time the flag is clicked.
+    pub flag_scripts: Vec<Script>,
     pub name: String,
     pub graphics: GraphicalProperties,
     pub costumes: Vec<Costume<'a>>,
+    pub sounds: Vec<Sound>,
+    // The bubble text and scale the texture was last rendered with.
+    pub bubble_texture: Option<(String, f32, sdl2::render::Texture<'a>)>,
 }
 
 impl<'a> Sprite<'a> {
     pub fn new(name: String, graphical_properties: GraphicalProperties) -> Sprite<'a> {
         Sprite {
+            id: 0,
             threads: vec![],
+            flag_scripts: vec![],
             name,
             graphics: graphical_properties,
             costumes: vec![],
+            sounds: vec![],
+            bubble_texture: None,
         }
     }
 
     pub fn load_costumes(
         &mut self,
-        sprite: &serde_json::Value,
-        project: &Project<'a>,
-        db: &usvg_text_layout::fontdb::Database,
+        costumes: &[CachedCostume],
         texture_creator: &'a sdl2::render::TextureCreator<sdl2::video::WindowContext>,
     ) -> Result<(), String> {
-        let costumes = match sprite["costumes"].as_array() {
-            Some(costumes) => costumes,
-            None => return Err("JSON error: Cannot find costumes field in sprite.".to_owned()),
-        };
-
-        for costume_json in costumes {
-            if costume_json["dataFormat"].as_str().unwrap() == "svg" {
-                crate::costume_loader::convert_svg_to_png(costume_json, project, db)?;
-            }
-
-            let texture =
-                match crate::costume_loader::load_png(texture_creator, project, costume_json) {
-                    Ok(texture) => texture,
-                    Err(err) => {
-                        return Err(format!("JSON error: Failed to load costume: {:?}", err))
-                    }
-                };
+        for costume in costumes {
+            let texture = match crate::costume_loader::load_png(texture_creator, &costume.png) {
+                Ok(texture) => texture,
+                Err(err) => return Err(format!("Failed to load costume {}: {err}", costume.name)),
+            };
 
             self.costumes.push(Costume {
-                centre_x: costume_json["rotationCenterX"].as_f64().unwrap(),
-                centre_y: costume_json["rotationCenterY"].as_f64().unwrap(),
+                centre_x: costume.centre_x,
+                centre_y: costume.centre_y,
                 data: texture,
-                name: costume_json["name"].as_str().unwrap().to_string(),
+                name: costume.name.clone(),
             });
         }
+        Ok(())
+    }
 
-        let costume_number = sprite["currentCostume"].as_i64().unwrap();
-        self.graphics.costume_number = costume_number as usize;
+    pub fn load_sounds(
+        &mut self,
+        sprite: &serde_json::Value,
+        project: &Project<'a>,
+    ) -> Result<(), String> {
+        let sounds = match sprite["sounds"].as_array() {
+            Some(sounds) => sounds,
+            None => return Err("JSON error: Cannot find sounds field in sprite.".to_owned()),
+        };
 
+        for sound_json in sounds {
+            let sound = match Sound::load(sound_json, project) {
+                Ok(sound) => sound,
+                Err(err) => {
+                    // A broken sound shouldn't stop the whole project from running.
+                    eprintln!("{}[sound]{} {err}", ansi_codes::YELLOW, ansi_codes::RESET);
+                    Sound::silent(sound_json["name"].as_str().unwrap_or_default().to_owned())
+                }
+            };
+            self.sounds.push(sound);
+        }
         Ok(())
     }
 
+    pub fn start_flag_scripts(&mut self) {
+        self.threads = self.flag_scripts.iter().map(Thread::new).collect();
+    }
+
+    // What the stop button does to a sprite. Variables, position and looks are kept.
+    pub fn stop(&mut self) {
+        self.threads.clear();
+        self.graphics.bubble = None;
+        self.graphics.sound_effects.clear();
+    }
+
+    // Gives every thread one turn. Returns whether any of them did something.
     pub fn run(
         &mut self,
-        memory: &mut [Value],
+        memory: &mut Memory,
         canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
         pen_canvas: &mut Renderer,
-    ) {
+        monitors: &mut [Monitor],
+    ) -> bool {
+        let mut any_active = false;
         let mut i = 0;
         while i < self.threads.len() {
             let thread = &mut self.threads[i];
-            thread.run(
+            any_active |= thread.run(
                 memory,
                 &mut self.graphics,
-                &self.costumes,
+                &SpriteAssets {
+                    sprite_id: self.id,
+                    costumes: &self.costumes,
+                    sounds: &self.sounds,
+                },
                 canvas,
                 pen_canvas,
+                monitors,
             );
 
             if thread.killed {
@@ -118,5 +204,6 @@ impl<'a> Sprite<'a> {
                 i += 1;
             }
         }
+        any_active
     }
 }
@@ -0,0 +1,90 @@
+use sdl2::pixels::PixelFormatEnum;
+use usvg_text_layout::TreeTextToPath;
+
+type SDLTextureCreator = sdl2::render::TextureCreator<sdl2::video::WindowContext>;
+
+// SDL2_ttf isn't available, so anything with text in it (monitors, prompts)
+// is written as an SVG and rasterized with resvg, just like costumes.
+pub fn render_svg<'a>(
+    svg_data: &str,
+    scale: f32,
+    font_database: &usvg_text_layout::fontdb::Database,
+    texture_creator: &'a SDLTextureCreator,
+) -> Result<sdl2::render::Texture<'a>, String> {
+    let mut tree = match usvg::Tree::from_str(svg_data, &usvg::Options::default()) {
+        Ok(tree) => tree,
+        Err(err) => return Err(format!("[error] Text render: Failed to parse svg: {err:?}")),
+    };
+    tree.convert_text(font_database);
+
+    let width = ((tree.size.width() as f32 * scale).ceil() as u32).max(1);
+    let height = ((tree.size.height() as f32 * scale).ceil() as u32).max(1);
+    let mut pixmap = match tiny_skia::Pixmap::new(width, height) {
+        Some(pixmap) => pixmap,
+        None => return Err("[error] Text render: Failed to create pixmap".to_owned()),
+    };
+
+    resvg::render(
+        &tree,
+        usvg::FitTo::Zoom(scale),
+        tiny_skia::Transform::default(),
+        pixmap.as_mut(),
+    )
+    .ok_or("[error] Text render: Failed to render svg")?;
+
+    // SDL expects straight alpha, tiny-skia gives us premultiplied alpha.
+    let mut pixels: Vec<u8> = Vec::with_capacity((width * height * 4) as usize);
+    for pixel in pixmap.pixels() {
+        let color = pixel.demultiply();
+        pixels.extend_from_slice(&[color.red(), color.green(), color.blue(), color.alpha()]);
+    }
+
+    let mut texture = texture_creator
+        .create_texture_static(PixelFormatEnum::RGBA32, width, height)
+        .map_err(|err| format!("[error] Text render: Failed to create texture: {err}"))?;
+    texture
+        .update(None, &pixels, width as usize * 4)
+        .map_err(|err| format!("[error] Text render: Failed to upload texture: {err}"))?;
+    texture.set_blend_mode(sdl2::render::BlendMode::Blend);
+    Ok(texture)
+}
+
+pub fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        match character {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(character),
+        }
+    }
+    escaped
+}
+
+// Rough width of a string in pixels, used to size boxes before rendering.
+pub fn estimate_text_width(text: &str, font_size: f64) -> f64 {
+    text.chars().count() as f64 * font_size * 0.6
+}
+
+// Greedy word wrap using the same width estimate.
+pub fn wrap_text(text: &str, max_width: f64, font_size: f64) -> Vec<String> {
+    let mut lines: Vec<String> = vec![];
+    let mut line = String::new();
+    for word in text.split(' ') {
+        let candidate = if line.is_empty() {
+            word.to_owned()
+        } else {
+            format!("{line} {word}")
+        };
+        if estimate_text_width(&candidate, font_size) <= max_width || line.is_empty() {
+            line = candidate;
+        } else {
+            lines.push(std::mem::replace(&mut line, word.to_owned()));
+        }
+    }
+    lines.push(line);
+    lines
+}
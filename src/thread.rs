@@ -1,255 +1,487 @@
+use chrono::{Datelike, Timelike};
 use sdl2::pixels::Color;
+use serde::{Deserialize, Serialize};
 
 use crate::{
     ansi_codes,
-    interpreter::{Instruction, Value},
+    aot::CompiledFunction,
+    audio::{
+        audio_decoder::Sound,
+        audio_synth::{render_drum, render_note, DRUM_COUNT, INSTRUMENT_COUNT, SYNTH_SAMPLE_RATE},
+    },
+    interpreter::{Instruction, Memory, Registers, Value},
+    monitor::Monitor,
     pen_line,
     project::project_main::get_sprite_rect,
     project_state::Renderer,
-    sprite::{Costume, GraphicalProperties},
+    sprite::{GraphicalProperties, SpriteAssets},
+    viewport::Viewport,
 };
 
+// What the compiler makes from a script, before it is set up to run.
+#[derive(Serialize, Deserialize)]
+pub struct ScriptBytecode {
+    pub instructions: Vec<Instruction>,
+    pub register_count: usize,
+}
+
+// A compiled script, which any number of threads can be started from.
+#[derive(Clone)]
+pub struct Script {
+    pub instructions: std::rc::Rc<[Instruction]>,
+    pub register_count: usize,
+    // Native code for the script from a compiled game.
+    pub compiled: Option<CompiledFunction>,
+    #[cfg(feature = "jit")]
+    pub native: Option<std::rc::Rc<crate::jit::NativeScript>>,
+}
+
 pub struct Thread {
-    instructions: Box<[Instruction]>,
+    instructions: std::rc::Rc<[Instruction]>,
+    // Temporaries used while working out blocks, separate from the project's variables.
+    registers: Registers,
+    compiled: Option<CompiledFunction>,
+    #[cfg(feature = "jit")]
+    native: Option<std::rc::Rc<crate::jit::NativeScript>>,
     pub killed: bool,
     counter: usize,
+    // Ticket of the question this thread is waiting on an answer for.
+    waiting_for_answer: Option<usize>,
+    // The sound this thread is waiting to finish playing.
+    waiting_for_sound: Option<usize>,
+    // Set by blocks that wait for a number of beats.
+    // By the renderer's clock.
+    waiting_until: Option<std::time::Duration>,
+    // When the current step started and how many warp mode loops it has done,
+    // so a stuck warp mode custom block still gives up eventually.
+    step_started: std::time::Duration,
+    warp_loops: usize,
+}
+
+// How long a warp mode thread can run before it has to yield, the same as Scratch.
+const WARP_TIME_LIMIT: std::time::Duration = std::time::Duration::from_millis(500);
+// The same, for when the clock is counting frames.
+const WARP_LOOP_LIMIT: usize = 100_000;
+
+impl Script {
+    pub fn new(bytecode: ScriptBytecode, sprite_name: &str) -> Script {
+        let instructions: std::rc::Rc<[Instruction]> = bytecode.instructions.into();
+        #[cfg(feature = "jit")]
+        let native = match crate::jit::compile(&instructions) {
+            Ok(native) => native.map(std::rc::Rc::new),
+            Err(err) => {
+                eprintln!(
+                    "{}[jit]{} Could not compile a script in sprite {sprite_name}, interpreting it instead: {err}",
+                    ansi_codes::YELLOW,
+                    ansi_codes::RESET,
+                );
+                None
+            }
+        };
+        #[cfg(not(feature = "jit"))]
+        let _ = sprite_name;
+        Script {
+            instructions,
+            register_count: bytecode.register_count,
+            compiled: None,
+            #[cfg(feature = "jit")]
+            native,
+        }
+    }
 }
 
 impl<'a> Thread {
-    pub fn new(instructions: Box<[Instruction]>) -> Thread {
+    pub fn new(script: &Script) -> Thread {
         Thread {
-            instructions,
+            instructions: script.instructions.clone(),
+            registers: Registers::new(script.register_count),
+            compiled: script.compiled,
+            #[cfg(feature = "jit")]
+            native: script.native.clone(),
             killed: false,
             counter: 0,
+            waiting_for_answer: None,
+            waiting_for_sound: None,
+            waiting_until: None,
+            step_started: std::time::Duration::ZERO,
+            warp_loops: 0,
         }
     }
 
+    // Runs the thread until it yields. Returns false if it
+    // was waiting on something or has finished, true otherwise.
     pub fn run(
         &mut self,
-        memory: &mut [Value],
+        memory: &mut Memory,
         properties: &mut GraphicalProperties,
-        costumes: &Vec<Costume<'a>>,
+        assets: &SpriteAssets<'_, 'a>,
         canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
         pen_canvas: &mut Renderer,
-    ) {
+        monitors: &mut [Monitor],
+    ) -> bool {
+        if self.killed {
+            return false;
+        }
+        if let Some(time) = self.waiting_until {
+            if pen_canvas.clock.now() < time {
+                return false;
+            }
+            self.waiting_until = None;
+        }
+        if let Some(id) = self.waiting_for_sound {
+            if pen_canvas.audio.is_playing(id) {
+                return false;
+            }
+            self.waiting_for_sound = None;
+        }
+        if let Some(ticket) = self.waiting_for_answer {
+            if pen_canvas.ask_prompt.is_waiting(ticket) {
+                properties.bubble = pen_canvas.ask_prompt.get_bubble(ticket);
+                return false;
+            }
+            properties.bubble = None;
+            self.waiting_for_answer = None;
+        }
+        self.step_started = pen_canvas.clock.now();
+        self.warp_loops = 0;
+        // Taken out while running, so instructions can borrow the thread at the same time.
+        let mut registers = std::mem::take(&mut self.registers);
+        let mut memory = Memory::new(
+            &mut *memory.variables,
+            &mut registers,
+            &mut *memory.list_versions,
+        );
+        if let Some(compiled) = self.compiled {
+            let instructions = self.instructions.clone();
+            compiled(
+                &mut memory,
+                &instructions,
+                self.counter,
+                &mut |memory, counter| {
+                    self.counter = counter;
+                    if self.instructions[counter]
+                        .requests_redraw(properties.shown || properties.pen_down)
+                    {
+                        pen_canvas.redraw_requested = true;
+                    }
+                    let should_break =
+                        self.run_bytecode(memory, properties, assets, canvas, pen_canvas, monitors);
+                    (!should_break).then_some(self.counter)
+                },
+            );
+            self.registers = registers;
+            return !self.killed;
+        }
         loop {
-            let should_break: bool =
-                self.run_bytecode(memory, properties, costumes, canvas, pen_canvas);
-            self.counter += 1;
+            #[cfg(feature = "jit")]
+            if let Some(native) = &self.native {
+                if native.can_run(self.counter) {
+                    self.counter = native.run(&mut memory, self.counter);
+                }
+            }
+            if self.instructions[self.counter]
+                .requests_redraw(properties.shown || properties.pen_down)
+            {
+                pen_canvas.redraw_requested = true;
+            }
+            let should_break: bool = self.run_bytecode(
+                &mut memory,
+                properties,
+                assets,
+                canvas,
+                pen_canvas,
+                monitors,
+            );
             if should_break {
                 break;
             }
         }
+        self.registers = registers;
+        !self.killed
     }
 
-    fn get_place(&self, string: &String) -> Option<usize> {
-        for (index, item) in self.instructions.iter().enumerate() {
-            if let Instruction::FlowDefinePlace(n) = item {
-                if n == string {
-                    return Some(index);
-                }
-            }
-        }
-        None
+    fn wait_for_beats(&mut self, beats: f64, project_state: &Renderer) {
+        let seconds = beats_to_seconds(beats, project_state.tempo);
+        self.waiting_until =
+            Some(project_state.clock.now() + std::time::Duration::from_secs_f64(seconds));
     }
 
     fn run_bytecode(
         &mut self,
-        memory: &mut [Value],
+        memory: &mut Memory,
         properties: &mut GraphicalProperties,
-        costumes: &Vec<Costume<'a>>,
+        assets: &SpriteAssets<'_, 'a>,
         canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
         project_state: &mut Renderer,
+        monitors: &mut [Monitor],
     ) -> bool {
-        match &self.instructions[self.counter] {
+        // Moved on before running the instruction, so jumps can just overwrite it.
+        let counter = self.counter;
+        self.counter += 1;
+        let instruction = &self.instructions[counter];
+        match instruction {
             Instruction::MemoryDump => {
                 println!("{}[memory dump]{} {{", ansi_codes::GREEN, ansi_codes::RESET);
                 dump_memory(memory);
                 println!("}}")
             }
-            Instruction::MemoryStore(location, value) => {
-                let location = location.get_pointer();
-                let value_read: Value;
-                if let Value::Pointer(n) = value {
-                    value_read = memory[*n].clone();
-                } else {
-                    value_read = value.clone();
-                }
-                memory[location] = value_read;
-            }
-            Instruction::ThreadPause => {
-                return true;
+            Instruction::MemoryStore(..)
+            | Instruction::FlowIfJump(..)
+            | Instruction::FlowIfNotJump(..) => {
+                run_memory_instruction(instruction, memory, &mut self.counter);
+            }
+            Instruction::ThreadPause => return true,
+            Instruction::ThreadWarpPause => {
+                self.warp_loops += 1;
+                return project_state.clock.is_over(
+                    self.step_started,
+                    WARP_TIME_LIMIT,
+                    self.warp_loops,
+                    WARP_LOOP_LIMIT,
+                );
             }
             Instruction::ThreadKill => {
                 self.killed = true;
                 return true;
             }
-            Instruction::OperatorModulo(location, a, b) => {
-                let location = location.get_pointer();
-                let a = a.get_number(memory);
-                let b = b.get_number(memory);
-                memory[location] = Value::Number(a.rem_euclid(b));
-            }
-            Instruction::OperatorAdd(location, a, b) => {
-                let location = location.get_pointer();
-                let a = a.get_number(memory);
-                let b = b.get_number(memory);
-                memory[location] = Value::Number(a + b);
-            }
-            Instruction::OperatorSubtract(location, a, b) => {
-                let location = location.get_pointer();
-                let a = a.get_number(memory);
-                let b = b.get_number(memory);
-                memory[location] = Value::Number(a - b);
-            }
-            Instruction::OperatorMultiply(location, a, b) => {
-                let location = location.get_pointer();
-                let a = a.get_number(memory);
-                let b = b.get_number(memory);
-                memory[location] = Value::Number(a * b);
-            }
-            Instruction::OperatorDivide(location, a, b) => {
-                let location = location.get_pointer();
-                let a = a.get_number(memory);
-                let b = b.get_number(memory);
-                memory[location] = Value::Number(a / b);
-            }
-            Instruction::OperatorPower(location, a, b) => {
-                let location = location.get_pointer();
-                let a = a.get_number(memory);
-                let b = b.get_number(memory);
-                memory[location] = Value::Number(a.powf(b));
-            }
-            Instruction::OperatorERaised(l, n) => {
-                memory[l.get_pointer()] = Value::Number(n.get_number(memory).exp())
-            }
-            Instruction::OperatorSin(l, n) => {
-                memory[l.get_pointer()] =
-                    Value::Number((n.get_number(memory) * std::f64::consts::PI / 180.0).sin())
-            }
-            Instruction::OperatorCos(l, n) => {
-                memory[l.get_pointer()] =
-                    Value::Number((n.get_number(memory) * std::f64::consts::PI / 180.0).cos())
-            }
-            Instruction::OperatorTan(l, n) => {
-                memory[l.get_pointer()] =
-                    Value::Number((n.get_number(memory) * std::f64::consts::PI / 180.0).tan())
-            }
-            Instruction::OperatorAbs(l, n) => {
-                memory[l.get_pointer()] = Value::Number(n.get_number(memory).abs())
-            }
-            Instruction::OperatorASin(l, n) => {
-                memory[l.get_pointer()] =
-                    Value::Number(n.get_number(memory).asin() * (180.0 / std::f64::consts::PI))
-            }
-            Instruction::OperatorACos(l, n) => {
-                memory[l.get_pointer()] =
-                    Value::Number(n.get_number(memory).acos() * (180.0 / std::f64::consts::PI))
-            }
-            Instruction::OperatorATan(l, n) => {
-                memory[l.get_pointer()] =
-                    Value::Number(n.get_number(memory).atan() * (180.0 / std::f64::consts::PI))
-            }
-            Instruction::OperatorSqrt(l, n) => {
-                let num = n.get_number(memory);
-                memory[l.get_pointer()] = if num < 0.0 {
-                    Value::Number(0.0)
-                } else {
-                    Value::Number(num.sqrt())
+            Instruction::OperatorModulo(..)
+            | Instruction::OperatorAdd(..)
+            | Instruction::OperatorSubtract(..)
+            | Instruction::OperatorMultiply(..)
+            | Instruction::OperatorDivide(..)
+            | Instruction::OperatorPower(..)
+            | Instruction::OperatorLesser(..)
+            | Instruction::OperatorGreater(..)
+            | Instruction::OperatorEquals(..)
+            | Instruction::OperatorNumbers(..)
+            | Instruction::OperatorERaised(..)
+            | Instruction::OperatorSin(..)
+            | Instruction::OperatorCos(..)
+            | Instruction::OperatorTan(..)
+            | Instruction::OperatorAbs(..)
+            | Instruction::OperatorASin(..)
+            | Instruction::OperatorACos(..)
+            | Instruction::OperatorATan(..)
+            | Instruction::OperatorSqrt(..)
+            | Instruction::OperatorLn(..)
+            | Instruction::OperatorLog(..)
+            | Instruction::OperatorFloor(..)
+            | Instruction::OperatorCeiling(..) => {
+                run_memory_instruction(instruction, memory, &mut self.counter);
+            }
+            Instruction::SensingTimer(location) => {
+                memory.set(
+                    location,
+                    Value::Number(
+                        (project_state.clock.now() - project_state.scratch_timer).as_secs_f64(),
+                    ),
+                );
+            }
+            Instruction::SensingAskAndWait(question, can_show_bubble) => {
+                let shows_bubble = *can_show_bubble && properties.shown;
+                let ticket = project_state
+                    .ask_prompt
+                    .ask(question.get_string(memory), shows_bubble);
+                properties.bubble = project_state.ask_prompt.get_bubble(ticket);
+                self.waiting_for_answer = Some(ticket);
+                return true;
+            }
+            Instruction::SensingAnswer(location) => {
+                memory.set(
+                    location,
+                    Value::String(project_state.ask_prompt.answer.clone()),
+                );
+            }
+            Instruction::SoundPlay(sound) => {
+                if let Some(index) = get_sound_index(sound, memory, assets.sounds) {
+                    project_state.audio.play(
+                        &assets.sounds[index],
+                        assets.sprite_id,
+                        &properties.sound_effects,
+                    );
                 }
             }
-            Instruction::OperatorLn(l, n) => {
-                memory[l.get_pointer()] = Value::Number(n.get_number(memory).ln())
+            Instruction::SoundPlayUntilDone(sound) => {
+                if let Some(index) = get_sound_index(sound, memory, assets.sounds) {
+                    self.waiting_for_sound = Some(project_state.audio.play(
+                        &assets.sounds[index],
+                        assets.sprite_id,
+                        &properties.sound_effects,
+                    ));
+                    return true;
+                }
             }
-            Instruction::OperatorLog(l, n) => {
-                memory[l.get_pointer()] = Value::Number(n.get_number(memory).log10())
+            Instruction::SoundStopAll => project_state.audio.stop_all(),
+            Instruction::SoundSetEffect(effect, value) => {
+                properties
+                    .sound_effects
+                    .set_effect(effect, value.get_number(memory), false);
+                project_state
+                    .audio
+                    .set_effects(assets.sprite_id, &properties.sound_effects);
+            }
+            Instruction::SoundChangeEffect(effect, value) => {
+                properties
+                    .sound_effects
+                    .set_effect(effect, value.get_number(memory), true);
+                project_state
+                    .audio
+                    .set_effects(assets.sprite_id, &properties.sound_effects);
+            }
+            Instruction::SoundClearEffects => {
+                properties.sound_effects.clear();
+                project_state
+                    .audio
+                    .set_effects(assets.sprite_id, &properties.sound_effects);
+            }
+            Instruction::SoundSetVolume(volume) => {
+                properties
+                    .sound_effects
+                    .set_volume(volume.get_number(memory));
+                project_state
+                    .audio
+                    .set_effects(assets.sprite_id, &properties.sound_effects);
+            }
+            Instruction::SoundChangeVolume(volume) => {
+                let new_volume = properties.sound_effects.volume + volume.get_number(memory);
+                properties.sound_effects.set_volume(new_volume);
+                project_state
+                    .audio
+                    .set_effects(assets.sprite_id, &properties.sound_effects);
+            }
+            Instruction::SoundGetVolume(location) => {
+                memory.set(location, Value::Number(properties.sound_effects.volume));
+            }
+            Instruction::MusicPlayDrum(drum, beats) => {
+                let drum = get_menu_number(drum, memory, DRUM_COUNT);
+                let (samples, sample_rate) = project_state
+                    .music_samples
+                    .drum(drum)
+                    .unwrap_or_else(|| (render_drum(drum).into(), SYNTH_SAMPLE_RATE));
+                project_state.audio.play_samples(
+                    samples,
+                    sample_rate,
+                    assets.sprite_id,
+                    &properties.sound_effects,
+                );
+                self.wait_for_beats(beats.get_number(memory), project_state);
+                return true;
             }
-            Instruction::OperatorFloor(l, n) => {
-                memory[l.get_pointer()] = Value::Number(n.get_number(memory).floor())
+            Instruction::MusicPlayNote(note, beats) => {
+                let note = note.get_number(memory).clamp(0.0, 130.0);
+                let seconds = beats_to_seconds(beats.get_number(memory), project_state.tempo);
+                if seconds > 0.0 {
+                    let (samples, sample_rate) = project_state
+                        .music_samples
+                        .note(properties.instrument, note, seconds)
+                        .unwrap_or_else(|| {
+                            (
+                                render_note(properties.instrument, note, seconds).into(),
+                                SYNTH_SAMPLE_RATE,
+                            )
+                        });
+                    project_state.audio.play_samples(
+                        samples,
+                        sample_rate,
+                        assets.sprite_id,
+                        &properties.sound_effects,
+                    );
+                }
+                self.wait_for_beats(beats.get_number(memory), project_state);
+                return true;
             }
-            Instruction::OperatorCeiling(l, n) => {
-                memory[l.get_pointer()] = Value::Number(n.get_number(memory).ceil())
+            Instruction::MusicRest(beats) => {
+                self.wait_for_beats(beats.get_number(memory), project_state);
+                return true;
             }
-            Instruction::OperatorLesser(location, a, b) => {
-                let location = location.get_pointer();
-                let a = a.get_number(memory);
-                let b = b.get_number(memory);
-                memory[location] = Value::Boolean(a < b);
+            Instruction::MusicSetTempo(tempo) => {
+                project_state.tempo = tempo.get_number(memory).clamp(20.0, 500.0)
             }
-            Instruction::OperatorGreater(location, a, b) => {
-                let location = location.get_pointer();
-                let a = a.get_number(memory);
-                let b = b.get_number(memory);
-                memory[location] = Value::Boolean(a > b);
+            Instruction::MusicChangeTempo(tempo) => {
+                project_state.tempo =
+                    (project_state.tempo + tempo.get_number(memory)).clamp(20.0, 500.0)
             }
-            Instruction::OperatorEquals(location, a, b) => {
-                let a = if let Value::Pointer(n) = a {
-                    &memory[*n]
-                } else {
-                    a
-                };
-                let b = if let Value::Pointer(n) = b {
-                    &memory[*n]
+            Instruction::MusicGetTempo(location) => {
+                memory.set(location, Value::Number(project_state.tempo));
+            }
+            Instruction::MusicSetInstrument(instrument) => {
+                properties.instrument = get_menu_number(instrument, memory, INSTRUMENT_COUNT)
+            }
+            Instruction::SensingResetTimer => {
+                project_state.scratch_timer = project_state.clock.now();
+            }
+            Instruction::SensingDistanceTo(location, target) => {
+                let target = target.get_string(memory);
+                let position = if target == "_mouse_" {
+                    Some(project_state.mouse_position)
                 } else {
-                    b
+                    project_state
+                        .sprite_info
+                        .iter()
+                        .find(|sprite| sprite.name == target)
+                        .map(|sprite| (sprite.x, sprite.y))
                 };
-                let location = location.get_pointer();
-                memory[location] = Value::Boolean(match &(a, b) {
-                    (Value::Number(n1), Value::Number(n2)) => *n1 == *n2,
-                    (Value::Boolean(n1), Value::Boolean(n2)) => *n1 == *n2,
-                    (Value::String(n1), Value::String(n2)) => *n1 == *n2,
-                    (Value::Number(_), Value::Boolean(n2)) => a.get_bool(memory) == *n2,
-                    (Value::Boolean(n1), Value::Number(_)) => *n1 == b.get_bool(memory),
-                    (Value::String(_), Value::Number(n2)) => a.get_number(memory) == *n2,
-                    (Value::Number(n1), Value::String(_)) => *n1 == b.get_number(memory),
-                    (Value::Boolean(n1), Value::String(_)) => *n1 == b.get_bool(memory),
-                    (Value::String(_), Value::Boolean(n2)) => a.get_bool(memory) == *n2,
-                    _ => panic!(
-                        "Unsupported types for equality comparison at {}",
-                        self.counter
-                    ),
-                })
+                memory.set(
+                    location,
+                    Value::Number(match position {
+                        Some((x, y)) => {
+                            ((properties.x - x).powi(2) + (properties.y - y).powi(2)).sqrt()
+                        }
+                        None => 10000.0,
+                    }),
+                );
             }
-            Instruction::SensingTimer(location) => {
-                memory[location.get_pointer()] =
-                    Value::Number(project_state.scratch_timer.elapsed().as_secs_f64())
+            Instruction::SensingOf(location, property, object) => {
+                let object = object.get_string(memory);
+                memory.set(
+                    location,
+                    get_property_of_sprite(project_state, property, &object),
+                );
             }
-            Instruction::FlowIfJump(condition, location) => {
-                if condition.get_bool(memory) {
-                    let location = location.get_number(memory) as usize;
-                    self.counter = location;
-                }
+            Instruction::SensingCurrent(location, menu) => {
+                let now = chrono::Local::now();
+                memory.set(
+                    location,
+                    Value::Number(match menu.as_str() {
+                        "YEAR" => now.year() as f64,
+                        "MONTH" => now.month() as f64,
+                        "DATE" => now.day() as f64,
+                        "DAYOFWEEK" => now.weekday().number_from_sunday() as f64,
+                        "HOUR" => now.hour() as f64,
+                        "MINUTE" => now.minute() as f64,
+                        "SECOND" => now.second() as f64,
+                        _ => 0.0,
+                    }),
+                );
             }
-            Instruction::FlowIfJumpToPlace(condition, place) => {
-                if condition.get_bool(memory) {
-                    let location = self
-                        .get_place(place)
-                        .expect("Could not find jump point in program");
-                    self.counter = location;
-                }
+            Instruction::SensingDaysSince2000(location) => {
+                // Like Scratch, this counts from midnight on 1 January 2000 in local time.
+                let start = chrono::NaiveDate::from_ymd_opt(2000, 1, 1)
+                    .unwrap()
+                    .and_hms_opt(0, 0, 0)
+                    .unwrap();
+                let elapsed = chrono::Local::now().naive_local() - start;
+                memory.set(
+                    location,
+                    Value::Number(elapsed.num_milliseconds() as f64 / 86_400_000.0),
+                );
             }
-            Instruction::FlowIfNotJump(condition, location) => {
-                if !condition.get_bool(memory) {
-                    let location = location.get_number(memory) as usize;
-                    self.counter = location;
-                }
+            Instruction::SensingUsername(location) => {
+                memory.set(location, Value::String(project_state.username.clone()));
             }
-            Instruction::FlowIfNotJumpToPlace(condition, place) => {
-                if !condition.get_bool(memory) {
-                    let location = self.get_place(place).unwrap_or_else(|| {
-                        panic!("Could not find jump point in program {}", place)
-                    });
-                    self.counter = location;
-                }
+            Instruction::FlowIfJumpToPlace(..)
+            | Instruction::FlowIfNotJumpToPlace(..)
+            | Instruction::FlowDefinePlace(_) => {
+                unreachable!("Labels are linked when the thread is compiled")
             }
-            Instruction::FlowDefinePlace(_) => {}
             Instruction::MotionChangeX(n) => {
                 let mut new_x = properties.x + n.get_number(memory);
                 let mut new_y = properties.y;
                 fencing_clamp(
                     &mut new_x,
                     &mut new_y,
-                    costumes[properties.costume_number].data.query(),
+                    assets.costumes[properties.costume_number].data.query(),
+                    &project_state.viewport,
                 );
                 pen_line::draw(canvas, project_state, properties, new_x, new_y);
                 properties.x = new_x;
@@ -260,7 +492,8 @@ impl<'a> Thread {
                 fencing_clamp(
                     &mut new_x,
                     &mut new_y,
-                    costumes[properties.costume_number].data.query(),
+                    assets.costumes[properties.costume_number].data.query(),
+                    &project_state.viewport,
                 );
                 pen_line::draw(canvas, project_state, properties, new_x, new_y);
                 properties.y = new_y;
@@ -271,7 +504,8 @@ impl<'a> Thread {
                 fencing_clamp(
                     &mut new_x,
                     &mut new_y,
-                    costumes[properties.costume_number].data.query(),
+                    assets.costumes[properties.costume_number].data.query(),
+                    &project_state.viewport,
                 );
                 pen_line::draw(canvas, project_state, properties, new_x, new_y);
                 properties.x = new_x;
@@ -282,7 +516,8 @@ impl<'a> Thread {
                 fencing_clamp(
                     &mut new_x,
                     &mut new_y,
-                    costumes[properties.costume_number].data.query(),
+                    assets.costumes[properties.costume_number].data.query(),
+                    &project_state.viewport,
                 );
                 pen_line::draw(canvas, project_state, properties, new_x, new_y);
                 properties.y = new_y;
@@ -293,7 +528,8 @@ impl<'a> Thread {
                 fencing_clamp(
                     &mut new_x,
                     &mut new_y,
-                    costumes[properties.costume_number].data.query(),
+                    assets.costumes[properties.costume_number].data.query(),
+                    &project_state.viewport,
                 );
                 pen_line::draw(canvas, project_state, properties, new_x, new_y);
                 properties.x = new_x;
@@ -302,7 +538,8 @@ impl<'a> Thread {
             Instruction::LooksSetSize(size) => properties.size = size.get_number(memory) as f32,
             Instruction::LooksSetCostume(costume_val) => {
                 let costume_name = costume_val.get_string(memory);
-                match costumes
+                match assets
+                    .costumes
                     .iter()
                     .position(|costume| costume.name == costume_name)
                 {
@@ -315,7 +552,7 @@ impl<'a> Thread {
                     // Setting costume via number.
                     // Example: set costume to 1.
                     None => {
-                        let number_of_costumes = costumes.len() as i32;
+                        let number_of_costumes = assets.costumes.len() as i32;
                         let costume_number = costume_val.get_number(memory) as i32;
                         properties.costume_number =
                             ((costume_number - 1).rem_euclid(number_of_costumes)) as usize;
@@ -324,14 +561,16 @@ impl<'a> Thread {
                 }
             }
             Instruction::LooksGetCostumeNumber(location) => {
-                memory[location.get_pointer()] =
-                    Value::Number(properties.costume_number as f64 + 1.0);
+                memory.set(
+                    location,
+                    Value::Number(properties.costume_number as f64 + 1.0),
+                );
             }
             Instruction::MotionGetX(location) => {
-                memory[location.get_pointer()] = Value::Number(properties.x)
+                memory.set(location, Value::Number(properties.x));
             }
             Instruction::MotionGetY(location) => {
-                memory[location.get_pointer()] = Value::Number(properties.y)
+                memory.set(location, Value::Number(properties.y));
             }
             Instruction::PenClear => {
                 canvas
@@ -342,17 +581,18 @@ impl<'a> Thread {
                     .unwrap();
             }
             Instruction::PenStamp => {
-                let size = canvas.output_size().unwrap();
+                let query = project_state.main_canvas.query();
+                let viewport = project_state.viewport.resized((query.width, query.height));
                 canvas
                     .with_texture_canvas(&mut project_state.main_canvas, |texture_canvas| {
                         texture_canvas
                             .copy(
-                                &costumes[properties.costume_number].data,
+                                &assets.costumes[properties.costume_number].data,
                                 None,
                                 get_sprite_rect(
                                     properties,
-                                    &costumes[properties.costume_number],
-                                    size,
+                                    &assets.costumes[properties.costume_number],
+                                    &viewport,
                                 ),
                             )
                             .unwrap();
@@ -360,46 +600,200 @@ impl<'a> Thread {
                     .unwrap();
             }
             Instruction::PenUp => properties.pen_down = false,
-            Instruction::PenDown => properties.pen_down = true,
-            Instruction::PenSetRadius(value) => {
-                properties.pen_radius = value.get_number(memory) as i32 * 2
+            Instruction::PenDown => {
+                // Putting the pen down leaves a dot, even if the sprite never moves.
+                properties.pen_down = true;
+                pen_line::draw(
+                    canvas,
+                    project_state,
+                    properties,
+                    properties.x,
+                    properties.y,
+                );
             }
+            Instruction::PenSetRadius(value) => {
+                properties.pen_size = value.get_number(memory).clamp(1.0, 1200.0)
+            }
+            Instruction::PenChangeRadius(value) => {
+                properties.pen_size =
+                    (properties.pen_size + value.get_number(memory)).clamp(1.0, 1200.0)
+            }
+            Instruction::PenSetColor(color) => properties.pen_color.set_to_color(color, memory),
+            Instruction::PenSetColorParam(param, value) => properties.pen_color.set_param(
+                &param.get_string(memory),
+                value.get_number(memory),
+                false,
+            ),
+            Instruction::PenChangeColorParam(param, value) => properties.pen_color.set_param(
+                &param.get_string(memory),
+                value.get_number(memory),
+                true,
+            ),
+            Instruction::PenSetHue(hue) => {
+                properties.pen_color.set_legacy_hue(hue.get_number(memory))
+            }
+            Instruction::PenSetShade(shade) => properties
+                .pen_color
+                .set_legacy_shade(shade.get_number(memory)),
             Instruction::LooksHide => properties.shown = false,
             Instruction::LooksShow => properties.shown = true,
             Instruction::LooksNextCostume => {
-                let number_of_costumes = costumes.len() as i32;
+                let number_of_costumes = assets.costumes.len() as i32;
                 let costume_number = properties.costume_number as i32 + 1;
                 properties.costume_number =
                     (costume_number.rem_euclid(number_of_costumes)) as usize;
             }
+            Instruction::DataShowVariable(variable) => {
+                if let Some(pointer) = variable.get_pointer() {
+                    set_monitor_visible(monitors, pointer, true)
+                }
+            }
+            Instruction::DataHideVariable(variable) => {
+                if let Some(pointer) = variable.get_pointer() {
+                    set_monitor_visible(monitors, pointer, false)
+                }
+            }
         }
         false
     }
 }
 
-fn fencing_clamp(new_x: &mut f64, new_y: &mut f64, query: sdl2::render::TextureQuery) {
-    if *new_x > 240.0 {
+// Runs an instruction that only uses memory, which is all the assembler can run and all
+// the JIT compiles. Returns false for other instructions, which need a running project.
+pub fn run_memory_instruction(
+    instruction: &Instruction,
+    memory: &mut Memory,
+    counter: &mut usize,
+) -> bool {
+    match instruction {
+        Instruction::MemoryStore(location, value) => {
+            let value_read = memory.resolve(value).into_owned();
+            memory.set(location, value_read);
+        }
+        Instruction::FlowIfJump(condition, location) => {
+            if condition.get_bool(memory) {
+                *counter = *location;
+            }
+        }
+        Instruction::FlowIfNotJump(condition, location) => {
+            if !condition.get_bool(memory) {
+                *counter = *location;
+            }
+        }
+        // Results that go to a register bank skip set.
+        Instruction::OperatorNumbers(op, location, a, b) => {
+            match (
+                op.apply(a.get_number(memory), b.get_number(memory)),
+                location,
+            ) {
+                (Value::Number(n), Value::NumberRegister(register)) => {
+                    memory.numbers[*register] = n
+                }
+                (Value::Boolean(b), Value::BooleanRegister(register)) => {
+                    memory.booleans[*register] = b
+                }
+                (value, location) => memory.set(location, value),
+            }
+        }
+        _ if instruction.is_operator() => {
+            let (location, value) = instruction.evaluate_operator(memory).unwrap();
+            memory.set(location, value);
+        }
+        _ => return false,
+    }
+    true
+}
+
+fn beats_to_seconds(beats: f64, tempo: f64) -> f64 {
+    beats.clamp(0.0, 100.0) * 60.0 / tempo
+}
+
+// Instruments and drums are numbered from 1, and wrap around past the end of the menu.
+fn get_menu_number(value: &Value, memory: &Memory, count: usize) -> usize {
+    let number = value.get_number(memory).round() as i64 - 1;
+    number.rem_euclid(count as i64) as usize
+}
+
+// Sounds can be picked by name or by number, like costumes.
+fn get_sound_index(sound: &Value, memory: &Memory, sounds: &[Sound]) -> Option<usize> {
+    let name = sound.get_string(memory);
+    if let Some(index) = sounds.iter().position(|sound| sound.name == name) {
+        return Some(index);
+    }
+    if sounds.is_empty() || name.trim().is_empty() {
+        return None;
+    }
+    let number = name.trim().parse::<f64>().ok()?;
+    Some((number.round() as i64 - 1).rem_euclid(sounds.len() as i64) as usize)
+}
+
+fn fencing_clamp(
+    new_x: &mut f64,
+    new_y: &mut f64,
+    query: sdl2::render::TextureQuery,
+    viewport: &Viewport,
+) {
+    let (edge_x, edge_y) = (viewport.stage_width / 2.0, viewport.stage_height / 2.0);
+    if *new_x > edge_x {
         if query.width > 32 {
-            *new_x = 240.0 + (query.width / 2) as f64 - 15.0;
+            *new_x = edge_x + (query.width / 2) as f64 - 15.0;
         } else {
-            *new_x = 240.0
+            *new_x = edge_x
         }
     }
-    if *new_y > 180.0 {
+    if *new_y > edge_y {
         if query.height > 32 {
-            *new_y = 180.0 + (query.height / 2) as f64 - 15.0;
+            *new_y = edge_y + (query.height / 2) as f64 - 15.0;
         } else {
-            *new_y = 180.0
+            *new_y = edge_y
         }
     }
 }
 
-fn dump_memory(memory: &[Value]) {
-    for val in memory {
+fn get_property_of_sprite(project_state: &Renderer, property: &str, object: &str) -> Value {
+    let object = if object == "_stage_" { "Stage" } else { object };
+    let Some(sprite) = project_state
+        .sprite_info
+        .iter()
+        .find(|sprite| sprite.name == object)
+    else {
+        return Value::Number(0.0);
+    };
+    match property {
+        "x position" => Value::Number(sprite.x),
+        "y position" => Value::Number(sprite.y),
+        "direction" => Value::Number(sprite.direction as f64),
+        "costume #" | "backdrop #" => Value::Number(sprite.costume_number as f64 + 1.0),
+        "costume name" | "backdrop name" => Value::String(sprite.costume_name.clone()),
+        "size" => Value::Number(sprite.size as f64),
+        "volume" => Value::Number(sprite.volume),
+        _ => Value::Number(0.0),
+    }
+}
+
+fn set_monitor_visible(monitors: &mut [Monitor], pointer: usize, visible: bool) {
+    for monitor in monitors.iter_mut() {
+        if monitor.pointer == pointer {
+            monitor.visible = visible;
+        }
+    }
+}
+
+pub fn dump_memory(memory: &Memory) {
+    for val in memory.variables.iter().chain(memory.registers.iter()) {
         match val {
             Value::Pointer(n) => {
                 println!("    pointer: {}", n)
             }
+            Value::Register(n) => {
+                println!("    register: {}", n)
+            }
+            Value::NumberRegister(n) => {
+                println!("    number register: {}", n)
+            }
+            Value::BooleanRegister(n) => {
+                println!("    bool register: {}", n)
+            }
             Value::Number(n) => {
                 println!("    number: {}", n)
             }
@@ -409,6 +803,15 @@ fn dump_memory(memory: &[Value]) {
             Value::String(n) => {
                 println!("    string: {}", n)
             }
+            Value::List(_) => {
+                println!("    list: {}", val.print(None))
+            }
         }
     }
+    for n in memory.numbers.iter() {
+        println!("    number: {}", n)
+    }
+    for n in memory.booleans.iter() {
+        println!("    bool: {}", n)
+    }
 }
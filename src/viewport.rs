@@ -0,0 +1,68 @@
+use sdl2::rect::Rect;
+
+// Where the stage is drawn inside the window (or any other target, like the pen layer).
+// The stage always keeps its aspect ratio, any leftover space becomes black bars.
+#[derive(Clone, Copy)]
+pub struct Viewport {
+    pub x: f64,
+    pub y: f64,
+    // Target pixels per stage pixel.
+    pub scale: f64,
+    // 480x360 in Scratch, but TurboWarp projects can change it.
+    pub stage_width: f64,
+    pub stage_height: f64,
+}
+
+impl Viewport {
+    pub fn new((width, height): (u32, u32), (stage_width, stage_height): (u32, u32)) -> Viewport {
+        let (stage_width, stage_height) = (stage_width as f64, stage_height as f64);
+        let scale = (width as f64 / stage_width).min(height as f64 / stage_height);
+        Viewport {
+            x: ((width as f64 - stage_width * scale) / 2.0).floor(),
+            y: ((height as f64 - stage_height * scale) / 2.0).floor(),
+            scale,
+            stage_width,
+            stage_height,
+        }
+    }
+
+    // The same stage, fitted into a target of a different size.
+    pub fn resized(&self, target_size: (u32, u32)) -> Viewport {
+        Viewport::new(
+            target_size,
+            (self.stage_width as u32, self.stage_height as u32),
+        )
+    }
+
+    pub fn rect(&self) -> Rect {
+        Rect::new(
+            self.x as i32,
+            self.y as i32,
+            (self.stage_width * self.scale).round() as u32,
+            (self.stage_height * self.scale).round() as u32,
+        )
+    }
+
+    // Scratch coordinates, where (0, 0) is the centre of the stage and y goes up.
+    pub fn stage_to_window(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        self.stage_pixel_to_window(self.stage_to_stage_pixel((x, y)))
+    }
+
+    pub fn window_to_stage(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        let (x, y) = self.window_to_stage_pixel((x, y));
+        (x - self.stage_width / 2.0, self.stage_height / 2.0 - y)
+    }
+
+    // Stage pixels from the top left of the stage, which is how monitors are positioned.
+    pub fn stage_pixel_to_window(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        (self.x + x * self.scale, self.y + y * self.scale)
+    }
+
+    pub fn window_to_stage_pixel(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        ((x - self.x) / self.scale, (y - self.y) / self.scale)
+    }
+
+    pub fn stage_to_stage_pixel(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        (x + self.stage_width / 2.0, self.stage_height / 2.0 - y)
+    }
+}